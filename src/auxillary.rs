@@ -1,11 +1,23 @@
-use teloxide::{dispatching::{update_listeners::{self, StatefulListener}, stop_token::AsyncStopToken}, prelude::*, types::{Update, InlineKeyboardMarkup, InlineKeyboardButton} };
+use teloxide::{dispatching::{update_listeners::{self, StatefulListener}, stop_token::{AsyncStopToken, StopToken}}, prelude::*, types::{Update, InlineKeyboardMarkup, InlineKeyboardButton, PhotoSize, User}, ApiError, RequestError };
+use teloxide::payloads::SendMessageSetters;
 use teloxide_core::adaptors::AutoSend;
-use std::{convert::Infallible, net::SocketAddr, process::{Command as ConsoleCommand, Stdio}};
+use std::{convert::Infallible, io::Write, net::SocketAddr, path::{Path, PathBuf}, process::{Command as ConsoleCommand, Stdio}, sync::{Arc, atomic::Ordering}};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
-use warp::Filter;
+use warp::{hyper::body::Bytes, path::FullPath, Filter};
 use reqwest::{StatusCode, Url};
-use crate::updater::model::{ButtonCommand, OutputAction};
+use petgraph::graph::NodeIndex;
+use crate::config::check_writable;
+use crate::error::{BotError, StorageError};
+use crate::handlers::AppState;
+use crate::updater::model::{Author, ButtonCommand, Called, OutputAction, Recovery, StateError};
+use crate::session::{Retention, TreeId, UserTrees, TreeIndexError};
+use crate::storage::random_token;
+use crate::updater::chart::{Focus, Theme};
+use crate::updater::export::ExportFormat;
+use crate::updater::validation::Warning;
+use crate::updater::statement::{Proposal, StatementError};
+use crate::locale::{tr, Lang, Msg};
 
 #[derive(Debug)]
 pub enum OutputCommand {
@@ -14,31 +26,180 @@ pub enum OutputCommand {
 }
 
 async fn handle_rejection(error: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
+    if error.is_not_found() {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+    if error.find::<warp::reject::MethodNotAllowed>().is_some() {
+        return Ok(StatusCode::METHOD_NOT_ALLOWED);
+    }
     log::error!("Cannot process the request due to: {:?}", error);
     Ok(StatusCode::INTERNAL_SERVER_ERROR)
 }
 
-/// Webhook stateful listener
-/// Copied from https://github.com/teloxide/teloxide/blob/85ef14867fb9b23d1a221ebc911dca458bec3291/examples/ngrok_ping_pong_bot/src/main.rs#L23-L58
-pub async fn webhook(bot: AutoSend<Bot>, server_url: Url, socket_addr: SocketAddr) -> impl update_listeners::UpdateListener<Infallible> {
-    bot.set_webhook(server_url)
-        .await
-        .expect("Cannot setup a webhook");
+/// Checks whether the bot can serve users, listing what's broken otherwise
+fn readiness(state: &AppState) -> Vec<String> {
+    let mut problems = vec![];
+    if !state.webhook_registered.load(Ordering::SeqCst) {
+        problems.push("webhook is not registered".to_string());
+    }
+    if let Err(error) = renderer_version() {
+        problems.push(error.to_string());
+    }
+    if let Err(error) = check_writable(&state.storage_path) {
+        problems.push(format!("storage path {} is not writable: {}", state.storage_path.display(), error));
+    }
+    problems
+}
 
-    let (tx, rx) = mpsc::unbounded_channel();
+/// Probes for the orchestrator and metrics for Prometheus, served next to the webhook
+fn monitoring(state: Arc<AppState>) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let healthz = warp::path!("healthz").map(|| "ok");
+    let ready_state = state.clone();
+    let readyz = warp::path!("readyz").and_then(move || {
+        let state = ready_state.clone();
+        async move {
+            // running graphviz blocks, so it's kept off the server's threads
+            let problems = tokio::task::spawn_blocking(move || readiness(&state)).await
+                .unwrap_or_else(|error| vec![format!("readiness check failed: {}", error)]);
+            let reply = match problems[..] {
+                [] => warp::reply::with_status("ready".to_string(), StatusCode::OK),
+                _ => warp::reply::with_status(problems.join("\n"), StatusCode::SERVICE_UNAVAILABLE)
+            };
+            Ok::<_, Infallible>(reply)
+        }
+    });
+    let metrics = warp::path!("metrics").map(move || {
+        warp::reply::with_header(state.metrics.render(state.storage.tree_count()), "content-type", "text/plain; version=0.0.4")
+    });
+    warp::get().and(healthz.or(readyz).or(metrics))
+}
+
+const WEBHOOK_SECRET_LENGTH: usize = 32;
+const SECRET_TOKEN_HEADER: &str = "x-telegram-bot-api-secret-token";
 
-    let server = warp::post()
-        .and(warp::body::json())
-        .map(move |json: serde_json::Value| {
-            if let Ok(update) = Update::try_parse(&json) {
-                tx.send(Ok(update)).expect("Cannot send an incoming update from the webhook")
+/// Secrets only Telegram learns from `setWebhook`: the last segment of the webhook path and the value of its header.
+/// New ones are made on every start, so a leaked URL stops working after a restart.
+#[derive(Clone, Debug)]
+pub struct WebhookSecret {
+    path: String,
+    token: String,
+}
+
+impl WebhookSecret {
+    pub fn generate() -> Self { Self { path: random_token(WEBHOOK_SECRET_LENGTH), token: random_token(WEBHOOK_SECRET_LENGTH) } }
+
+    /// Server URL with the secret segment, e.g. `https://example.com/bot/<secret>`
+    fn url(&self, server_url: &Url) -> Url {
+        let mut url = server_url.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(&self.path);
+        }
+        url
+    }
+
+    /// Compares in constant time, so the token can't be guessed byte by byte from response times
+    fn accepts(&self, token: Option<&str>) -> bool {
+        match token {
+            Some(token) if token.len() == self.token.len() => token.bytes().zip(self.token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0,
+            _ => false
+        }
+    }
+}
+
+/// reqwest errors show the request URL, which has the bot token in it, so it's hidden before the error gets logged
+fn redact_token(bot: &Bot, error: impl std::fmt::Display) -> RequestError {
+    RequestError::Io(std::io::Error::other(error.to_string().replace(bot.token(), "<token>")))
+}
+
+/// teloxide doesn't know the `secret_token` of setWebhook yet, so the request is made by hand
+async fn set_webhook(bot: &Bot, url: Url, secret: &WebhookSecret) -> Result<(), BotError> {
+    let method = format!("{}/bot{}/setWebhook", bot.api_url().as_str().trim_end_matches('/'), bot.token());
+    let response = bot.client()
+        .post(&method)
+        .json(&serde_json::json!({ "url": url.as_str(), "secret_token": secret.token }))
+        .send().await
+        .map_err(|error| redact_token(bot, error))?;
+    let status_code = response.status();
+    let body: serde_json::Value = response.json().await.map_err(|error| redact_token(bot, error))?;
+    if body["ok"].as_bool() != Some(true) {
+        let description = body["description"].as_str().unwrap_or("no description").to_string();
+        return Err(RequestError::ApiError { kind: ApiError::Unknown(description), status_code }.into());
+    }
+    Ok(())
+}
+
+/// Where the webhook puts updates for the dispatcher, taken away on shutdown so no more updates are accepted
+type UpdateSender = Arc<std::sync::Mutex<Option<mpsc::UnboundedSender<Result<Update, Infallible>>>>>;
+
+/// Stops the webhook: new updates are refused at once, so the dispatcher finishes the ones it has,
+/// while the server completes the requests it's answering
+#[derive(Clone)]
+struct WebhookStop {
+    server: AsyncStopToken,
+    updates: UpdateSender,
+}
+
+impl StopToken for WebhookStop {
+    fn stop(self) {
+        self.updates.lock().expect("update sender lock is never poisoned").take();
+        self.server.stop();
+    }
+}
+
+/// Takes updates POSTed to the path of the webhook URL with the secret header, anything else is rejected
+fn updates(state: Arc<AppState>, secret: WebhookSecret, webhook_url: &Url, tx: UpdateSender) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let path = webhook_url.path().to_string();
+    warp::post()
+        .and(warp::path::full())
+        .and_then(move |full: FullPath| {
+            let found = full.as_str() == path;
+            async move { if found { Ok(()) } else { Err(warp::reject::not_found()) } }
+        })
+        .untuple_one()
+        .and(warp::header::optional::<String>(SECRET_TOKEN_HEADER))
+        .and(warp::addr::remote())
+        .and(warp::body::bytes())
+        .map(move |token: Option<String>, remote: Option<SocketAddr>, body: Bytes| {
+            if !secret.accepts(token.as_deref()) {
+                let rejected = state.metrics.webhook_rejected();
+                log::warn!("Rejected webhook request from {:?} with a wrong secret token, {} so far", remote, rejected);
+                return StatusCode::UNAUTHORIZED;
+            }
+            let tx = tx.lock().expect("update sender lock is never poisoned");
+            let tx = match tx.as_ref() {
+                Some(tx) => tx,
+                // Telegram keeps the update and sends it again, hopefully to the bot that replaces this one
+                None => return StatusCode::SERVICE_UNAVAILABLE
+            };
+            state.metrics.update_received();
+            let update = serde_json::from_slice(&body).ok().and_then(|json| Update::try_parse(&json).ok());
+            if let Some(update) = update {
+                if tx.send(Ok(update)).is_err() {
+                    log::warn!("Dropping an update, the dispatcher has stopped");
+                }
             }
 
             StatusCode::OK
         })
+}
+
+/// Webhook stateful listener
+/// Copied from https://github.com/teloxide/teloxide/blob/85ef14867fb9b23d1a221ebc911dca458bec3291/examples/ngrok_ping_pong_bot/src/main.rs#L23-L58
+pub async fn webhook(bot: AutoSend<Bot>, state: Arc<AppState>, server_url: Url, socket_addr: SocketAddr) -> Result<impl update_listeners::UpdateListener<Infallible>, BotError> {
+    let secret = WebhookSecret::generate();
+    let webhook_url = secret.url(&server_url);
+    set_webhook(bot.inner(), webhook_url.clone(), &secret).await?;
+    state.webhook_registered.store(true, Ordering::SeqCst);
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let tx = Arc::new(std::sync::Mutex::new(Some(tx)));
+
+    let server = updates(state.clone(), secret, &webhook_url, tx.clone())
+        .or(monitoring(state))
         .recover(handle_rejection);
 
-    let (stop_token, stop_flag) = AsyncStopToken::new_pair();
+    let (server_stop, stop_flag) = AsyncStopToken::new_pair();
+    let stop_token = WebhookStop { server: server_stop, updates: tx };
 
     let server = warp::serve(server);
     let (_addr, fut) = server.bind_with_graceful_shutdown(socket_addr, stop_flag);
@@ -48,30 +209,167 @@ pub async fn webhook(bot: AutoSend<Bot>, server_url: Url, socket_addr: SocketAdd
 
     fn streamf<S, T>(state: &mut (S, T)) -> &mut S { &mut state.0 }
     
-    StatefulListener::new((stream, stop_token), streamf, |state: &mut (_, AsyncStopToken)| state.1.clone())
+    Ok(StatefulListener::new((stream, stop_token), streamf, |state: &mut (_, WebhookStop)| state.1.clone()))
 }
 
-pub fn print_graph(dot_graph: String) -> Vec<u8> {
-    let mut piped_graph = ConsoleCommand::new("echo")
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .arg(dot_graph)
-        .spawn().unwrap();
+/// Charts with more people are sent as documents, Telegram would compress a photo into an unreadable thumbnail
+pub const DOCUMENT_NODE_THRESHOLD: usize = 30;
 
-    let _ = piped_graph.wait();
-    let du_output = match piped_graph.stdout.take() {
-        Some(it) => it,
-        None => panic!("Error printing graph")
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChartFormat {
+    #[default]
+    Png,
+    Svg,
+    Pdf,
+}
+
+impl ChartFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChartFormat::Png => "png",
+            ChartFormat::Svg => "svg",
+            ChartFormat::Pdf => "pdf",
+        }
+    }
+
+    /// SVG would only link the photos by their path on the server
+    pub fn embeds_photos(&self) -> bool {
+        *self != ChartFormat::Svg
+    }
+}
+
+/// Options of /finish, e.g. `/finish svg` or `/finish png document`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChartOptions {
+    pub format: ChartFormat,
+    pub as_document: bool,
+}
+
+impl ChartOptions {
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut options = ChartOptions::default();
+        for word in text.split_whitespace() {
+            match word.to_lowercase().as_str() {
+                "png" => options.format = ChartFormat::Png,
+                "svg" => options.format = ChartFormat::Svg,
+                "pdf" => options.format = ChartFormat::Pdf,
+                "document" | "file" => options.as_document = true,
+                _ => return None
+            }
+        }
+        Some(options)
+    }
+
+    /// Only PNG can be sent as a photo, and only while the tree is small enough to stay readable
+    pub fn sends_document(&self, node_count: usize) -> bool {
+        self.as_document || self.format != ChartFormat::Png || node_count > DOCUMENT_NODE_THRESHOLD
+    }
+}
+
+/// Longer exports are sent as files, Telegram messages are limited to 4096 characters
+pub const EXPORT_MESSAGE_LIMIT: usize = 4000;
+
+/// Files bigger than that can't be a family tree and aren't downloaded
+pub const IMPORT_SIZE_LIMIT: u32 = 1024 * 1024;
+/// Wrong rows listed after a failed import, the rest are only counted
+pub const IMPORT_ERRORS_SHOWN: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportRequest {
+    /// Format and whether to send a file
+    Text(ExportFormat, bool),
+    /// People and relationships tables for spreadsheets, always sent as files
+    Tables,
+    /// Page with photos, always sent as a file
+    Html,
+}
+
+/// Reads the arguments of /export: `mermaid|plantuml [document]`, `csv` or `html`
+pub fn parse_export(text: &str) -> Option<ExportRequest> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    match words[..] {
+        [format] if format.eq_ignore_ascii_case("csv") => Some(ExportRequest::Tables),
+        [format] if format.eq_ignore_ascii_case("html") => Some(ExportRequest::Html),
+        [format] => Some(ExportRequest::Text(ExportFormat::parse(format)?, false)),
+        [format, document] if ["document", "file"].contains(&document.to_lowercase().as_str()) => Some(ExportRequest::Text(ExportFormat::parse(format)?, true)),
+        _ => None
+    }
+}
+
+/// Photos are downloaded in the size closest to that width, charts scale them down further
+pub const THUMBNAIL_WIDTH: u32 = 320;
+/// Directory of the downloaded photos in the storage path
+const PHOTO_CACHE_DIR: &str = "photos";
+
+/// The largest size which is still a thumbnail, Telegram lists sizes from the smallest
+pub fn pick_thumbnail(sizes: &[PhotoSize]) -> Option<&PhotoSize> {
+    sizes.iter().rfind(|size| size.width <= THUMBNAIL_WIDTH).or_else(|| sizes.first())
+}
+
+/// Where the downloaded photo is kept. Telegram file ids are URL-safe, anything else could come
+/// from an imported table and point outside of the cache, so there's no path for it.
+pub fn photo_path(storage_path: &Path, file_id: &str) -> Option<PathBuf> {
+    let safe = !file_id.is_empty() && file_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if safe { Some(storage_path.join(PHOTO_CACHE_DIR).join(format!("{}.jpg", file_id))) } else { None }
+}
+
+/// Generations shown above and below the person by /chart unless given
+pub const DEFAULT_FOCUS_GENERATIONS: usize = 2;
+
+/// Reads the arguments of /chart: `<name> [up] [down] [siblings]`
+pub fn parse_focus(text: &str) -> Option<(String, Focus)> {
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+    let siblings = words.last().map(|word| word.eq_ignore_ascii_case("siblings")).unwrap_or(false);
+    if siblings {
+        words.pop();
+    }
+    let numbers = words.iter().rev().take(2).take_while(|word| word.parse::<usize>().is_ok()).count();
+    let generations: Vec<usize> = words.split_off(words.len() - numbers).iter().filter_map(|word| word.parse().ok()).collect();
+    if words.is_empty() {
+        return None;
+    }
+    let focus = Focus {
+        up: generations.first().copied().unwrap_or(DEFAULT_FOCUS_GENERATIONS),
+        down: generations.get(1).copied().unwrap_or(DEFAULT_FOCUS_GENERATIONS),
+        siblings,
     };
-    let png_img = ConsoleCommand::new("dot")
-        .stdin(du_output)
-        .arg("-Tpng")
-        .output().unwrap();
+    Some((words.join(" "), focus))
+}
 
-    png_img.stdout
+/// Version of graphviz, failing when it's not installed
+pub fn renderer_version() -> Result<String, BotError> {
+    let output = ConsoleCommand::new("dot").arg("-V").output()
+        .map_err(|error| BotError::Render(format!("cannot run dot: {}", error)))?;
+    if !output.status.success() {
+        return Err(BotError::Render(format!("dot exited with {}", output.status)));
+    }
+    // dot prints its version to stderr
+    Ok(String::from_utf8_lossy(&output.stderr).trim().to_string())
 }
 
-pub fn make_inline_keyboard(commands: &Vec<(ButtonCommand, String)>) -> InlineKeyboardMarkup {
+/// Renders the chart with graphviz, failing with its complaints when the graph can't be drawn
+pub fn print_graph(dot_graph: String, format: ChartFormat) -> Result<Vec<u8>, BotError> {
+    let render_error = |error: std::io::Error| BotError::Render(format!("cannot run dot: {}", error));
+    let mut dot = ConsoleCommand::new("dot")
+        .arg(format!("-T{}", format.extension()))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(render_error)?;
+
+    // dot reads the whole graph before drawing, so the pipe is closed before the output is read
+    if let Some(mut stdin) = dot.stdin.take() {
+        stdin.write_all(dot_graph.as_bytes()).map_err(render_error)?;
+    }
+    let output = dot.wait_with_output().map_err(render_error)?;
+    if !output.status.success() {
+        return Err(BotError::Render(format!("dot exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(output.stdout)
+}
+
+pub fn make_inline_keyboard(commands: &[(ButtonCommand, String)]) -> InlineKeyboardMarkup {
     let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
 
     for versions in commands.chunks(3) {
@@ -86,57 +384,398 @@ pub fn make_inline_keyboard(commands: &Vec<(ButtonCommand, String)>) -> InlineKe
     InlineKeyboardMarkup::new(keyboard)
 }
 
-pub fn map_next_action_output(action: &OutputAction) -> OutputCommand {
-    return match action {
-        OutputAction::AskFirstParent(description) => 
-            OutputCommand::PromptButtons(
-                vec![
-                    (ButtonCommand::No, "Don't know".to_string())
-                ],
-                format!("Write then name of the 1st parent of {}. If you don't know the name, press the button.", description)
-            ),
-        OutputAction::AskSecondParent(description) => 
-            OutputCommand::PromptButtons(
-                vec![
-                    (ButtonCommand::No, "Don't know".to_string())
-                ],
-                format!("Write then name of the 2nd parent of {}. If you don't know the name, press the button.", description)
-            ),
-        OutputAction::AskIfSiblings(description) => 
-            OutputCommand::PromptButtons(
-                vec![
-                    (ButtonCommand::No, "No siblings".to_string())
-                ],
-                format!("Maybe {} has some siblings? Write the name of the first one that you know or press the button.", description)
-            ),
-        OutputAction::AskIfMoreSiblings(description) => 
-            OutputCommand::PromptButtons(
-                vec![
-                    (ButtonCommand::No, "No more siblings".to_string())
-                ],
-                format!("Tell me the name of one more sibling of {} or press the button.", description)
-            ),
-        OutputAction::AskIfChildren(description) => 
-            OutputCommand::PromptButtons(
-                vec![
-                    (ButtonCommand::No, "No children".to_string())
-                ],
-                format!("Tell me if {} has any children. If so, tell me the name. If none or you don't know, press the button.", description)
-            ),
-        OutputAction::AskIfMoreChildren(description) => 
-            OutputCommand::PromptButtons(
-                vec![
-                    (ButtonCommand::No, "No".to_string())
-                ],
-                format!("Maybe {} has any other kids? If there's none, press the button. If you know someone, write the name.", description)
-            ),
-        OutputAction::NotifyError =>
-            OutputCommand::Prompt(
-                "Some error occured :( Please restart the bot!".to_string()
-            ),
-        OutputAction::NotifyComplete =>
-            OutputCommand::Prompt(
-                "We asked enough! you can get your pedigree chart by performing /finish command".to_string()
-            ),
-    }
-}
\ No newline at end of file
+pub fn map_next_action_output(lang: Lang, action: &OutputAction) -> OutputCommand {
+    let navigation = [(ButtonCommand::Back, Msg::GoBack), (ButtonCommand::Skip, Msg::SkipPerson), (ButtonCommand::Stop, Msg::StopHere)];
+    let ask = |answers: Vec<(ButtonCommand, Msg)>, prompt: Msg| OutputCommand::PromptButtons(
+        answers.into_iter().chain(navigation).map(|(command, label)| (command, tr(lang, label))).collect(),
+        tr(lang, prompt)
+    );
+    match action {
+        OutputAction::AskRoot => OutputCommand::Prompt(tr(lang, Msg::AskRoot)),
+        OutputAction::AskFirstParent(subject) => ask(vec![(ButtonCommand::No, Msg::DontKnow), (ButtonCommand::Unknown, Msg::UnknownParent)], Msg::AskFirstParent(subject)),
+        OutputAction::AskSecondParent(subject) => ask(vec![(ButtonCommand::No, Msg::DontKnow), (ButtonCommand::Unknown, Msg::UnknownParent)], Msg::AskSecondParent(subject)),
+        OutputAction::AskIfSiblings(subject) => ask(vec![(ButtonCommand::No, Msg::NoSiblings)], Msg::AskIfSiblings(subject)),
+        OutputAction::AskIfMoreSiblings(subject) => ask(vec![(ButtonCommand::No, Msg::NoMoreSiblings)], Msg::AskIfMoreSiblings(subject)),
+        OutputAction::AskIfChildren(subject) => ask(vec![(ButtonCommand::No, Msg::NoChildren)], Msg::AskIfChildren(subject)),
+        OutputAction::AskIfMoreChildren(subject) => ask(vec![(ButtonCommand::No, Msg::NoMoreChildren)], Msg::AskIfMoreChildren(subject)),
+        OutputAction::ConfirmSurname(child, surname) => ask(
+            vec![
+                (ButtonCommand::Yes, Msg::YesSurname(surname)),
+                (ButtonCommand::No, Msg::NoSurname)
+            ],
+            Msg::ConfirmSurname(child)
+        ),
+        OutputAction::NotifyComplete => OutputCommand::Prompt(tr(lang, Msg::NotifyComplete)),
+    }
+}
+
+pub fn map_trees_output(lang: Lang, trees: &UserTrees) -> OutputCommand {
+    if trees.is_empty() {
+        return OutputCommand::Prompt(tr(lang, Msg::NoTree));
+    }
+    let buttons = trees.list()
+        .map(|(id, tree)| {
+            let label = if trees.is_active(id) { tr(lang, Msg::CurrentTreeLabel(&tree.name)) } else { tree.name.clone() };
+            (ButtonCommand::SwitchTree(id), label)
+        })
+        .collect();
+    OutputCommand::PromptButtons(buttons, tr(lang, Msg::TreeList))
+}
+
+/// Warning found at the revision of the tree, the fix is refused once the tree changes
+pub fn map_warning_output(lang: Lang, tree_id: TreeId, revision: u64, warning: &Warning) -> OutputCommand {
+    OutputCommand::PromptButtons(
+        vec![
+            (ButtonCommand::Unlink(tree_id, revision, warning.parent.index() as u32, warning.child.index() as u32), tr(lang, Msg::RemoveLink)),
+            (ButtonCommand::Ignore, tr(lang, Msg::KeepAsIs))
+        ],
+        tr(lang, Msg::Warning(&warning.issue))
+    )
+}
+
+/// Confirms the people read from an answer listing several of them, the button takes the whole answer back
+pub fn map_names_output(lang: Lang, tree_id: TreeId, revision: u64, names: &[String]) -> OutputCommand {
+    OutputCommand::PromptButtons(
+        vec![(ButtonCommand::EditNames(tree_id, revision), tr(lang, Msg::EditNames))],
+        tr(lang, Msg::NamesUnderstood(names))
+    )
+}
+
+/// Warns that the tree is about to expire, offering to keep it or to get its tables
+pub fn map_expiry_output(lang: Lang, tree_id: TreeId, name: &str, hours_left: u64) -> OutputCommand {
+    OutputCommand::PromptButtons(
+        vec![
+            (ButtonCommand::Keep(tree_id), tr(lang, Msg::KeepTree)),
+            (ButtonCommand::ExportNow(tree_id), tr(lang, Msg::ExportNow))
+        ],
+        tr(lang, Msg::TreeExpiring(name, hours_left))
+    )
+}
+
+/// Shows what the typed statement changes and asks to confirm it
+pub fn map_proposal_output(lang: Lang, tree_id: TreeId, proposal: &Proposal) -> OutputCommand {
+    OutputCommand::PromptButtons(
+        vec![
+            (ButtonCommand::ApplyStatement(tree_id), tr(lang, Msg::ApplyStatement)),
+            (ButtonCommand::Ignore, tr(lang, Msg::DiscardStatement))
+        ],
+        tr(lang, Msg::ConfirmStatement(proposal))
+    )
+}
+
+pub fn map_statement_error(lang: Lang, error: &StatementError) -> String {
+    match error {
+        StatementError::Ambiguous(names) => tr(lang, Msg::PersonAmbiguous(names)),
+        StatementError::SamePerson => tr(lang, Msg::SamePerson),
+        StatementError::AlreadyKnown => tr(lang, Msg::AlreadyKnown),
+        StatementError::ParentUnclear(name) => tr(lang, Msg::ParentUnclear(name)),
+        StatementError::NothingPending => tr(lang, Msg::NothingPending),
+    }
+}
+
+/// Lists the placeholders with buttons to choose whom to name
+pub fn map_placeholders_output(lang: Lang, tree_id: TreeId, placeholders: &[(NodeIndex<u32>, Called)]) -> OutputCommand {
+    OutputCommand::PromptButtons(
+        placeholders.iter()
+            .map(|(ix, placeholder)| (ButtonCommand::Edit(tree_id, ix.index() as u32), tr(lang, Msg::PersonLabel(placeholder))))
+            .collect(),
+        tr(lang, Msg::ChoosePlaceholder)
+    )
+}
+
+pub fn map_tree_index_error(lang: Lang, error: &TreeIndexError) -> String {
+    match error {
+        TreeIndexError::EmptyName => tr(lang, Msg::EmptyTreeName),
+        TreeIndexError::NameTaken(name) => tr(lang, Msg::TreeNameTaken(name)),
+        TreeIndexError::NotFound(name) => tr(lang, Msg::TreeNotFound(name)),
+    }
+}
+
+/// Explains why the interview can't go on and offers the ways out as buttons
+pub fn map_state_error(lang: Lang, error: &StateError) -> OutputCommand {
+    let prompt = match error {
+        StateError::TreeFinished => Msg::TreeFinished,
+        StateError::InvalidInput => Msg::InvalidAnswer,
+        StateError::UnknownPerson(_) => Msg::PersonGone,
+        StateError::Inconsistent(_) => Msg::InterviewConfused,
+        StateError::NothingToUndo => Msg::NothingToUndo,
+        StateError::Outdated => Msg::WarningOutdated,
+    };
+    let buttons = error.recoveries().into_iter()
+        .map(|recovery| {
+            let label = match recovery {
+                Recovery::Continue => Msg::ContinueInterview,
+                Recovery::Undo => Msg::UndoAnswer,
+                Recovery::PickAnother => Msg::PickAnotherPerson,
+            };
+            (ButtonCommand::Recover(recovery), tr(lang, label))
+        })
+        .collect();
+    OutputCommand::PromptButtons(buttons, tr(lang, prompt))
+}
+
+/// What the user is told when handling their update failed, details are only logged
+pub fn map_error(lang: Lang, error: &BotError) -> OutputCommand {
+    match error {
+        BotError::Render(_) => OutputCommand::Prompt(tr(lang, Msg::ChartFailed)),
+        BotError::Telegram(_) => OutputCommand::Prompt(tr(lang, Msg::TelegramFailed)),
+        BotError::Download(_) => OutputCommand::Prompt(tr(lang, Msg::DownloadFailed)),
+        BotError::State(error) => map_state_error(lang, error),
+        BotError::Storage(StorageError::TreeGone(_)) => OutputCommand::Prompt(tr(lang, Msg::TreeGone)),
+        BotError::Storage(StorageError::PhotoCache(_)) | BotError::Config(_) => OutputCommand::Prompt(tr(lang, Msg::NotifyError)),
+    }
+}
+
+pub fn map_languages_output(lang: Lang, prompt: Msg) -> OutputCommand {
+    let buttons = Lang::ALL.iter()
+        .map(|language| (ButtonCommand::Language(*language), language.native_name().to_string()))
+        .collect();
+    OutputCommand::PromptButtons(buttons, tr(lang, prompt))
+}
+
+pub fn map_themes_output(lang: Lang, prompt: Msg) -> OutputCommand {
+    let buttons = Theme::ALL.iter()
+        .map(|theme| (ButtonCommand::Theme(*theme), theme.name().to_string()))
+        .collect();
+    OutputCommand::PromptButtons(buttons, tr(lang, prompt))
+}
+
+pub fn map_retentions_output(lang: Lang, prompt: Msg) -> OutputCommand {
+    let buttons = Retention::ALL.iter()
+        .map(|retention| (ButtonCommand::Retention(*retention), tr(lang, Msg::RetentionOption(*retention))))
+        .collect();
+    OutputCommand::PromptButtons(buttons, tr(lang, prompt))
+}
+
+pub fn author_of(user: &User) -> Author {
+    Author::new(user.id, user.full_name())
+}
+
+/// Sends the output, threading it to `reply_to` message if given
+pub async fn send_output(bot: &AutoSend<Bot>, chat_id: i64, reply_to: Option<i32>, output: OutputCommand) -> Result<Message, RequestError> {
+    let (prompt, keyboard) = match output {
+        OutputCommand::Prompt(prompt) => (prompt, None),
+        OutputCommand::PromptButtons(commands, prompt) => (prompt, Some(make_inline_keyboard(&commands))),
+    };
+    let mut request = bot.send_message(chat_id, prompt);
+    if let Some(keyboard) = keyboard {
+        request = request.reply_markup(keyboard);
+    }
+    if let Some(reply_to) = reply_to {
+        request = request.reply_to_message_id(reply_to);
+    }
+    request.await
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::updater::model::{Sex, Subject};
+    use crate::updater::name::PersonName;
+
+    #[test]
+    fn chart_options() {
+        assert_eq!(ChartOptions::parse(""), Some(ChartOptions { format: ChartFormat::Png, as_document: false }));
+        assert_eq!(ChartOptions::parse("SVG"), Some(ChartOptions { format: ChartFormat::Svg, as_document: false }));
+        assert_eq!(ChartOptions::parse("png document"), Some(ChartOptions { format: ChartFormat::Png, as_document: true }));
+        assert_eq!(ChartOptions::parse("jpeg"), None);
+    }
+
+    #[test]
+    fn focus_arguments() {
+        let focus = |up, down, siblings| Focus { up, down, siblings };
+        assert_eq!(parse_focus("Anna Smith"), Some(("Anna Smith".to_string(), focus(DEFAULT_FOCUS_GENERATIONS, DEFAULT_FOCUS_GENERATIONS, false))));
+        assert_eq!(parse_focus("Anna 3"), Some(("Anna".to_string(), focus(3, DEFAULT_FOCUS_GENERATIONS, false))));
+        assert_eq!(parse_focus("Anna 1 0 siblings"), Some(("Anna".to_string(), focus(1, 0, true))));
+        assert_eq!(parse_focus("2 1"), None);
+        assert_eq!(parse_focus(""), None);
+    }
+
+    #[test]
+    fn export_arguments() {
+        assert_eq!(parse_export("Mermaid"), Some(ExportRequest::Text(ExportFormat::Mermaid, false)));
+        assert_eq!(parse_export("plantuml file"), Some(ExportRequest::Text(ExportFormat::PlantUml, true)));
+        assert_eq!(parse_export("CSV"), Some(ExportRequest::Tables));
+        assert_eq!(parse_export("html"), Some(ExportRequest::Html));
+        assert_eq!(parse_export("mermaid svg"), None);
+        assert_eq!(parse_export(""), None);
+    }
+
+    #[test]
+    fn thumbnail_size() {
+        let size = |width: u32| PhotoSize { file_id: width.to_string(), file_unique_id: width.to_string(), width, height: width, file_size: None };
+        assert_eq!(pick_thumbnail(&[size(90), size(320), size(800)]).map(|size| size.width), Some(320));
+        assert_eq!(pick_thumbnail(&[size(1280)]).map(|size| size.width), Some(1280), "Should take the smallest size of a big photo");
+        assert_eq!(pick_thumbnail(&[]), None);
+    }
+
+    #[test]
+    fn photo_paths_stay_in_cache() {
+        let storage = Path::new("/var/lib/pedigree");
+        assert_eq!(photo_path(storage, "AgACAgIAAxkBAAIB_-1"), Some(PathBuf::from("/var/lib/pedigree/photos/AgACAgIAAxkBAAIB_-1.jpg")));
+        assert_eq!(photo_path(storage, "../../etc/passwd"), None);
+        assert_eq!(photo_path(storage, ""), None);
+    }
+
+    #[test]
+    fn broken_chart_is_an_error() {
+        // without graphviz installed it's the same error
+        assert!(matches!(print_graph("not a graph".to_string(), ChartFormat::Png), Err(BotError::Render(_))));
+    }
+
+    fn prompt(output: OutputCommand) -> String {
+        match output {
+            OutputCommand::Prompt(prompt) | OutputCommand::PromptButtons(_, prompt) => prompt
+        }
+    }
+
+    #[test]
+    fn errors_are_explained() {
+        assert_eq!(prompt(map_error(Lang::En, &BotError::Render("syntax error".to_string()))), tr(Lang::En, Msg::ChartFailed));
+        assert_eq!(prompt(map_error(Lang::En, &BotError::State(StateError::TreeFinished))), tr(Lang::En, Msg::TreeFinished));
+        assert_eq!(prompt(map_error(Lang::Ru, &BotError::Storage(StorageError::TreeGone(1)))), tr(Lang::Ru, Msg::TreeGone));
+    }
+
+    #[test]
+    fn questions_offer_navigation() {
+        let subject = Subject::named(PersonName::parse("Anna"));
+        match map_next_action_output(Lang::En, &OutputAction::AskIfSiblings(subject)) {
+            OutputCommand::PromptButtons(buttons, _) => {
+                let commands: Vec<ButtonCommand> = buttons.into_iter().map(|(command, _)| command).collect();
+                assert_eq!(commands, vec![ButtonCommand::No, ButtonCommand::Back, ButtonCommand::Skip, ButtonCommand::Stop]);
+            }
+            output => panic!("Should offer buttons, got {:?}", output)
+        }
+        assert!(matches!(map_next_action_output(Lang::En, &OutputAction::AskRoot), OutputCommand::Prompt(_)), "Should have nothing to go back to");
+    }
+
+    #[test]
+    fn placeholders_are_listed() {
+        let placeholders = vec![(NodeIndex::new(3), Called::Unknown(Some(Sex::Male), vec![PersonName::parse("Robert").into()]))];
+        match map_placeholders_output(Lang::En, 7, &placeholders) {
+            OutputCommand::PromptButtons(buttons, _) => {
+                assert_eq!(buttons, vec![(ButtonCommand::Edit(7, 3), "Unknown father of Robert".to_string())]);
+                assert_eq!(buttons[0].0.to_string().parse(), Ok(ButtonCommand::Edit(7, 3)));
+            }
+            output => panic!("Should offer buttons, got {:?}", output)
+        }
+    }
+
+    #[test]
+    fn state_errors_offer_recoveries() {
+        match map_state_error(Lang::En, &StateError::Inconsistent("asked about Anna".to_string())) {
+            OutputCommand::PromptButtons(buttons, _) => {
+                let commands: Vec<ButtonCommand> = buttons.into_iter().map(|(command, _)| command).collect();
+                assert_eq!(commands, vec![ButtonCommand::Recover(Recovery::PickAnother), ButtonCommand::Recover(Recovery::Undo)]);
+            }
+            output => panic!("Should offer buttons, got {:?}", output)
+        }
+        assert_eq!(ButtonCommand::Recover(Recovery::Undo).to_string().parse(), Ok(ButtonCommand::Recover(Recovery::Undo)));
+        assert_eq!(ButtonCommand::Unlink(1, 7, 2, 0).to_string().parse(), Ok(ButtonCommand::Unlink(1, 7, 2, 0)));
+    }
+
+    #[test]
+    fn listed_names_can_be_edited() {
+        let names = vec!["Anna".to_string(), "Bruce".to_string()];
+        match map_names_output(Lang::En, 7, 12, &names) {
+            OutputCommand::PromptButtons(buttons, prompt) => {
+                assert_eq!(prompt, "I've added 2 people: Anna; Bruce.");
+                assert_eq!(buttons[0].0.to_string().parse(), Ok(ButtonCommand::EditNames(7, 12)));
+            }
+            output => panic!("Should offer the edit button, got {:?}", output)
+        }
+    }
+
+    #[test]
+    fn expiry_warning_offers_keep_and_export() {
+        match map_expiry_output(Lang::Ru, 3, "Смиты", 2) {
+            OutputCommand::PromptButtons(buttons, prompt) => {
+                assert_eq!(prompt, "Дерево «Смиты» давно не открывали, через 2 часа оно будет удалено. Сохраните его или экспортируйте сейчас, чтобы импортировать позже. Напишите /retention, чтобы хранить деревья дольше.");
+                let commands: Vec<ButtonCommand> = buttons.iter().map(|(command, _)| command.to_string().parse().unwrap()).collect();
+                assert_eq!(commands, vec![ButtonCommand::Keep(3), ButtonCommand::ExportNow(3)]);
+            }
+            output => panic!("Should offer buttons, got {:?}", output)
+        }
+    }
+
+    #[test]
+    fn retention_buttons() {
+        match map_retentions_output(Lang::De, Msg::ChooseRetention) {
+            OutputCommand::PromptButtons(buttons, _) => {
+                let commands: Vec<ButtonCommand> = buttons.iter().map(|(command, _)| command.to_string().parse().unwrap()).collect();
+                assert_eq!(commands, Retention::ALL.into_iter().map(ButtonCommand::Retention).collect::<Vec<_>>());
+                assert_eq!(buttons[2].1, "Bis ich sie lösche");
+            }
+            output => panic!("Should offer buttons, got {:?}", output)
+        }
+    }
+
+    #[tokio::test]
+    async fn monitoring_endpoints() {
+        let state = Arc::new(AppState::new("bot".to_string(), std::env::temp_dir().join("pedigree-bot-monitoring-test")));
+        state.metrics.update_received();
+        let server = monitoring(state.clone()).recover(handle_rejection);
+
+        let health = warp::test::request().path("/healthz").reply(&server).await;
+        assert_eq!((health.status(), health.body().as_ref()), (StatusCode::OK, b"ok".as_ref()));
+        let ready = warp::test::request().path("/readyz").reply(&server).await;
+        assert_eq!(ready.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(String::from_utf8_lossy(ready.body()).contains("webhook is not registered"));
+        let metrics = warp::test::request().path("/metrics").reply(&server).await;
+        assert!(String::from_utf8_lossy(metrics.body()).lines().any(|line| line == "pedigree_updates_received_total 1"));
+        assert_eq!(warp::test::request().path("/status").reply(&server).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn webhook_url_ends_with_secret() {
+        let secret = WebhookSecret { path: "s3cret".to_string(), token: "t0ken".to_string() };
+        assert_eq!(secret.url(&Url::parse("https://example.com").unwrap()).as_str(), "https://example.com/s3cret");
+        assert_eq!(secret.url(&Url::parse("https://example.com/bot/").unwrap()).as_str(), "https://example.com/bot/s3cret");
+        assert!(secret.accepts(Some("t0ken")));
+        assert!(!secret.accepts(Some("t0ke")));
+        assert!(!secret.accepts(None));
+    }
+
+    #[tokio::test]
+    async fn webhook_rejects_requests_without_secrets() {
+        let state = Arc::new(AppState::new("bot".to_string(), std::env::temp_dir().join("pedigree-bot-webhook-test")));
+        let secret = WebhookSecret { path: "s3cret".to_string(), token: "t0ken".to_string() };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let tx = Arc::new(std::sync::Mutex::new(Some(tx)));
+        let webhook_url = secret.url(&Url::parse("https://example.com/bot").unwrap());
+        let server = updates(state.clone(), secret, &webhook_url, tx.clone()).recover(handle_rejection);
+        let post = |path: &str, token: &str| warp::test::request().method("POST").path(path).header(SECRET_TOKEN_HEADER, token).body("{}");
+
+        assert_eq!(post("/bot/s3cret", "t0ken").reply(&server).await.status(), StatusCode::OK);
+        assert_eq!(post("/bot/s3cret", "wrong").reply(&server).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(warp::test::request().method("POST").path("/bot/s3cret").body("{}").reply(&server).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(post("/", "t0ken").reply(&server).await.status(), StatusCode::NOT_FOUND);
+        assert_eq!(post("/s3cret", "t0ken").reply(&server).await.status(), StatusCode::NOT_FOUND);
+        assert_eq!(post("/anything/s3cret", "t0ken").reply(&server).await.status(), StatusCode::NOT_FOUND, "Should only take the exact webhook path");
+        let metrics = state.metrics.render(0);
+        assert!(metrics.lines().any(|line| line == "pedigree_updates_received_total 1"));
+        assert!(metrics.lines().any(|line| line == "pedigree_webhook_rejected_total 2"));
+
+        tx.lock().unwrap().take();
+        assert_eq!(post("/bot/s3cret", "t0ken").reply(&server).await.status(), StatusCode::SERVICE_UNAVAILABLE, "Should refuse updates after shutdown");
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn webhook_errors_hide_the_token() {
+        // nothing listens on port 1, so the request fails with the URL in the error
+        let bot = Bot::new("123:s3cret").set_api_url(Url::parse("http://127.0.0.1:1").unwrap());
+        let secret = WebhookSecret::generate();
+        let error = set_webhook(&bot, Url::parse("https://example.com").unwrap(), &secret).await.unwrap_err();
+        assert!(!error.to_string().contains("s3cret"), "Should not log the token: {}", error);
+    }
+
+    #[test]
+    fn big_charts_are_sent_as_documents() {
+        let png = ChartOptions::parse("").unwrap();
+        assert!(!png.sends_document(DOCUMENT_NODE_THRESHOLD));
+        assert!(png.sends_document(DOCUMENT_NODE_THRESHOLD + 1));
+        assert!(ChartOptions::parse("pdf").unwrap().sends_document(1), "Should never send PDF as a photo");
+    }
+}
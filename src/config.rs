@@ -0,0 +1,369 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::auxillary::renderer_version;
+use crate::error::BotError;
+
+/// Environment variable with the path of the TOML file, `--config` overrides it
+const CONFIG_FILE_VAR: &str = "PEDIGREE_CONFIG";
+/// Proxy for requests to Telegram, teloxide reads it itself when the client is made
+const PROXY_VAR: &str = "TELOXIDE_PROXY";
+/// Command line flags followed by a value
+const VALUE_FLAGS: [&str; 8] = ["--config", "--server-url", "--ip", "--port", "--clear-session-hours", "--storage-path", "--api-url", "--shutdown-timeout-secs"];
+const DEFAULT_IP: &str = "0.0.0.0";
+const DEFAULT_PORT: i64 = 8080;
+const DEFAULT_CLEAR_SESSION_HOURS: i64 = 6;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: i64 = 30;
+/// Directory in the system temp dir for the bot's files unless `storage_path` is set
+const DEFAULT_STORAGE_DIR: &str = "pedigree-bot";
+
+/// Settings given by one source, each of them may be missing.
+/// Numbers are kept wide so the range is checked with a clear message instead of failing to parse.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Layer {
+    token: Option<String>,
+    server_url: Option<String>,
+    ip: Option<String>,
+    port: Option<i64>,
+    clear_session_hours: Option<i64>,
+    storage_path: Option<PathBuf>,
+    api_url: Option<String>,
+    shutdown_timeout_secs: Option<i64>,
+    delete_webhook_on_shutdown: Option<bool>,
+}
+
+impl Layer {
+    /// Settings missing here are taken from the source with lower precedence
+    fn or(self, lower: Layer) -> Layer {
+        Layer {
+            token: self.token.or(lower.token),
+            server_url: self.server_url.or(lower.server_url),
+            ip: self.ip.or(lower.ip),
+            port: self.port.or(lower.port),
+            clear_session_hours: self.clear_session_hours.or(lower.clear_session_hours),
+            storage_path: self.storage_path.or(lower.storage_path),
+            api_url: self.api_url.or(lower.api_url),
+            shutdown_timeout_secs: self.shutdown_timeout_secs.or(lower.shutdown_timeout_secs),
+            delete_webhook_on_shutdown: self.delete_webhook_on_shutdown.or(lower.delete_webhook_on_shutdown),
+        }
+    }
+
+    fn from_file(path: &Path) -> Result<Layer, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("cannot read {}: {}", path.display(), error))?;
+        toml::from_str(&text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// Reads the variables the bot has always used, `var` is `std::env::var` outside of tests
+    fn from_env<F: Fn(&str) -> Option<String>>(var: F) -> Result<Layer, Vec<String>> {
+        let mut problems = vec![];
+        let mut number = |name: &str| var(name).and_then(|value| parse_number(name, &value).map_err(|problem| problems.push(problem)).ok());
+        let port = number("PORT");
+        let clear_session_hours = number("CLEAR_SESSION_HOURS");
+        let shutdown_timeout_secs = number("SHUTDOWN_TIMEOUT_SECS");
+        let delete_webhook_on_shutdown = var("DELETE_WEBHOOK_ON_SHUTDOWN").and_then(|value| match value.trim().to_lowercase().as_str() {
+            "true" | "1" | "yes" => Some(true),
+            "false" | "0" | "no" => Some(false),
+            _ => {
+                problems.push(format!("DELETE_WEBHOOK_ON_SHUTDOWN should be true or false, got \"{}\"", value));
+                None
+            }
+        });
+        // teloxide panics on a proxy reqwest can't use, so it's checked here the same way, with http:// by default
+        if let Some(proxy) = var(PROXY_VAR) {
+            let url = Url::parse(&proxy).ok().filter(Url::has_host).or_else(|| Url::parse(&format!("http://{}", proxy)).ok());
+            if !url.is_some_and(|url| url.has_host() && ["http", "https"].contains(&url.scheme())) {
+                problems.push(format!("{} should be an http or https proxy URL, got \"{}\"", PROXY_VAR, proxy));
+            }
+        }
+        let layer = Layer {
+            port,
+            clear_session_hours,
+            shutdown_timeout_secs,
+            delete_webhook_on_shutdown,
+            api_url: var("TELEGRAM_API_URL"),
+            token: var("TELOXIDE_TOKEN"),
+            server_url: var("SERVER_URL"),
+            ip: var("IP"),
+            storage_path: var("STORAGE_PATH").map(PathBuf::from),
+        };
+        if problems.is_empty() { Ok(layer) } else { Err(problems) }
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<i64, String> {
+    value.trim().parse().map_err(|_| format!("{} should be a number, got \"{}\"", name, value))
+}
+
+/// What the command line asks for: settings overriding the other sources, where the file is and whether to only check the configuration
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub check_config: bool,
+    config_file: Option<PathBuf>,
+    layer: Layer,
+}
+
+impl Options {
+    pub const USAGE: &'static str = "Usage: pedigree-bot [--config <file.toml>] [--server-url <url>] [--ip <address>] [--port <port>] \
+        [--clear-session-hours <hours>] [--storage-path <dir>] [--api-url <url>] [--shutdown-timeout-secs <seconds>] \
+        [--delete-webhook-on-shutdown] [--check-config]";
+
+    /// Reads `--flag value` and `--flag=value` arguments, the program name is already skipped
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, BotError> {
+        let usage = |problem: String| BotError::Config(format!("{}\n{}", problem, Options::USAGE));
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None)
+            };
+            if flag == "--check-config" && inline.is_none() {
+                options.check_config = true;
+                continue;
+            }
+            if flag == "--delete-webhook-on-shutdown" && inline.is_none() {
+                options.layer.delete_webhook_on_shutdown = Some(true);
+                continue;
+            }
+            if !VALUE_FLAGS.contains(&flag.as_str()) {
+                return Err(usage(format!("unknown argument {}", flag)));
+            }
+            let value = match inline.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(usage(format!("{} needs a value", flag)))
+            };
+            let layer = &mut options.layer;
+            match flag.as_str() {
+                "--config" => options.config_file = Some(PathBuf::from(value)),
+                "--server-url" => layer.server_url = Some(value),
+                "--ip" => layer.ip = Some(value),
+                "--port" => layer.port = Some(parse_number(&flag, &value).map_err(usage)?),
+                "--clear-session-hours" => layer.clear_session_hours = Some(parse_number(&flag, &value).map_err(usage)?),
+                "--storage-path" => layer.storage_path = Some(PathBuf::from(value)),
+                "--api-url" => layer.api_url = Some(value),
+                "--shutdown-timeout-secs" => layer.shutdown_timeout_secs = Some(parse_number(&flag, &value).map_err(usage)?),
+                _ => unreachable!("every flag with a value is matched")
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Creates the directory if needed and writes a file there to be sure the bot can save its files
+pub fn check_writable(path: &Path) -> std::io::Result<()> {
+    let probe = path.join(".write-check");
+    fs::create_dir_all(path)?;
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+/// Validated settings of the bot
+#[derive(Debug, PartialEq)]
+pub struct Config {
+    pub token: String,
+    /// Public HTTPS address Telegram sends updates to
+    pub server_url: Url,
+    /// Where the webhook server listens
+    pub addr: SocketAddr,
+    /// Trees unused for that long are cleared
+    pub clear_session_hours: u32,
+    /// Directory for cached photos and trees saved on shutdown
+    pub storage_path: PathBuf,
+    /// Bot API server other than Telegram's, e.g. a local one
+    pub api_url: Option<Url>,
+    /// How long updates being handled may take to finish on shutdown
+    pub shutdown_timeout: Duration,
+    /// Whether Telegram should stop sending updates once the bot is stopped
+    pub delete_webhook_on_shutdown: bool,
+}
+
+impl Config {
+    /// Combines the sources, the command line wins over the environment, which wins over the file.
+    /// Every problem is reported at once, so a broken deployment is fixed in one go.
+    pub fn load(options: Options) -> Result<Config, BotError> {
+        let env = Layer::from_env(|name| std::env::var(name).ok());
+        let file = match options.config_file.or_else(|| std::env::var(CONFIG_FILE_VAR).ok().map(PathBuf::from)) {
+            Some(path) => Layer::from_file(&path).map_err(|problem| vec![problem]),
+            None => Ok(Layer::default())
+        };
+        let config = match (env, file) {
+            (Ok(env), Ok(file)) => Config::resolve(options.layer.or(env).or(file)),
+            (env, file) => Err(env.err().into_iter().chain(file.err()).flatten().collect())
+        };
+        let problems = match config {
+            Ok(config) => match config.check_environment()[..] {
+                [] => return Ok(config),
+                ref problems => problems.to_vec()
+            },
+            Err(problems) => problems
+        };
+        Err(BotError::Config(problems.join("; ")))
+    }
+
+    /// Checks the values of the settings, filling in defaults for the optional ones
+    fn resolve(layer: Layer) -> Result<Config, Vec<String>> {
+        let mut problems = vec![];
+        let token = layer.token.filter(|token| !token.trim().is_empty());
+        if token.is_none() {
+            problems.push("no bot token, set TELOXIDE_TOKEN or token in the file".to_string());
+        }
+        let server_url = match layer.server_url.as_deref().map(Url::parse) {
+            Some(Ok(url)) if url.scheme() == "https" => Some(url),
+            Some(Ok(url)) => {
+                problems.push(format!("server_url should use https, Telegram doesn't send updates over {}", url.scheme()));
+                None
+            }
+            Some(Err(error)) => {
+                problems.push(format!("server_url: {}", error));
+                None
+            }
+            None => {
+                problems.push("no server_url, set SERVER_URL, --server-url or server_url in the file".to_string());
+                None
+            }
+        };
+        let ip = layer.ip.unwrap_or_else(|| DEFAULT_IP.to_string());
+        let ip = ip.parse::<IpAddr>().map_err(|_| problems.push(format!("ip \"{}\" is not an IP address", ip))).ok();
+        let port = layer.port.unwrap_or(DEFAULT_PORT);
+        let port = u16::try_from(port).ok().filter(|port| *port > 0);
+        if port.is_none() {
+            problems.push(format!("port should be between 1 and {}", u16::MAX));
+        }
+        let clear_session_hours = u32::try_from(layer.clear_session_hours.unwrap_or(DEFAULT_CLEAR_SESSION_HOURS)).ok().filter(|hours| *hours > 0);
+        if clear_session_hours.is_none() {
+            problems.push("clear_session_hours should be a positive number of hours".to_string());
+        }
+        let storage_path = layer.storage_path.unwrap_or_else(|| std::env::temp_dir().join(DEFAULT_STORAGE_DIR));
+        let api_url = match layer.api_url.as_deref().map(Url::parse) {
+            Some(Ok(url)) if ["http", "https"].contains(&url.scheme()) => Some(url),
+            Some(Ok(url)) => {
+                problems.push(format!("api_url should use http or https, not {}", url.scheme()));
+                None
+            }
+            Some(Err(error)) => {
+                problems.push(format!("api_url: {}", error));
+                None
+            }
+            None => None
+        };
+        let shutdown_timeout = u64::try_from(layer.shutdown_timeout_secs.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS)).ok().map(Duration::from_secs);
+        if shutdown_timeout.is_none() {
+            problems.push("shutdown_timeout_secs can't be negative".to_string());
+        }
+        let delete_webhook_on_shutdown = layer.delete_webhook_on_shutdown.unwrap_or(false);
+
+        match (token, server_url, ip, port, clear_session_hours, shutdown_timeout) {
+            (Some(token), Some(server_url), Some(ip), Some(port), Some(clear_session_hours), Some(shutdown_timeout)) if problems.is_empty() => {
+                let addr = SocketAddr::new(ip, port);
+                Ok(Config { token, server_url, addr, clear_session_hours, storage_path, api_url, shutdown_timeout, delete_webhook_on_shutdown })
+            }
+            _ => Err(problems)
+        }
+    }
+
+    /// Checks what the bot needs from the machine: graphviz to draw charts and a writable storage directory
+    fn check_environment(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if let Err(error) = renderer_version() {
+            problems.push(format!("charts can't be drawn, {}", error));
+        }
+        if let Err(error) = check_writable(&self.storage_path) {
+            problems.push(format!("storage_path {} is not writable: {}", self.storage_path.display(), error));
+        }
+        problems
+    }
+}
+
+/// The effective configuration as it would be written in the file, without the token
+impl Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "token = \"<hidden>\"")?;
+        writeln!(f, "server_url = \"{}\"", self.server_url)?;
+        writeln!(f, "ip = \"{}\"", self.addr.ip())?;
+        writeln!(f, "port = {}", self.addr.port())?;
+        writeln!(f, "clear_session_hours = {}", self.clear_session_hours)?;
+        writeln!(f, "storage_path = \"{}\"", self.storage_path.display())?;
+        if let Some(api_url) = &self.api_url {
+            writeln!(f, "api_url = \"{}\"", api_url)?;
+        }
+        writeln!(f, "shutdown_timeout_secs = {}", self.shutdown_timeout.as_secs())?;
+        write!(f, "delete_webhook_on_shutdown = {}", self.delete_webhook_on_shutdown)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn layer(server_url: &str, port: i64) -> Layer {
+        Layer { server_url: Some(server_url.to_string()), port: Some(port), ..Layer::default() }
+    }
+
+    #[test]
+    fn command_line_wins_over_env_and_file() {
+        let options = Options::parse(args("--port=8443 --check-config --delete-webhook-on-shutdown --storage-path /var/lib/pedigree")).unwrap();
+        assert!(options.check_config);
+        let env = Layer::from_env(|name| match name {
+            "TELOXIDE_TOKEN" => Some("123:abc".to_string()),
+            "SERVER_URL" => Some("https://env.example.com".to_string()),
+            "PORT" => Some("9000".to_string()),
+            "SHUTDOWN_TIMEOUT_SECS" => Some("5".to_string()),
+            "DELETE_WEBHOOK_ON_SHUTDOWN" => Some("no".to_string()),
+            _ => None
+        }).unwrap();
+        let file: Layer = toml::from_str("server_url = \"https://file.example.com\"\nip = \"127.0.0.1\"\nclear_session_hours = 24").unwrap();
+
+        let config = Config::resolve(options.layer.or(env).or(file)).unwrap();
+        assert_eq!(config.server_url.as_str(), "https://env.example.com/");
+        assert_eq!(config.addr, "127.0.0.1:8443".parse().unwrap());
+        assert_eq!(config.clear_session_hours, 24);
+        assert_eq!(config.storage_path, PathBuf::from("/var/lib/pedigree"));
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(5));
+        assert!(config.delete_webhook_on_shutdown);
+        assert_eq!(config.api_url, None);
+        assert!(!config.to_string().contains("123:abc"), "Should not print the token");
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let problems = Config::resolve(Layer { ip: Some("localhost".to_string()), clear_session_hours: Some(0), ..layer("http://example.com", 70000) }).unwrap_err();
+        assert_eq!(problems, vec![
+            "no bot token, set TELOXIDE_TOKEN or token in the file",
+            "server_url should use https, Telegram doesn't send updates over http",
+            "ip \"localhost\" is not an IP address",
+            "port should be between 1 and 65535",
+            "clear_session_hours should be a positive number of hours",
+        ]);
+        assert_eq!(Layer::from_env(|name| if name == "PORT" { Some("eighty".to_string()) } else { None }).unwrap_err(), vec!["PORT should be a number, got \"eighty\""]);
+        assert_eq!(Layer::from_env(|name| if name == PROXY_VAR { Some("socks5://proxy:1080".to_string()) } else { None }).unwrap_err(), vec!["TELOXIDE_PROXY should be an http or https proxy URL, got \"socks5://proxy:1080\""]);
+        assert!(Layer::from_env(|name| if name == PROXY_VAR { Some("localhost:3128".to_string()) } else { None }).is_ok(), "Should take a proxy without a scheme");
+    }
+
+    #[test]
+    fn wrong_arguments_are_rejected() {
+        assert!(matches!(Options::parse(args("--port")), Err(BotError::Config(_))));
+        assert!(matches!(Options::parse(args("--verbose yes")), Err(BotError::Config(_))));
+        assert!(toml::from_str::<Layer>("sever_url = \"https://example.com\"").is_err(), "Should catch misspelled settings");
+    }
+
+    #[test]
+    fn storage_path_must_be_a_writable_directory() {
+        let file = std::env::temp_dir().join("pedigree-bot-config-test");
+        fs::write(&file, b"not a directory").unwrap();
+        let config = Config { storage_path: file.clone(), ..Config::resolve(Layer { token: Some("123:abc".to_string()), ..layer("https://example.com", 443) }).unwrap() };
+        assert!(config.check_environment().iter().any(|problem| problem.starts_with("storage_path")));
+        fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::{self, Display};
+
+use teloxide::{DownloadError, RequestError};
+
+use crate::session::TreeId;
+use crate::updater::model::StateError;
+
+/// Data the bot keeps which turned out to be missing or unwritable
+#[derive(Debug)]
+pub enum StorageError {
+    /// The tree was deleted while the update was handled
+    TreeGone(TreeId),
+    /// Downloaded photo can't be saved to the cache
+    PhotoCache(std::io::Error),
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::TreeGone(tree_id) => write!(f, "tree {} doesn't exist anymore", tree_id),
+            StorageError::PhotoCache(error) => write!(f, "cannot cache the photo: {}", error),
+        }
+    }
+}
+
+/// Everything that stops the bot from handling an update or from starting.
+/// Handlers return it, the dispatcher logs it and tells the user what went wrong.
+#[derive(Debug)]
+pub enum BotError {
+    /// Graphviz is missing or rejected the chart, with its output
+    Render(String),
+    Telegram(RequestError),
+    Download(DownloadError),
+    State(StateError),
+    Storage(StorageError),
+    /// Missing or malformed setting, only on startup
+    Config(String),
+}
+
+impl BotError {
+    /// Short name of the variant to count errors by
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BotError::Render(_) => "render",
+            BotError::Telegram(_) => "telegram",
+            BotError::Download(_) => "download",
+            BotError::State(_) => "state",
+            BotError::Storage(_) => "storage",
+            BotError::Config(_) => "config",
+        }
+    }
+}
+
+impl Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BotError::Render(detail) => write!(f, "cannot render the chart: {}", detail),
+            BotError::Telegram(error) => write!(f, "Telegram request failed: {}", error),
+            BotError::Download(error) => write!(f, "cannot download the file: {}", error),
+            BotError::State(error) => write!(f, "cannot continue the interview: {}", error),
+            BotError::Storage(error) => write!(f, "{}", error),
+            BotError::Config(detail) => write!(f, "bad configuration: {}", detail),
+        }
+    }
+}
+
+impl std::error::Error for BotError {}
+
+impl From<RequestError> for BotError {
+    fn from(error: RequestError) -> Self { BotError::Telegram(error) }
+}
+
+impl From<DownloadError> for BotError {
+    fn from(error: DownloadError) -> Self { BotError::Download(error) }
+}
+
+impl From<StateError> for BotError {
+    fn from(error: StateError) -> Self { BotError::State(error) }
+}
+
+impl From<StorageError> for BotError {
+    fn from(error: StorageError) -> Self { BotError::Storage(error) }
+}
@@ -0,0 +1,1014 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use teloxide::adaptors::AutoSend;
+use teloxide::net::Download;
+use teloxide::{ utils::command::BotCommand, prelude::*};
+use teloxide::payloads::{AnswerCallbackQuerySetters, SendMessageSetters};
+use teloxide_core::types::{Chat, Document, InputFile, ParseMode, PhotoSize};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use petgraph::graph::NodeIndex;
+
+use crate::auxillary::{self, author_of, parse_export, parse_focus, photo_path, pick_thumbnail, ChartOptions, ExportRequest, EXPORT_MESSAGE_LIMIT, IMPORT_ERRORS_SHOWN, IMPORT_SIZE_LIMIT, map_error, map_expiry_output, map_languages_output, map_names_output, map_placeholders_output, map_proposal_output, map_retentions_output, map_statement_error, map_themes_output, map_next_action_output, map_warning_output, map_tree_index_error, map_trees_output, send_output, OutputCommand};
+use crate::error::{BotError, StorageError};
+use crate::incoming::{classify, Incoming};
+use crate::locale::{tr, Lang, Msg};
+use crate::metrics::Metrics;
+use crate::session::{Access, Retention, Tree, TreeId, TreeIndexError};
+use crate::storage::{JoinError, Storage};
+use crate::updater::chart::{PhotoFiles, Theme};
+use crate::updater::export::ExportFormat;
+use crate::updater::graph_updater::GraphUpdater;
+use crate::updater::model::{Author, ButtonCommand, InputAction, OutputAction, Recovery, StateError, PLACEHOLDER_NAME};
+use crate::updater::statement::{parse_statement, Statement};
+use crate::updater::table::Table;
+use crate::updater::utility::escape_html;
+
+/// How long before expiry members are warned, storage shortens it to half of the retention
+const EXPIRY_WARNING: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// State shared by all update handlers
+pub struct AppState {
+    pub storage: Storage,
+    pub bot_username: String,
+    /// Directory for cached photos
+    pub storage_path: PathBuf,
+    pub metrics: Metrics,
+    /// Telegram has accepted the webhook, so updates are coming
+    pub webhook_registered: AtomicBool,
+}
+
+impl AppState {
+    pub fn new(bot_username: String, storage_path: PathBuf) -> Self {
+        Self { storage: Storage::new(), bot_username, storage_path, metrics: Metrics::default(), webhook_registered: AtomicBool::new(false) }
+    }
+}
+
+#[derive(BotCommand)]
+#[command(rename = "lowercase", description = "These commands are supported:")]
+pub enum Command {
+    #[command(description = "List all commands")]
+    Help,
+    #[command(description = "Start/restart tree generation")]
+    Start(String),
+    #[command(description = "Print your family tree to the screen: /finish [svg|pdf] [document]")]
+    Finish(String),
+    #[command(description = "Print relatives of one person: /chart <name> [generations up] [generations down] [siblings]")]
+    Chart(String),
+    #[command(description = "Export the tree as text or tables: /export mermaid, /export plantuml or /export csv")]
+    Export(String),
+    #[command(description = "Choose a person to send a photo of: /edit <name>")]
+    Edit(String),
+    #[command(description = "Create a new tree: /new <name>")]
+    New(String),
+    #[command(description = "List your trees")]
+    Trees,
+    #[command(description = "Switch to another tree: /switch <name>")]
+    Switch(String),
+    #[command(rename = "rename_tree", description = "Rename the current tree: /rename_tree <new name>")]
+    RenameTree(String),
+    #[command(rename = "delete_tree", description = "Delete a tree: /delete_tree <name>")]
+    DeleteTree(String),
+    #[command(description = "Invite relatives to the current tree: /share view or /share edit")]
+    Share(String),
+    #[command(description = "Change the language: /language en, ru or de")]
+    Language(String),
+    #[command(description = "Change the chart style: /theme classic, pastel, dark or plain")]
+    Theme(String),
+    #[command(description = "Choose how long unused trees are kept: /retention default, month or forever")]
+    Retention(String),
+}
+
+impl Command {
+    /// Name to count the command by
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Help => "help",
+            Command::Start(_) => "start",
+            Command::Finish(_) => "finish",
+            Command::Chart(_) => "chart",
+            Command::Export(_) => "export",
+            Command::Edit(_) => "edit",
+            Command::New(_) => "new",
+            Command::Trees => "trees",
+            Command::Switch(_) => "switch",
+            Command::RenameTree(_) => "rename_tree",
+            Command::DeleteTree(_) => "delete_tree",
+            Command::Share(_) => "share",
+            Command::Language(_) => "language",
+            Command::Theme(_) => "theme",
+            Command::Retention(_) => "retention",
+        }
+    }
+}
+
+/// Sends the text to every chat in its own language. A relative who has blocked the bot doesn't stop the others from being notified.
+async fn notify<F: Fn(Lang) -> String>(bot: &AutoSend<Bot>, storage: &Storage, chat_ids: Vec<i64>, text: F) {
+    for chat_id in chat_ids {
+        if let Err(error) = bot.send_message(chat_id, text(storage.language(chat_id, None))).await {
+            log::warn!("Cannot notify chat {}: {}", chat_id, error);
+        }
+    }
+}
+
+/// Warns members of trees nobody has used for almost `max_age`, so they can keep or export them before they are cleared
+pub async fn warn_expiring(bot: &AutoSend<Bot>, storage: &Storage, max_age: Duration) {
+    for (tree_id, members, left) in storage.expiring(max_age, EXPIRY_WARNING) {
+        let hours_left = ((left.as_secs() + 59 * 60) / (60 * 60)).max(1);
+        for chat_id in members {
+            let name = match storage.user(chat_id).get(tree_id) {
+                Some(entry) => entry.name.clone(),
+                None => continue
+            };
+            let output = map_expiry_output(storage.language(chat_id, None), tree_id, &name, hours_left);
+            if let Err(error) = send_output(bot, chat_id, None, output).await {
+                log::warn!("Cannot warn chat {} about expiry: {}", chat_id, error);
+            }
+        }
+    }
+}
+
+/// Where to send a question: the chat and, in groups, the message of the asked user to thread the question to
+struct Asked {
+    chat_id: i64,
+    user_id: i64,
+    reply_to: Option<i32>,
+    lang: Lang,
+}
+
+impl Asked {
+    fn new(chat: &Chat, user_id: i64, message_id: i32, lang: Lang) -> Self {
+        let reply_to = if chat.is_private() { None } else { Some(message_id) };
+        Self { chat_id: chat.id, user_id, reply_to, lang }
+    }
+}
+
+/// Sends the next question and remembers it so buttons of older questions are ignored
+async fn ask(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, tree_id: TreeId, output: OutputCommand) -> Result<(), BotError> {
+    let message = send_output(bot, asked.chat_id, asked.reply_to, output).await?;
+    storage.set_question(asked.chat_id, tree_id, asked.user_id, message.id);
+    Ok(())
+}
+
+async fn answer(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, author: &Author, input: InputAction<'_>) -> Result<(), BotError> {
+    let chat_id = asked.chat_id;
+    let (tree_id, entry, tree) = match storage.active_tree(chat_id) {
+        Some(active) => active,
+        None => return Ok(())
+    };
+    if !entry.access.can_edit() {
+        bot.send_message(chat_id, tr(asked.lang, Msg::ViewOnly(&entry.name))).await?;
+        return Ok(());
+    }
+
+    let (output_action, added, revision, warnings, collaborators) = {
+        let mut tree = tree.lock().await;
+        let count = tree.graph_updater.node_count();
+        let output_action = tree.graph_updater.handle_command_by(input, author)?;
+        let warnings = tree.graph_updater.take_new_warnings();
+        (output_action, tree.graph_updater.names_since(count), tree.graph_updater.revision(), warnings, tree.collaborators(chat_id))
+    };
+
+    if added.len() > 1 {
+        send_output(bot, chat_id, asked.reply_to, map_names_output(asked.lang, tree_id, revision, &added)).await?;
+    }
+    for warning in warnings {
+        send_output(bot, chat_id, asked.reply_to, map_warning_output(asked.lang, tree_id, revision, &warning)).await?;
+    }
+    ask(bot, storage, asked, tree_id, map_next_action_output(asked.lang, &output_action)).await?;
+    if !added.is_empty() {
+        notify(bot, storage, collaborators, |lang| tr(lang, Msg::PeopleAdded(&author.name, &added, &entry.name))).await;
+    }
+    Ok(())
+}
+
+async fn unlink(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, tree_id: TreeId, revision: u64, parent: u32, child: u32) -> Result<(), BotError> {
+    let (chat_id, lang) = (asked.chat_id, asked.lang);
+    let can_edit = storage.user(chat_id).list().any(|(id, entry)| id == tree_id && entry.access.can_edit());
+    if !can_edit {
+        return Ok(());
+    }
+    let tree = storage.tree(tree_id).ok_or(StorageError::TreeGone(tree_id))?;
+    let (unlinked, revision, warnings) = {
+        let mut tree = tree.lock().await;
+        let unlinked = tree.graph_updater.unlink(revision, NodeIndex::new(parent as usize), NodeIndex::new(child as usize));
+        // an outdated button is replaced with buttons for what is wrong with the tree now
+        let warnings = match unlinked {
+            Err(StateError::Outdated) => tree.graph_updater.warnings(),
+            _ => {
+                tree.graph_updater.take_new_warnings();
+                vec![]
+            }
+        };
+        (unlinked, tree.graph_updater.revision(), warnings)
+    };
+    let answer = match unlinked {
+        Ok(Some((parent, child))) => tr(lang, Msg::Unlinked(&parent, &child)),
+        Ok(None) => tr(lang, Msg::AlreadyUnlinked),
+        Err(StateError::Outdated) => tr(lang, Msg::WarningOutdated),
+        Err(error) => return Err(error.into())
+    };
+    bot.send_message(chat_id, answer).await?;
+    for warning in warnings {
+        send_output(bot, chat_id, asked.reply_to, map_warning_output(lang, tree_id, revision, &warning)).await?;
+    }
+    Ok(())
+}
+
+async fn switch(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, tree_id: TreeId) -> Result<(), BotError> {
+    let chat_id = asked.chat_id;
+    // the guard of the chat's trees is dropped here, not held while Telegram answers
+    let switched = storage.user(chat_id).switch(tree_id).map(|entry| entry.name.clone());
+    let name = match switched {
+        Some(name) => name,
+        None => {
+            bot.send_message(chat_id, tr(asked.lang, Msg::TreeGone)).await?;
+            return Ok(());
+        }
+    };
+    let tree = storage.tree(tree_id).ok_or(StorageError::TreeGone(tree_id))?;
+    let question = tree.lock().await.graph_updater.current_question(asked.user_id)?;
+    bot.send_message(chat_id, tr(asked.lang, Msg::SwitchedTo(&name))).await?;
+    ask(bot, storage, asked, tree_id, map_next_action_output(asked.lang, &question)).await
+}
+
+async fn start(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, author: &Author, token: &str) -> Result<(), BotError> {
+    let chat_id = asked.chat_id;
+    if token.is_empty() {
+        let restarted = match storage.active_tree(chat_id) {
+            Some((_, entry, tree)) if entry.access == Access::Owner => {
+                tree.lock().await.restart();
+                true
+            }
+            Some((_, entry, _)) => {
+                bot.send_message(chat_id, tr(asked.lang, Msg::OnlyOwnerRestarts(&entry.name))).await?;
+                false
+            }
+            None => {
+                storage.create_default_tree(chat_id);
+                true
+            }
+        };
+        if restarted {
+            bot.send_message(chat_id, tr(asked.lang, Msg::Started)).await?;
+        }
+        return Ok(());
+    }
+
+    match storage.join(token, chat_id).await {
+        Ok((invite, entry)) => {
+            bot.send_message(chat_id, tr(asked.lang, Msg::Joined(&entry.name, entry.access.can_edit()))).await?;
+            notify(bot, storage, vec![invite.inviter], |lang| tr(lang, Msg::MemberJoined(&author.name, &invite.name))).await;
+            if entry.access.can_edit() {
+                if let Some(tree) = storage.tree(invite.tree_id) {
+                    let question = tree.lock().await.graph_updater.current_question(asked.user_id)?;
+                    ask(bot, storage, asked, invite.tree_id, map_next_action_output(asked.lang, &question)).await?;
+                }
+            }
+        }
+        Err(JoinError::UnknownInvite) | Err(JoinError::TreeDeleted) => {
+            bot.send_message(chat_id, tr(asked.lang, Msg::InviteInvalid)).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn delete(bot: &AutoSend<Bot>, storage: &Storage, chat_id: i64, lang: Lang, author: &Author, name: &str) -> Result<(), BotError> {
+    let removed = storage.user(chat_id).remove(name);
+    let (tree_id, entry) = match removed {
+        Ok(removed) => removed,
+        Err(TreeIndexError::NotFound(name)) if name.is_empty() => {
+            bot.send_message(chat_id, map_tree_index_error(lang, &TreeIndexError::EmptyName)).await?;
+            return Ok(());
+        }
+        Err(error) => {
+            bot.send_message(chat_id, map_tree_index_error(lang, &error)).await?;
+            return Ok(());
+        }
+    };
+
+    let tree = if entry.access == Access::Owner { storage.delete_tree(tree_id) } else { storage.tree(tree_id) };
+    let collaborators = match tree {
+        Some(tree) => {
+            let mut tree = tree.lock().await;
+            tree.members.remove(&chat_id);
+            tree.collaborators(chat_id)
+        }
+        None => vec![]
+    };
+    let deleted = entry.access == Access::Owner;
+    let answer = if deleted { tr(lang, Msg::TreeDeleted(&entry.name)) } else { tr(lang, Msg::TreeLeft(&entry.name)) };
+    let current = match storage.user(chat_id).active() {
+        Some((_, active)) => tr(lang, Msg::ActiveTree(&active.name)),
+        None => tr(lang, Msg::NoTree)
+    };
+    bot.send_message(chat_id, format!("{} {}", answer, current)).await?;
+    notify(bot, storage, collaborators, |lang| {
+        if deleted { tr(lang, Msg::TreeDeletedBy(&author.name, &entry.name)) } else { tr(lang, Msg::TreeLeftBy(&author.name, &entry.name)) }
+    }).await;
+    Ok(())
+}
+
+async fn share(bot: &AutoSend<Bot>, state: &AppState, chat_id: i64, lang: Lang, access: &str) -> Result<(), BotError> {
+    let storage = &state.storage;
+    let access = match access.trim().to_lowercase().as_str() {
+        "" | "view" => Access::View,
+        "edit" => Access::Edit,
+        _ => {
+            bot.send_message(chat_id, tr(lang, Msg::ShareUsage)).await?;
+            return Ok(());
+        }
+    };
+    let answer = match storage.active_tree(chat_id) {
+        Some((tree_id, entry, _)) if entry.access == Access::Owner => {
+            let token = storage.share(tree_id, access, &entry.name, chat_id);
+            let link = format!("https://t.me/{}?start={}", state.bot_username, token);
+            tr(lang, Msg::ShareLink(&entry.name, access.can_edit(), &link))
+        }
+        Some((_, entry, _)) => tr(lang, Msg::OnlyOwnerShares(&entry.name)),
+        None => tr(lang, Msg::NoTree)
+    };
+    bot.send_message(chat_id, answer).await?;
+    Ok(())
+}
+
+async fn send_chart(bot: &AutoSend<Bot>, metrics: &Metrics, chat_id: i64, name: &str, dot_graph: String, node_count: usize, options: ChartOptions) -> Result<(), BotError> {
+    let started = Instant::now();
+    let graph = auxillary::print_graph(dot_graph, options.format)?;
+    metrics.chart_rendered(started.elapsed());
+    let chart = InputFile::Memory {
+        file_name: format!("{}.{}", name, options.format.extension()),
+        data: std::borrow::Cow::Owned(graph)
+    };
+    if options.sends_document(node_count) {
+        bot.send_document(chat_id, chart).await?;
+    } else {
+        bot.send_photo(chat_id, chart).await?;
+    }
+    Ok(())
+}
+
+/// Sends the export as a code block to copy or as a file when it's too long for a message
+async fn send_export(bot: &AutoSend<Bot>, chat_id: i64, name: &str, exported: String, format: ExportFormat, as_document: bool) -> Result<(), BotError> {
+    let block = format!("<pre><code class=\"language-{}\">{}</code></pre>", format.language(), escape_html(&exported));
+    if as_document || block.chars().count() > EXPORT_MESSAGE_LIMIT {
+        let file = InputFile::Memory {
+            file_name: format!("{}.{}", name, format.extension()),
+            data: std::borrow::Cow::Owned(exported.into_bytes())
+        };
+        bot.send_document(chat_id, file).await?;
+    } else {
+        bot.send_message(chat_id, block).parse_mode(ParseMode::Html).await?;
+    }
+    Ok(())
+}
+
+/// Tables are sent under their own names, so relatives know which files to send back for the import
+async fn send_tables(bot: &AutoSend<Bot>, chat_id: i64, (people, relationships): (String, String)) -> Result<(), BotError> {
+    for (table, text) in [(Table::People, people), (Table::Relationships, relationships)] {
+        let file = InputFile::Memory {
+            file_name: table.file_name().to_string(),
+            data: std::borrow::Cow::Owned(text.into_bytes())
+        };
+        bot.send_document(chat_id, file).await?;
+    }
+    Ok(())
+}
+
+async fn download(bot: &AutoSend<Bot>, file_id: &str) -> Result<Vec<u8>, BotError> {
+    let file = bot.get_file(file_id).await?;
+    let mut data = vec![];
+    bot.download_file(&file.file_path, &mut data).await?;
+    Ok(data)
+}
+
+async fn cache_photo(bot: &AutoSend<Bot>, file_id: &str, path: &Path) -> Result<(), BotError> {
+    let data = download(bot, file_id).await?;
+    path.parent().map(std::fs::create_dir_all).unwrap_or(Ok(()))
+        .and_then(|_| std::fs::write(path, data))
+        .map_err(|error| StorageError::PhotoCache(error).into())
+}
+
+/// Downloads photos which aren't cached yet. Photos that can't be downloaded are left out, the chart is still worth sending.
+async fn download_photos(bot: &AutoSend<Bot>, storage_path: &Path, file_ids: Vec<String>) -> PhotoFiles {
+    let mut photos = PhotoFiles::new();
+    for file_id in file_ids {
+        let path = match photo_path(storage_path, &file_id) {
+            Some(path) => path,
+            None => continue
+        };
+        if !path.exists() {
+            if let Err(error) = cache_photo(bot, &file_id, &path).await {
+                log::warn!("Leaving out photo {}: {}", file_id, error);
+                continue;
+            }
+        }
+        photos.insert(file_id, path);
+    }
+    photos
+}
+
+/// The only person called like that or the message explaining why there's none
+fn pick_person(updater: &GraphUpdater, lang: Lang, name: &str) -> Result<NodeIndex<u32>, String> {
+    match updater.find_people(name)[..] {
+        [person] => Ok(person),
+        [] => Err(tr(lang, Msg::PersonNotFound(name))),
+        ref people => {
+            let names: Vec<String> = people.iter().map(|person| updater.person_name(*person)).collect();
+            Err(tr(lang, Msg::PersonAmbiguous(&names)))
+        }
+    }
+}
+
+/// Chooses the person for the next photo. Without a name, or with the placeholder name, lists placeholders to name.
+async fn edit(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, name: &str) -> Result<(), BotError> {
+    let chat_id = asked.chat_id;
+    if name.trim().is_empty() || name.trim() == PLACEHOLDER_NAME {
+        let placeholders = match storage.active_tree(chat_id) {
+            Some((tree_id, _, tree)) => Some((tree_id, tree.lock().await.graph_updater.placeholders())).filter(|(_, placeholders)| !placeholders.is_empty()),
+            None => None
+        };
+        let output = match placeholders {
+            Some((tree_id, placeholders)) => map_placeholders_output(asked.lang, tree_id, &placeholders),
+            None => OutputCommand::Prompt(tr(asked.lang, Msg::EditUsage))
+        };
+        send_output(bot, chat_id, asked.reply_to, output).await?;
+        return Ok(());
+    }
+    let answer = match storage.active_tree(chat_id) {
+        Some((_, entry, _)) if !entry.access.can_edit() => tr(asked.lang, Msg::ViewOnly(&entry.name)),
+        Some((_, _, tree)) => {
+            let mut tree = tree.lock().await;
+            match pick_person(&tree.graph_updater, asked.lang, name) {
+                Ok(person) => {
+                    tree.graph_updater.edit(asked.user_id, person);
+                    tr(asked.lang, Msg::EditingPerson(&tree.graph_updater.person_name(person)))
+                }
+                Err(answer) => answer
+            }
+        }
+        None => tr(asked.lang, Msg::NoTree)
+    };
+    bot.send_message(chat_id, answer).await?;
+    Ok(())
+}
+
+/// Makes the next answer of the user the name of the placeholder
+async fn name_placeholder(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, expected: TreeId, person: u32) -> Result<(), BotError> {
+    let chat_id = asked.chat_id;
+    let answer = match storage.active_tree(chat_id) {
+        Some((tree_id, entry, tree)) if tree_id == expected => {
+            if !entry.access.can_edit() {
+                tr(asked.lang, Msg::ViewOnly(&entry.name))
+            } else {
+                let mut tree = tree.lock().await;
+                let person = NodeIndex::new(person as usize);
+                let placeholder = tree.graph_updater.placeholders().into_iter().find(|(ix, _)| *ix == person);
+                match placeholder {
+                    Some((_, placeholder)) => {
+                        tree.graph_updater.edit(asked.user_id, person);
+                        tr(asked.lang, Msg::NamePlaceholder(&placeholder))
+                    }
+                    None => tr(asked.lang, Msg::NothingPending)
+                }
+            }
+        }
+        _ => tr(asked.lang, Msg::NothingPending)
+    };
+    bot.send_message(chat_id, answer).await?;
+    Ok(())
+}
+
+async fn attach_photo(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, sizes: &[PhotoSize]) -> Result<(), BotError> {
+    let chat_id = asked.chat_id;
+    let thumbnail = match pick_thumbnail(sizes) {
+        Some(thumbnail) => thumbnail,
+        None => return Ok(())
+    };
+    let answer = match storage.active_tree(chat_id) {
+        Some((_, entry, _)) if !entry.access.can_edit() => tr(asked.lang, Msg::ViewOnly(&entry.name)),
+        Some((_, _, tree)) => match tree.lock().await.graph_updater.attach_photo(asked.user_id, &thumbnail.file_id) {
+            Some(person) => tr(asked.lang, Msg::PhotoAttached(&tr(asked.lang, Msg::PersonLabel(&person)))),
+            None => tr(asked.lang, Msg::NoPersonForPhoto)
+        },
+        None => tr(asked.lang, Msg::NoTree)
+    };
+    bot.send_message(chat_id, answer).await?;
+    Ok(())
+}
+
+/// Reminds that only names are understood. Groups are chatty, so there it's only for users in the middle of the interview.
+async fn ask_for_name(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, private: bool) -> Result<(), BotError> {
+    let interviewed = storage.user(asked.chat_id).active().map(|(_, entry)| entry.questions.contains_key(&asked.user_id)).unwrap_or(false);
+    if private || interviewed {
+        send_output(bot, asked.chat_id, asked.reply_to, OutputCommand::Prompt(tr(asked.lang, Msg::AnswerWithName))).await?;
+    }
+    Ok(())
+}
+
+/// Page with photos of the tree
+async fn send_html(bot: &AutoSend<Bot>, storage_path: &Path, chat_id: i64, lang: Lang, name: &str, tree: &Mutex<Tree>) -> Result<(), BotError> {
+    let photos = download_photos(bot, storage_path, tree.lock().await.graph_updater.photos()).await;
+    let images: HashMap<String, Vec<u8>> = photos.into_iter()
+        .filter_map(|(file_id, path)| Some((file_id, std::fs::read(path).ok()?)))
+        .collect();
+    let html = tree.lock().await.graph_updater.export_html(name, &images, lang);
+    let file = InputFile::Memory {
+        file_name: format!("{}.html", name),
+        data: std::borrow::Cow::Owned(html.into_bytes())
+    };
+    bot.send_document(chat_id, file).await?;
+    Ok(())
+}
+
+/// Shows what the typed statement would change in the tree and waits for the user to confirm it
+async fn propose(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, statement: Statement) -> Result<(), BotError> {
+    let chat_id = asked.chat_id;
+    let output = match storage.active_tree(chat_id) {
+        Some((_, entry, _)) if !entry.access.can_edit() => OutputCommand::Prompt(tr(asked.lang, Msg::ViewOnly(&entry.name))),
+        Some((tree_id, _, tree)) => match tree.lock().await.graph_updater.propose(asked.user_id, statement) {
+            Ok(proposal) => map_proposal_output(asked.lang, tree_id, &proposal),
+            Err(error) => OutputCommand::Prompt(map_statement_error(asked.lang, &error))
+        },
+        None => OutputCommand::Prompt(tr(asked.lang, Msg::NoTree))
+    };
+    send_output(bot, chat_id, asked.reply_to, output).await?;
+    Ok(())
+}
+
+/// Applies the statement the user has confirmed and goes on with the interview
+async fn apply_statement(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, author: &Author, expected: TreeId) -> Result<(), BotError> {
+    let chat_id = asked.chat_id;
+    let (tree_id, entry, tree) = match storage.active_tree(chat_id) {
+        Some(active) if active.0 == expected => active,
+        _ => {
+            bot.send_message(chat_id, tr(asked.lang, Msg::NothingPending)).await?;
+            return Ok(());
+        }
+    };
+    if !entry.access.can_edit() {
+        bot.send_message(chat_id, tr(asked.lang, Msg::ViewOnly(&entry.name))).await?;
+        return Ok(());
+    }
+
+    let (applied, revision, warnings, collaborators) = {
+        let mut tree = tree.lock().await;
+        let applied = tree.graph_updater.apply_statement(author);
+        (applied, tree.graph_updater.revision(), tree.graph_updater.take_new_warnings(), tree.collaborators(chat_id))
+    };
+    let proposal = match applied {
+        Ok(proposal) => proposal,
+        Err(error) => {
+            bot.send_message(chat_id, map_statement_error(asked.lang, &error)).await?;
+            return Ok(());
+        }
+    };
+
+    bot.send_message(chat_id, tr(asked.lang, Msg::StatementApplied)).await?;
+    for warning in warnings {
+        send_output(bot, chat_id, asked.reply_to, map_warning_output(asked.lang, tree_id, revision, &warning)).await?;
+    }
+    if !proposal.new_people.is_empty() {
+        notify(bot, storage, collaborators, |lang| tr(lang, Msg::PeopleAdded(&author.name, &proposal.new_people, &entry.name))).await;
+    }
+    let question = tree.lock().await.graph_updater.current_question(asked.user_id)?;
+    ask(bot, storage, asked, tree_id, map_next_action_output(asked.lang, &question)).await
+}
+
+/// Continues the interview the way the user chose after an error
+async fn recover(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, recovery: Recovery) -> Result<(), BotError> {
+    resume(bot, storage, asked, None, recovery == Recovery::Undo, |updater, user_id| match recovery {
+        Recovery::Continue => updater.current_question(user_id),
+        Recovery::Undo => updater.undo(user_id),
+        Recovery::PickAnother => updater.pick_another(user_id),
+    }).await
+}
+
+/// Takes back the answer listing several people so the user can write it again
+async fn edit_names(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, tree_id: TreeId, revision: u64) -> Result<(), BotError> {
+    resume(bot, storage, asked, Some(tree_id), true, |updater, user_id| updater.undo_answer(user_id, revision)).await
+}
+
+/// Moves the interview in the active tree, or only in the given one, and asks the question it has come to
+async fn resume<F>(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, expected: Option<TreeId>, undoing: bool, step: F) -> Result<(), BotError>
+where
+    F: FnOnce(&mut GraphUpdater, i64) -> Result<OutputAction, StateError>
+{
+    let chat_id = asked.chat_id;
+    let (tree_id, entry, tree) = match storage.active_tree(chat_id) {
+        Some(active) => active,
+        None => return Ok(())
+    };
+    if expected.is_some_and(|expected| expected != tree_id) {
+        return Err(StateError::NothingToUndo.into());
+    }
+    if !entry.access.can_edit() {
+        bot.send_message(chat_id, tr(asked.lang, Msg::ViewOnly(&entry.name))).await?;
+        return Ok(());
+    }
+
+    let (question, revision, warnings) = {
+        let mut tree = tree.lock().await;
+        let question = step(&mut tree.graph_updater, asked.user_id)?;
+        (question, tree.graph_updater.revision(), tree.graph_updater.take_new_warnings())
+    };
+
+    if undoing {
+        bot.send_message(chat_id, tr(asked.lang, Msg::Undone)).await?;
+    }
+    for warning in warnings {
+        send_output(bot, chat_id, asked.reply_to, map_warning_output(asked.lang, tree_id, revision, &warning)).await?;
+    }
+    ask(bot, storage, asked, tree_id, map_next_action_output(asked.lang, &question)).await
+}
+
+/// Keeps the uploaded table and replaces the active tree once both tables have arrived
+async fn import_table(bot: &AutoSend<Bot>, storage: &Storage, asked: &Asked, author: &Author, document: &Document) -> Result<(), BotError> {
+    let chat_id = asked.chat_id;
+    let (tree_id, entry, tree) = match storage.active_tree(chat_id) {
+        Some(active) => active,
+        None => {
+            bot.send_message(chat_id, tr(asked.lang, Msg::NoTree)).await?;
+            return Ok(());
+        }
+    };
+    if !entry.access.can_edit() {
+        bot.send_message(chat_id, tr(asked.lang, Msg::ViewOnly(&entry.name))).await?;
+        return Ok(());
+    }
+    if document.file_size.unwrap_or_default() > IMPORT_SIZE_LIMIT {
+        bot.send_message(chat_id, tr(asked.lang, Msg::TableTooBig)).await?;
+        return Ok(());
+    }
+    let table = String::from_utf8(download(bot, &document.file_id).await?).ok()
+        .and_then(|text| Some((Table::detect(&text)?, text)));
+    let (people, relationships) = match table {
+        Some((table, text)) => match storage.add_import_table(chat_id, table, text) {
+            Some(tables) => tables,
+            None => {
+                let missing = if table == Table::People { Table::Relationships } else { Table::People };
+                bot.send_message(chat_id, tr(asked.lang, Msg::TableWaiting(missing.file_name()))).await?;
+                return Ok(());
+            }
+        },
+        None => {
+            bot.send_message(chat_id, tr(asked.lang, Msg::UnknownTable)).await?;
+            return Ok(());
+        }
+    };
+
+    let imported = {
+        let mut tree = tree.lock().await;
+        let updater = &mut tree.graph_updater;
+        updater.import_tables(&people, &relationships, author)
+            .map(|count| (count, updater.revision(), updater.take_new_warnings(), updater.current_question(asked.user_id)))
+            .map(|(count, revision, warnings, question)| (count, revision, warnings, question, tree.collaborators(chat_id)))
+    };
+    match imported {
+        Ok((count, revision, warnings, question, collaborators)) => {
+            bot.send_message(chat_id, tr(asked.lang, Msg::Imported(count, &entry.name))).await?;
+            for warning in warnings {
+                send_output(bot, chat_id, asked.reply_to, map_warning_output(asked.lang, tree_id, revision, &warning)).await?;
+            }
+            ask(bot, storage, asked, tree_id, map_next_action_output(asked.lang, &question?)).await?;
+            notify(bot, storage, collaborators, |lang| tr(lang, Msg::TreeImportedBy(&author.name, &entry.name))).await;
+        }
+        Err(errors) => {
+            let shown = &errors[..errors.len().min(IMPORT_ERRORS_SHOWN)];
+            bot.send_message(chat_id, tr(asked.lang, Msg::ImportFailed(shown, errors.len()))).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn change_language(bot: &AutoSend<Bot>, storage: &Storage, chat_id: i64, user_id: i64, lang: Lang, language: &str) -> Result<(), BotError> {
+    let output = match Lang::parse(language) {
+        Some(chosen) => {
+            storage.set_language(user_id, chosen);
+            OutputCommand::Prompt(tr(chosen, Msg::LanguageChanged))
+        }
+        None if language.trim().is_empty() => map_languages_output(lang, Msg::ChooseLanguage),
+        None => map_languages_output(lang, Msg::UnknownLanguage(language.trim()))
+    };
+    send_output(bot, chat_id, None, output).await?;
+    Ok(())
+}
+
+/// Logs the failure for the operator and tells the user what went wrong in their language
+async fn report(bot: &AutoSend<Bot>, chat_id: i64, user_id: i64, lang: Lang, error: BotError) {
+    match error {
+        BotError::State(_) => log::warn!("Update of user {} in chat {} not handled: {}", user_id, chat_id, error),
+        _ => log::error!("Update of user {} in chat {} not handled: {}", user_id, chat_id, error),
+    }
+    if let Err(error) = send_output(bot, chat_id, None, map_error(lang, &error)).await {
+        log::error!("Cannot tell chat {} about the error: {}", chat_id, error);
+    }
+}
+
+pub async fn handle_message(cx: UpdateWithCx<AutoSend<Bot>, Message>, state: Arc<AppState>) {
+    let chat_id = cx.chat_id();
+    let storage = &state.storage;
+    let (author, lang) = match cx.update.from() {
+        Some(user) => (author_of(user), storage.language(user.id, user.language_code.as_deref())),
+        None => {
+            let lang = storage.language(chat_id, None);
+            (Author::new(chat_id, tr(lang, Msg::Someone)), lang)
+        }
+    };
+    if let Err(error) = process_message(&cx, &state, &author, lang).await {
+        state.metrics.error(&error);
+        report(&cx.requester, chat_id, author.user_id, lang, error).await;
+    }
+}
+
+async fn process_message(cx: &UpdateWithCx<AutoSend<Bot>, Message>, state: &AppState, author: &Author, lang: Lang) -> Result<(), BotError> {
+    let chat_id = cx.chat_id();
+    let storage = &state.storage;
+    let asked = Asked::new(&cx.update.chat, author.user_id, cx.update.id, lang);
+    let bot = &cx.requester;
+
+    let text = match classify(&cx.update) {
+        Incoming::Text(text) => text.to_string(),
+        Incoming::Document(document) => return import_table(bot, storage, &asked, author, document).await,
+        Incoming::Photo(sizes) => return attach_photo(bot, storage, &asked, sizes).await,
+        Incoming::Contact(name) => return answer(bot, storage, &asked, author, InputAction::Text(&name)).await,
+        Incoming::Other => return ask_for_name(bot, storage, &asked, cx.update.chat.is_private()).await,
+    };
+
+    let command = Command::parse(&text, state.bot_username.as_str());
+    if let Ok(command) = &command {
+        state.metrics.command_handled(command.name());
+    }
+    match command {
+        Ok(Command::Help) => {
+            cx.answer(tr(lang, Msg::Help)).await?;
+        }
+        Ok(Command::Start(token)) => {
+            start(bot, storage, &asked, author, token.trim()).await?;
+        }
+        Ok(Command::Finish(options)) => {
+            let options = match ChartOptions::parse(&options) {
+                Some(options) => options,
+                None => {
+                    cx.answer(tr(lang, Msg::ChartUsage)).await?;
+                    return Ok(());
+                }
+            };
+            if let Some((_, entry, tree)) = storage.active_tree(chat_id) {
+                let photo_ids = if options.format.embeds_photos() { tree.lock().await.graph_updater.photos() } else { vec![] };
+                let photos = download_photos(bot, &state.storage_path, photo_ids).await;
+                let (dot_graph, node_count) = {
+                    let tree = tree.lock().await;
+                    (tree.graph_updater.print_dot(storage.theme(chat_id), &photos, lang), tree.graph_updater.node_count())
+                };
+                send_chart(bot, &state.metrics, chat_id, &entry.name, dot_graph, node_count, options).await?;
+            }
+        }
+        Ok(Command::Chart(text)) => {
+            let (name, focus) = match parse_focus(&text) {
+                Some(parsed) => parsed,
+                None => {
+                    cx.answer(tr(lang, Msg::FocusUsage)).await?;
+                    return Ok(());
+                }
+            };
+            let (entry, tree) = match storage.active_tree(chat_id) {
+                Some((_, entry, tree)) => (entry, tree),
+                None => {
+                    cx.answer(tr(lang, Msg::NoTree)).await?;
+                    return Ok(());
+                }
+            };
+            let photos = download_photos(bot, &state.storage_path, tree.lock().await.graph_updater.photos()).await;
+            let chart = {
+                let tree = tree.lock().await;
+                let updater = &tree.graph_updater;
+                pick_person(updater, lang, &name).map(|person| updater.print_focus_dot(person, &focus, storage.theme(chat_id), &photos, lang))
+            };
+            match chart {
+                Ok((dot_graph, node_count)) => send_chart(bot, &state.metrics, chat_id, &entry.name, dot_graph, node_count, ChartOptions::default()).await?,
+                Err(answer) => {
+                    cx.answer(answer).await?;
+                }
+            }
+        }
+        Ok(Command::Export(text)) => {
+            let request = match parse_export(&text) {
+                Some(request) => request,
+                None => {
+                    cx.answer(tr(lang, Msg::ExportUsage)).await?;
+                    return Ok(());
+                }
+            };
+            match (storage.active_tree(chat_id), request) {
+                (Some((_, entry, tree)), ExportRequest::Text(format, as_document)) => {
+                    let exported = tree.lock().await.graph_updater.export(format, lang);
+                    send_export(bot, chat_id, &entry.name, exported, format, as_document).await?;
+                }
+                (Some((_, _, tree)), ExportRequest::Tables) => {
+                    let tables = tree.lock().await.graph_updater.export_tables();
+                    send_tables(bot, chat_id, tables).await?;
+                }
+                (Some((_, entry, tree)), ExportRequest::Html) => {
+                    send_html(bot, &state.storage_path, chat_id, lang, &entry.name, &tree).await?;
+                }
+                (None, _) => {
+                    cx.answer(tr(lang, Msg::NoTree)).await?;
+                }
+            }
+        }
+        Ok(Command::Edit(name)) => {
+            edit(bot, storage, &asked, &name).await?;
+        }
+        Ok(Command::New(name)) => {
+            let answer = match storage.create_tree(chat_id, &name) {
+                Ok(entry) => tr(lang, Msg::TreeCreated(&entry.name)),
+                Err(error) => map_tree_index_error(lang, &error)
+            };
+            cx.answer(answer).await?;
+        }
+        Ok(Command::Trees) => {
+            let output = map_trees_output(lang, &storage.user(chat_id));
+            send_output(bot, chat_id, None, output).await?;
+        }
+        Ok(Command::Switch(name)) => {
+            if name.trim().is_empty() {
+                let output = map_trees_output(lang, &storage.user(chat_id));
+                send_output(bot, chat_id, None, output).await?;
+                return Ok(());
+            }
+            let tree_id = storage.user(chat_id).find(&name);
+            match tree_id {
+                Some(tree_id) => switch(bot, storage, &asked, tree_id).await?,
+                None => {
+                    cx.answer(map_tree_index_error(lang, &TreeIndexError::NotFound(name.trim().to_string()))).await?;
+                }
+            }
+        }
+        Ok(Command::RenameTree(name)) => {
+            let answer = match storage.user(chat_id).rename_active(&name) {
+                Ok(entry) => tr(lang, Msg::TreeRenamed(&entry.name)),
+                Err(TreeIndexError::NotFound(_)) => tr(lang, Msg::NoTree),
+                Err(error) => map_tree_index_error(lang, &error)
+            };
+            cx.answer(answer).await?;
+        }
+        Ok(Command::DeleteTree(name)) => {
+            delete(bot, storage, chat_id, lang, author, &name).await?;
+        }
+        Ok(Command::Share(access)) => {
+            share(bot, state, chat_id, lang, &access).await?;
+        }
+        Ok(Command::Language(language)) => {
+            change_language(bot, storage, chat_id, author.user_id, lang, &language).await?;
+        }
+        Ok(Command::Theme(theme)) => {
+            let output = match Theme::parse(&theme) {
+                Some(theme) => {
+                    storage.set_theme(chat_id, theme);
+                    OutputCommand::Prompt(tr(lang, Msg::ThemeChanged(theme.name())))
+                }
+                None if theme.trim().is_empty() => map_themes_output(lang, Msg::ChooseTheme),
+                None => map_themes_output(lang, Msg::UnknownTheme(theme.trim()))
+            };
+            send_output(bot, chat_id, None, output).await?;
+        }
+        Ok(Command::Retention(retention)) => {
+            let output = match Retention::parse(&retention) {
+                Some(retention) => {
+                    storage.set_retention(chat_id, retention);
+                    OutputCommand::Prompt(tr(lang, Msg::RetentionChanged(retention)))
+                }
+                None if retention.trim().is_empty() => map_retentions_output(lang, Msg::ChooseRetention),
+                None => map_retentions_output(lang, Msg::UnknownRetention(retention.trim()))
+            };
+            send_output(bot, chat_id, None, output).await?;
+        }
+        _ => match parse_statement(&text) {
+            Some(statement) => propose(bot, storage, &asked, statement).await?,
+            None => answer(bot, storage, &asked, author, InputAction::Text(&text)).await?
+        },
+    }
+    Ok(())
+}
+
+pub async fn handle_query(cx: UpdateWithCx<AutoSend<Bot>, CallbackQuery>, state: Arc<AppState>) {
+    let UpdateWithCx { requester: bot, update: query } = cx;
+    let author = author_of(&query.from);
+    let lang = state.storage.language(author.user_id, query.from.language_code.as_deref());
+    let chat_id = query.message.as_ref().map(|message| message.chat.id);
+
+    if let Err(error) = process_query(&bot, &state, query, &author, lang).await {
+        state.metrics.error(&error);
+        match chat_id {
+            Some(chat_id) => report(&bot, chat_id, author.user_id, lang, error).await,
+            None => log::error!("Button of user {} not handled: {}", author.user_id, error)
+        }
+    }
+}
+
+async fn process_query(bot: &AutoSend<Bot>, state: &AppState, query: CallbackQuery, author: &Author, lang: Lang) -> Result<(), BotError> {
+    let storage = &state.storage;
+
+    if let (Some(data), Some(Message { chat, id, .. })) = (query.data, query.message) {
+        let asked = Asked::new(&chat, author.user_id, id, lang);
+
+        match ButtonCommand::from_str(&data) {
+            Ok(button @ (ButtonCommand::No | ButtonCommand::Yes | ButtonCommand::Back | ButtonCommand::Skip | ButtonCommand::Stop | ButtonCommand::Unknown)) => {
+                let input = match button {
+                    ButtonCommand::Yes => InputAction::Yes,
+                    ButtonCommand::Unknown => InputAction::Unknown,
+                    ButtonCommand::Back => InputAction::Back,
+                    ButtonCommand::Skip => InputAction::Skip,
+                    ButtonCommand::Stop => InputAction::Stop,
+                    _ => InputAction::No,
+                };
+                let question = storage.user(chat.id).active().and_then(|(_, entry)| entry.questions.get(&author.user_id).copied());
+                if question == Some(id) {
+                    bot.edit_message_reply_markup(chat.id, id).await?;
+                    answer(bot, storage, &asked, author, input).await?;
+                }
+                else if chat.is_private() {
+                    //user clicked on button of obsolete message, the answer belongs to another question
+                    bot.edit_message_reply_markup(chat.id, id).await?;
+                }
+                else {
+                    bot.answer_callback_query(query.id).text(tr(lang, Msg::NotYourQuestion)).await?;
+                }
+            }
+            Ok(ButtonCommand::Unlink(tree_id, revision, parent, child)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                unlink(bot, storage, &asked, tree_id, revision, parent, child).await?;
+            }
+            Ok(ButtonCommand::Ignore) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+            }
+            Ok(ButtonCommand::SwitchTree(tree_id)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                switch(bot, storage, &asked, tree_id).await?;
+            }
+            Ok(ButtonCommand::Theme(theme)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                storage.set_theme(chat.id, theme);
+                bot.send_message(chat.id, tr(lang, Msg::ThemeChanged(theme.name()))).await?;
+            }
+            Ok(ButtonCommand::Retention(retention)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                storage.set_retention(chat.id, retention);
+                bot.send_message(chat.id, tr(lang, Msg::RetentionChanged(retention))).await?;
+            }
+            Ok(ButtonCommand::Language(chosen)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                storage.set_language(author.user_id, chosen);
+                bot.send_message(chat.id, tr(chosen, Msg::LanguageChanged)).await?;
+            }
+            Ok(ButtonCommand::Recover(recovery)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                recover(bot, storage, &asked, recovery).await?;
+            }
+            Ok(ButtonCommand::EditNames(tree_id, revision)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                edit_names(bot, storage, &asked, tree_id, revision).await?;
+            }
+            Ok(ButtonCommand::Edit(tree_id, person)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                name_placeholder(bot, storage, &asked, tree_id, person).await?;
+            }
+            Ok(ButtonCommand::ApplyStatement(tree_id)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                apply_statement(bot, storage, &asked, author, tree_id).await?;
+            }
+            Ok(ButtonCommand::Keep(tree_id)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                let answer = match storage.member_tree(chat.id, tree_id) {
+                    Some((entry, _)) => tr(lang, Msg::TreeKept(&entry.name)),
+                    None => tr(lang, Msg::TreeGone)
+                };
+                bot.send_message(chat.id, answer).await?;
+            }
+            Ok(ButtonCommand::ExportNow(tree_id)) => {
+                bot.edit_message_reply_markup(chat.id, id).await?;
+                match storage.member_tree(chat.id, tree_id) {
+                    Some((_, tree)) => {
+                        let tables = tree.lock().await.graph_updater.export_tables();
+                        send_tables(bot, chat.id, tables).await?;
+                    }
+                    None => {
+                        bot.send_message(chat.id, tr(lang, Msg::TreeGone)).await?;
+                    }
+                }
+            }
+            Err(_) => {
+                bot.send_message(chat.id, tr(lang, Msg::UnknownButton)).await?;
+            }
+        }
+    }
+    Ok(())
+}
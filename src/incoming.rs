@@ -0,0 +1,109 @@
+use teloxide::types::{Contact, Document, Message, PhotoSize};
+
+/// What the user sent, so every kind of message gets its own handling instead of being read as text
+#[derive(Debug, PartialEq)]
+pub enum Incoming<'a> {
+    /// Command or answer to the question
+    Text(&'a str),
+    /// Table to import, a `.csv` file
+    Document(&'a Document),
+    /// Picture of a person, sizes from the smallest
+    Photo(&'a [PhotoSize]),
+    /// Shared contact, its name answers the question
+    Contact(String),
+    /// Stickers, voice messages, locations, other files and everything else the bot can't use
+    Other,
+}
+
+/// Tables are the only files the bot takes, other documents shared in a group aren't meant for it
+fn is_table(document: &Document) -> bool {
+    let csv_name = document.file_name.as_deref().is_some_and(|name| name.to_lowercase().ends_with(".csv"));
+    let csv_type = document.mime_type.as_ref().is_some_and(|mime| mime.essence_str() == "text/csv");
+    csv_name || csv_type
+}
+
+fn contact_name(contact: &Contact) -> String {
+    match &contact.last_name {
+        Some(last_name) => format!("{} {}", contact.first_name, last_name),
+        None => contact.first_name.clone()
+    }
+}
+
+pub fn classify(message: &Message) -> Incoming<'_> {
+    if let Some(text) = message.text() {
+        Incoming::Text(text)
+    } else if let Some(document) = message.document().filter(|document| is_table(document)) {
+        Incoming::Document(document)
+    } else if let Some(sizes) = message.photo() {
+        Incoming::Photo(sizes)
+    } else if let Some(contact) = message.contact() {
+        Incoming::Contact(contact_name(contact))
+    } else {
+        Incoming::Other
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Message of a private chat with the content fields. Documents and voice messages
+    /// of this Bot API version can't be read without `mime_type`.
+    fn message(content: Value) -> Message {
+        let mut message = json!({
+            "message_id": 1,
+            "date": 1600000000,
+            "from": { "id": 10, "is_bot": false, "first_name": "Anna", "language_code": "en" },
+            "chat": { "id": 10, "first_name": "Anna", "type": "private" }
+        });
+        for (key, value) in content.as_object().expect("content is an object") {
+            message[key] = value.clone();
+        }
+        serde_json::from_value(message).expect("message is valid")
+    }
+
+    #[test]
+    fn text_and_commands() {
+        assert_eq!(classify(&message(json!({ "text": "Robert Smith" }))), Incoming::Text("Robert Smith"));
+        assert_eq!(classify(&message(json!({ "text": "/finish" }))), Incoming::Text("/finish"));
+    }
+
+    #[test]
+    fn files_and_photos() {
+        let document = message(json!({ "document": { "file_id": "people", "file_unique_id": "p", "file_name": "people.csv", "mime_type": "text/csv" } }));
+        assert!(matches!(classify(&document), Incoming::Document(document) if document.file_id == "people"));
+        let excel = message(json!({ "document": { "file_id": "excel", "file_unique_id": "e", "file_name": "People.CSV", "mime_type": "application/vnd.ms-excel" } }));
+        assert!(matches!(classify(&excel), Incoming::Document(_)), "Should take tables saved by Excel");
+        let pdf = message(json!({ "document": { "file_id": "pdf", "file_unique_id": "d", "file_name": "invitation.pdf", "mime_type": "application/pdf" } }));
+        assert_eq!(classify(&pdf), Incoming::Other, "Should not import other files");
+
+        let photo = message(json!({
+            "photo": [
+                { "file_id": "small", "file_unique_id": "s", "width": 90, "height": 90 },
+                { "file_id": "big", "file_unique_id": "b", "width": 800, "height": 800 }
+            ],
+            "caption": "Granny"
+        }));
+        assert!(matches!(classify(&photo), Incoming::Photo(sizes) if sizes.len() == 2));
+    }
+
+    #[test]
+    fn contact_gives_name() {
+        let contact = message(json!({ "contact": { "phone_number": "+100", "first_name": "Clara", "last_name": "Brown" } }));
+        assert_eq!(classify(&contact), Incoming::Contact("Clara Brown".to_string()));
+        let contact = message(json!({ "contact": { "phone_number": "+100", "first_name": "Clara" } }));
+        assert_eq!(classify(&contact), Incoming::Contact("Clara".to_string()));
+    }
+
+    #[test]
+    fn everything_else() {
+        let sticker = message(json!({ "sticker": { "file_id": "sticker", "file_unique_id": "st", "width": 512, "height": 512, "is_animated": false } }));
+        let voice = message(json!({ "voice": { "file_id": "voice", "file_unique_id": "v", "duration": 3, "mime_type": "audio/ogg" } }));
+        let location = message(json!({ "location": { "longitude": 13.4, "latitude": 52.5 } }));
+        for message in [sticker, voice, location] {
+            assert_eq!(classify(&message), Incoming::Other);
+        }
+    }
+}
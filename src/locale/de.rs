@@ -0,0 +1,291 @@
+use super::{capitalized, Msg};
+use crate::session::Retention;
+use crate::updater::model::{Called, Sex, Subject};
+use crate::updater::statement::{Kinship, Proposal};
+use crate::updater::name::PersonName;
+use crate::updater::table::{RowError, RowProblem};
+use crate::updater::validation::{Issue, MAX_PARENTS};
+
+/// German keeps names in the nominative by putting them after "von"
+fn name(name: &PersonName) -> String {
+    let mut text = name.given.clone();
+    for nickname in &name.nicknames {
+        text.push_str(&format!(" \"{}\"", nickname));
+    }
+    if let Some(surname) = &name.surname {
+        text.push_str(&format!(" {}", surname));
+    }
+    if let Some(birth_surname) = &name.birth_surname {
+        text.push_str(&format!(" (geb. {})", birth_surname));
+    }
+    text
+}
+
+/// Placeholders are called like names, in the nominative: "unbekannter Vater von Robert"
+fn called(called: &Called) -> String {
+    match called {
+        Called::Name(person) => name(person),
+        Called::Unknown(sex, children) => {
+            let unknown = match sex {
+                Some(Sex::Female) => "unbekannte Mutter",
+                Some(Sex::Male) => "unbekannter Vater",
+                None => "unbekannter Elternteil",
+            };
+            format!("{} von {}", unknown, names(children))
+        }
+    }
+}
+
+fn names(names: &[Called]) -> String {
+    names.iter().map(called).collect::<Vec<String>>().join(", ")
+}
+
+fn describe(subject: &Subject) -> String {
+    let mut relations = vec![];
+    if !subject.children.is_empty() {
+        relations.push(format!("Elternteil von {}", names(&subject.children)));
+    }
+    if !subject.parents.is_empty() {
+        relations.push(format!("Kind von {}", names(&subject.parents)));
+    }
+    if relations.is_empty() {
+        called(&subject.name)
+    } else {
+        format!("{} ({})", called(&subject.name), relations.join("; "))
+    }
+}
+
+fn issue(issue: &Issue) -> String {
+    match issue {
+        Issue::Cycle(people) => format!("{} sind jetzt ihre eigenen Vorfahren.", people.join(", ")),
+        Issue::TooManyParents(child, count) => format!("{} hat jetzt {} Eltern, aber ein Mensch kann nicht mehr als {} haben.", child, count, MAX_PARENTS),
+        Issue::ChildBornBeforeParent(parent, child) => format!("{} wurde vor dem Elternteil {} geboren.", child, parent),
+        Issue::ParentTooYoung(parent, child, age) => format!("{} war erst {}, als {} geboren wurde.", parent, age, child),
+    }
+}
+
+fn role(kinship: Kinship, sex: Option<Sex>) -> &'static str {
+    match (kinship, sex) {
+        (Kinship::Parent, Some(Sex::Female)) => "die Mutter",
+        (Kinship::Parent, Some(Sex::Male)) => "der Vater",
+        (Kinship::Parent, None) => "ein Elternteil",
+        (Kinship::Child, Some(Sex::Female)) => "die Tochter",
+        (Kinship::Child, Some(Sex::Male)) => "der Sohn",
+        (Kinship::Child, None) => "ein Kind",
+        (Kinship::Grandparent, Some(Sex::Female)) => "die Großmutter",
+        (Kinship::Grandparent, Some(Sex::Male)) => "der Großvater",
+        (Kinship::Grandparent, None) => "ein Großelternteil",
+        (Kinship::Grandchild, Some(Sex::Female)) => "die Enkelin",
+        (Kinship::Grandchild, Some(Sex::Male)) => "der Enkel",
+        (Kinship::Grandchild, None) => "ein Enkelkind",
+        (Kinship::Sibling, Some(Sex::Female)) => "die Schwester",
+        (Kinship::Sibling, Some(Sex::Male)) => "der Bruder",
+        (Kinship::Sibling, None) => "ein Geschwisterteil",
+        (Kinship::Spouse, Some(Sex::Female)) => "die Ehefrau",
+        (Kinship::Spouse, Some(Sex::Male)) => "der Ehemann",
+        (Kinship::Spouse, None) => "verheiratet mit",
+    }
+}
+
+fn proposal(proposal: &Proposal) -> String {
+    let (person, relative) = (name(&proposal.person), name(&proposal.relative));
+    let mut text = match (proposal.kinship, proposal.year) {
+        (Kinship::Spouse, Some(year)) => format!("{} und {} haben {} geheiratet.", person, relative, year),
+        (Kinship::Spouse, None) => format!("{} und {} sind verheiratet.", person, relative),
+        (kinship, _) => format!("{} ist {} von {}.", person, role(kinship, proposal.sex), relative)
+    };
+    if !proposal.new_people.is_empty() {
+        text.push_str(&format!(" Neu im Stammbaum: {}.", proposal.new_people.join(", ")));
+    }
+    if proposal.through_unknown {
+        text.push_str(" Sie sind über einen Elternteil verbunden, dessen Name unbekannt ist.");
+    }
+    text
+}
+
+fn row_error(error: &RowError) -> String {
+    let problem = match &error.problem {
+        RowProblem::UnclosedQuote => "ein Anführungszeichen ist nicht geschlossen".to_string(),
+        RowProblem::MissingColumn(column) => format!("die Spalte „{}“ fehlt", column),
+        RowProblem::MissingId => "die id ist leer".to_string(),
+        RowProblem::DuplicateId(id) => format!("die id „{}“ kommt doppelt vor", id),
+        RowProblem::MissingName => "der Vorname ist leer".to_string(),
+        RowProblem::BadYear(year) => format!("„{}“ ist keine Jahreszahl", year),
+        RowProblem::BadSex(sex) => format!("das Geschlecht „{}“ muss F oder M sein", sex),
+        RowProblem::BadPlaceholder(mark) => format!("in der Spalte placeholder muss yes oder nichts stehen, nicht „{}“", mark),
+        RowProblem::UnknownPerson(id) => format!("in people.csv gibt es niemanden mit der id „{}“", id),
+        RowProblem::UnknownRelation(relation) => format!("der Typ „{}“ muss parent oder child sein", relation),
+        RowProblem::SelfLink => "ein Mensch kann nicht sein eigener Elternteil sein".to_string(),
+    };
+    format!("{}, Zeile {}: {}", error.table.file_name(), error.row, problem)
+}
+
+fn import_failed(errors: &[RowError], total: usize) -> String {
+    let mut text = String::from("Nichts wurde importiert. Bitte korrigiere diese Zeilen und schicke beide Dateien noch einmal:");
+    for error in errors {
+        text.push_str(&format!("\n{}", row_error(error)));
+    }
+    if total > errors.len() {
+        text.push_str(&format!("\n…und {} weitere", total - errors.len()));
+    }
+    text
+}
+
+fn people(count: usize) -> String {
+    format!("{} {}", count, if count == 1 { "Person" } else { "Personen" })
+}
+
+fn hours(count: u64) -> String {
+    format!("{} {}", count, if count == 1 { "Stunde" } else { "Stunden" })
+}
+
+fn rights(can_edit: bool) -> &'static str {
+    if can_edit { "ansehen und bearbeiten" } else { "ansehen" }
+}
+
+pub fn text(msg: Msg) -> String {
+    match msg {
+        Msg::Help => "Folgende Befehle werden unterstützt:
+/help — Alle Befehle anzeigen
+/start — Stammbaum beginnen oder neu beginnen
+/finish [svg|pdf] [document] — Stammbaum als Bild ausgeben
+/chart <Name> [hoch] [runter] [siblings] — Verwandte einer Person ausgeben
+/export mermaid|plantuml|csv|html [document] — Stammbaum als Text, Tabellen oder Seite exportieren
+/edit <Name> — Person wählen, um ein Foto zu schicken
+/new <Name> — Neuen Stammbaum anlegen
+/trees — Deine Stammbäume anzeigen
+/switch <Name> — Zu einem anderen Stammbaum wechseln
+/rename_tree <neuer Name> — Aktuellen Stammbaum umbenennen
+/delete_tree <Name> — Stammbaum löschen
+/share view oder /share edit — Verwandte zum aktuellen Stammbaum einladen
+/theme — Stil des Diagramms ändern
+/retention — Wählen, wie lange unbenutzte Stammbäume bleiben
+/language — Sprache ändern".to_string(),
+        Msg::AskRoot => "Bitte füge eine Person aus deiner Familie hinzu oder schreibe deinen Namen".to_string(),
+        Msg::AskFirstParent(subject) => format!("Schreibe den Namen des ersten Elternteils von {}. Wenn du den Namen nicht kennst, drücke den Knopf.", describe(subject)),
+        Msg::AskSecondParent(subject) => format!("Schreibe den Namen des zweiten Elternteils von {}. Wenn du den Namen nicht kennst, drücke den Knopf.", describe(subject)),
+        Msg::AskIfSiblings(subject) => format!("Hat {} vielleicht Geschwister? Schreibe den Namen des ersten, den du kennst, oder drücke den Knopf.", describe(subject)),
+        Msg::AskIfMoreSiblings(subject) => format!("Nenne mir ein weiteres Geschwister von {} oder drücke den Knopf.", describe(subject)),
+        Msg::AskIfChildren(subject) => format!("Hat {} Kinder? Wenn ja, schreibe den Namen. Wenn nicht oder du es nicht weißt, drücke den Knopf.", describe(subject)),
+        Msg::AskIfMoreChildren(subject) => format!("Hat {} vielleicht noch weitere Kinder? Wenn nicht, drücke den Knopf. Wenn du jemanden kennst, schreibe den Namen.", describe(subject)),
+        Msg::ConfirmSurname(child) => format!("Ich vermute, es ist {}. Stimmt der Nachname? Drücke den Knopf zur Bestätigung oder schreibe den richtigen Nachnamen.", name(child)),
+        Msg::ChartUsage => "Schreibe /finish für ein Bild, /finish svg oder /finish pdf für ein zoombares Diagramm. Mit document kommt das Bild unkomprimiert an.".to_string(),
+        Msg::FocusUsage => "Schreibe /chart und den Namen der Person, z. B. /chart Anna 2 1. Die Zahlen sind Generationen nach oben und unten, mit siblings werden auch Geschwister gezeigt.".to_string(),
+        Msg::PersonNotFound(name) => format!("Im Stammbaum gibt es niemanden namens „{}“.", name),
+        Msg::PersonAmbiguous(names) => format!("Mehrere Personen passen: {}. Bitte schreibe den vollen Namen.", names.join(", ")),
+        Msg::ExportUsage => "Schreibe /export mermaid oder /export plantuml, um den Stammbaum als Text für ein Wiki zu bekommen. Mit document kommt eine Datei. Mit /export csv bekommst du Tabellen für eine Tabellenkalkulation, schicke beide zurück, um den Stammbaum zu ersetzen. Mit /export html bekommst du eine Seite mit Fotos.".to_string(),
+        Msg::TableWaiting(file) => format!("Erhalten! Schicke jetzt {}, um den Import abzuschließen.", file),
+        Msg::UnknownTable => "Diese Datei kann ich nicht lesen. Schicke people.csv und relationships.csv, die du mit /export csv bekommen und als CSV UTF-8 gespeichert hast.".to_string(),
+        Msg::TableTooBig => "Diese Datei ist zu groß für den Import.".to_string(),
+        Msg::ImportFailed(errors, total) => import_failed(errors, total),
+        Msg::Imported(count, name) => format!("{} in „{}“ importiert.", people(count), name),
+        Msg::TreeImportedBy(author, name) => format!("{} hat den geteilten Stammbaum „{}“ durch einen importierten ersetzt.", author, name),
+        Msg::EditUsage => "Schreibe /edit und den Namen der Person, z. B. /edit Anna Schmidt.".to_string(),
+        Msg::EditingPerson(name) => format!("Schicke ein Foto von {}.", name),
+        Msg::PhotoAttached(name) => format!("Das Foto von {} ist gespeichert. Schreibe /finish, um es im Diagramm zu sehen.", name),
+        Msg::AnswerWithName => "Damit kann ich leider nichts anfangen. Bitte antworte mit einem Namen oder drücke einen Knopf.".to_string(),
+        Msg::NoPersonForPhoto => "Wessen Foto ist das? Schreibe /edit und den Namen der Person und schicke das Foto noch einmal.".to_string(),
+        Msg::NotifyError => "Ein Fehler ist aufgetreten :( Bitte starte den Bot neu!".to_string(),
+        Msg::TreeFinished => "Alle im Stammbaum sind beschrieben, es gibt nichts mehr zu beantworten. Schreibe /finish für das Diagramm oder nimm deine letzte Antwort zurück, falls sie ein Versehen war.".to_string(),
+        Msg::InvalidAnswer => "Mit dieser Antwort kann ich nichts anfangen. Bitte schreibe einen Namen (oder beide Eltern auf einmal) oder drücke einen Knopf.".to_string(),
+        Msg::PersonGone => "Die Person, nach der ich gefragt habe, ist nicht mehr im Stammbaum, jemand hat ihr Hinzufügen rückgängig gemacht.".to_string(),
+        Msg::InterviewConfused => "Entschuldige, ich weiß nicht mehr, nach wem ich dich fragen soll.".to_string(),
+        Msg::NothingToUndo => "Es gibt nichts rückgängig zu machen: Du hast noch nicht geantwortet oder ein Verwandter hat den Stammbaum seitdem geändert.".to_string(),
+        Msg::Undone => "Deine letzte Antwort ist rückgängig gemacht.".to_string(),
+        Msg::ContinueInterview => "Weiter".to_string(),
+        Msg::UndoAnswer => "Letzte Antwort zurücknehmen".to_string(),
+        Msg::PickAnotherPerson => "Nach jemand anderem fragen".to_string(),
+        Msg::ChartFailed => "Das Diagramm konnte leider nicht gezeichnet werden. Versuche es später noch einmal oder hole den Stammbaum als Text mit /export.".to_string(),
+        Msg::TelegramFailed => "Telegram hat meine Antwort nicht angenommen. Bitte versuche es in einer Minute noch einmal.".to_string(),
+        Msg::DownloadFailed => "Ich konnte die Datei nicht von Telegram herunterladen. Bitte schicke sie noch einmal.".to_string(),
+        Msg::NotifyComplete => "Wir haben genug gefragt! Deinen Stammbaum bekommst du mit dem Befehl /finish".to_string(),
+        Msg::DontKnow => "Weiß nicht".to_string(),
+        Msg::GoBack => "« Zurück".to_string(),
+        Msg::SkipPerson => "Person überspringen".to_string(),
+        Msg::StopHere => "Hier aufhören".to_string(),
+        Msg::NamesUnderstood(names) => format!("Ich habe {} Personen hinzugefügt: {}.", names.len(), names.join("; ")),
+        Msg::EditNames => "✏️ Ändern".to_string(),
+        Msg::ConfirmStatement(statement) => format!("{}\nSoll das in den Stammbaum?", proposal(statement)),
+        Msg::UnknownParent => "👤 Name unbekannt".to_string(),
+        Msg::ChoosePlaceholder => "Wen möchtest du benennen?".to_string(),
+        Msg::PersonLabel(person) => capitalized(called(person)),
+        Msg::NamePlaceholder(Called::Unknown(sex, children)) => {
+            let unknown = match sex {
+                Some(Sex::Female) => "der unbekannten Mutter",
+                Some(Sex::Male) => "des unbekannten Vaters",
+                None => "des unbekannten Elternteils",
+            };
+            format!("Schreibe den Namen {} von {} oder schicke ein Foto.", unknown, names(children))
+        }
+        Msg::NamePlaceholder(person) => format!("Schreibe den Namen von {} oder schicke ein Foto.", called(person)),
+        Msg::ApplyStatement => "✅ Hinzufügen".to_string(),
+        Msg::DiscardStatement => "❌ Abbrechen".to_string(),
+        Msg::StatementApplied => "Der Stammbaum ist aktualisiert.".to_string(),
+        Msg::SamePerson => "Niemand kann sein eigener Verwandter sein.".to_string(),
+        Msg::AlreadyKnown => "Das steht schon im Stammbaum.".to_string(),
+        Msg::ParentUnclear(name) => format!("Beide Eltern von {} sind bekannt. Bitte schreibe, wer von ihnen das Kind ist, z. B. \"Maria is Anna's mother\".", name),
+        Msg::NothingPending => "Dieser Vorschlag ist veraltet, bitte schreibe den Satz noch einmal.".to_string(),
+        Msg::TreeExpiring(name, left) => format!("Der Stammbaum „{}“ wurde länger nicht benutzt und wird in {} gelöscht. Behalte ihn oder exportiere ihn jetzt, um ihn später zu importieren. Schreibe /retention, um deine Stammbäume länger zu behalten.", name, hours(left)),
+        Msg::KeepTree => "Stammbaum behalten".to_string(),
+        Msg::ExportNow => "Jetzt exportieren".to_string(),
+        Msg::TreeKept(name) => format!("Der Stammbaum „{}“ bleibt erhalten.", name),
+        Msg::NoSiblings => "Keine Geschwister".to_string(),
+        Msg::NoMoreSiblings => "Keine weiteren Geschwister".to_string(),
+        Msg::NoChildren => "Keine Kinder".to_string(),
+        Msg::NoMoreChildren => "Nein".to_string(),
+        Msg::YesSurname(surname) => format!("Ja, {}", surname),
+        Msg::NoSurname => "Kein Nachname".to_string(),
+        Msg::Started => "Los geht's! Bitte füge eine Person aus deiner Familie hinzu oder schreibe deinen Namen".to_string(),
+        Msg::NoTree => "Du hast noch keinen Stammbaum. Schreibe /start oder /new <Name>, um einen anzulegen.".to_string(),
+        Msg::TreeList => "Deine Stammbäume. Drücke einen Knopf, um zu wechseln.".to_string(),
+        Msg::CurrentTreeLabel(name) => format!("{} (aktuell)", name),
+        Msg::ActiveTree(name) => format!("Der aktuelle Stammbaum ist „{}“.", name),
+        Msg::TreeCreated(name) => format!("Stammbaum „{}“ ist angelegt! Bitte füge eine Person aus deiner Familie hinzu oder schreibe deinen Namen", name),
+        Msg::TreeRenamed(name) => format!("Der aktuelle Stammbaum heißt jetzt „{}“.", name),
+        Msg::SwitchedTo(name) => format!("Gewechselt zu „{}“.", name),
+        Msg::TreeGone => "Diesen Stammbaum gibt es nicht mehr. Schreibe /trees, um deine Stammbäume zu sehen.".to_string(),
+        Msg::EmptyTreeName => "Bitte schreibe den Namen des Stammbaums nach dem Befehl.".to_string(),
+        Msg::TreeNameTaken(name) => format!("Du hast schon einen Stammbaum namens „{}“. Bitte wähle einen anderen Namen.", name),
+        Msg::TreeNotFound(name) => format!("Es gibt keinen Stammbaum namens „{}“. Schreibe /trees, um deine Stammbäume zu sehen.", name),
+        Msg::TreeDeleted(name) => format!("Stammbaum „{}“ ist gelöscht.", name),
+        Msg::TreeLeft(name) => format!("Du hast den geteilten Stammbaum „{}“ verlassen.", name),
+        Msg::OnlyOwnerRestarts(name) => format!("Nur der Besitzer kann den geteilten Stammbaum „{}“ neu beginnen. Schreibe /new <Name>, um einen eigenen anzulegen.", name),
+        Msg::ViewOnly(name) => format!("Du kannst „{}“ nur ansehen. Bitte den Besitzer um eine Einladung zum Bearbeiten.", name),
+        Msg::ShareUsage => "Schreibe /share view, damit Verwandte den Stammbaum sehen, oder /share edit, damit sie auch Verwandte hinzufügen.".to_string(),
+        Msg::ShareLink(name, can_edit, link) => format!("Schicke diesen Link an Verwandte, die „{}“ {} sollen:\n{}", name, rights(can_edit), link),
+        Msg::OnlyOwnerShares(name) => format!("Nur der Besitzer kann „{}“ teilen.", name),
+        Msg::Joined(name, can_edit) => format!("Du bist dem Stammbaum „{}“ beigetreten und kannst ihn {}. Schreibe /finish, um das Bild zu sehen.", name, rights(can_edit)),
+        Msg::InviteInvalid => "Dieser Einladungslink ist nicht mehr gültig. Bitte um einen neuen.".to_string(),
+        Msg::PeopleAdded(author, added, name) => format!("{} hat {} zum geteilten Stammbaum „{}“ hinzugefügt.", author, added.join(", "), name),
+        Msg::MemberJoined(author, name) => format!("{} ist dem Stammbaum „{}“ beigetreten.", author, name),
+        Msg::TreeDeletedBy(author, name) => format!("{} hat den geteilten Stammbaum „{}“ gelöscht.", author, name),
+        Msg::TreeLeftBy(author, name) => format!("{} hat den geteilten Stammbaum „{}“ verlassen.", author, name),
+        Msg::Warning(problem) => format!("⚠️ {} Wahrscheinlich war eine der Antworten falsch.", issue(problem)),
+        Msg::RemoveLink => "Letzte Verbindung entfernen".to_string(),
+        Msg::KeepAsIs => "So lassen".to_string(),
+        Msg::Unlinked(parent, child) => format!("{} ist nicht mehr Elternteil von {}.", parent, child),
+        Msg::AlreadyUnlinked => "Diese Verbindung ist schon entfernt.".to_string(),
+        Msg::WarningOutdated => "Der Stammbaum hat sich seit dieser Warnung geändert, daher bleibt die Verbindung bestehen.".to_string(),
+        Msg::NotYourQuestion => "Diese Frage wurde jemand anderem gestellt".to_string(),
+        Msg::UnknownButton => "Befehl nicht erkannt".to_string(),
+        Msg::ChooseTheme => "Wähle den Stil des Diagramms.".to_string(),
+        Msg::ThemeChanged(name) => format!("Diagramme verwenden jetzt das Thema „{}“. Schreibe /finish, um es zu sehen.", name),
+        Msg::UnknownTheme(name) => format!("Es gibt kein Thema namens „{}“. Wähle eines der Themen unten.", name),
+        Msg::ChooseRetention => "Wähle, wie lange deine Stammbäume bleiben, wenn niemand sie benutzt. Ein geteilter Stammbaum bleibt so lange, wie eines seiner Mitglieder möchte.".to_string(),
+        Msg::RetentionOption(retention) => match retention {
+            Retention::Default => "Wie üblich",
+            Retention::Month => "Einen Monat",
+            Retention::Forever => "Bis ich sie lösche",
+        }.to_string(),
+        Msg::RetentionChanged(retention) => match retention {
+            Retention::Default => "Deine Stammbäume werden wie üblich gelöscht, wenn niemand sie benutzt.",
+            Retention::Month => "Deine Stammbäume bleiben einen Monat, nachdem jemand sie zuletzt benutzt hat.",
+            Retention::Forever => "Deine Stammbäume bleiben, bis du sie mit /delete_tree löschst.",
+        }.to_string(),
+        Msg::UnknownRetention(text) => format!("„{}“ steht nicht zur Auswahl. Wähle unten, wie lange deine Stammbäume bleiben.", text),
+        Msg::ChooseLanguage => "Wähle die Sprache des Bots.".to_string(),
+        Msg::LanguageChanged => "Ab jetzt spreche ich Deutsch.".to_string(),
+        Msg::UnknownLanguage(language) => format!("„{}“ spreche ich noch nicht. Wähle eine der Sprachen unten.", language),
+        Msg::Someone => "Jemand".to_string(),
+    }
+}
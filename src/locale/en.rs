@@ -0,0 +1,255 @@
+use super::{capitalized, Msg};
+use crate::session::Retention;
+use crate::updater::model::{Called, Sex, Subject};
+use crate::updater::statement::{Kinship, Proposal};
+use crate::updater::table::{RowError, RowProblem};
+use crate::updater::validation::{Issue, MAX_PARENTS};
+
+fn names(names: &[impl ToString]) -> String {
+    names.iter().map(|name| name.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+fn called(called: &Called) -> String {
+    match called {
+        Called::Name(name) => name.to_string(),
+        Called::Unknown(sex, children) => format!("unknown {} of {}", role(Kinship::Parent, *sex), names(&children.iter().map(self::called).collect::<Vec<String>>())),
+    }
+}
+
+fn describe(subject: &Subject) -> String {
+    let relatives = |people: &[Called]| names(&people.iter().map(called).collect::<Vec<String>>());
+    let description = match (subject.children.is_empty(), subject.parents.is_empty()) {
+        (false, false) => format!("who is parent of {} and also child of {}", relatives(&subject.children), relatives(&subject.parents)),
+        (false, true) => format!("who is parent of {}", relatives(&subject.children)),
+        (true, false) => format!("who is child of {}", relatives(&subject.parents)),
+        (true, true) => return called(&subject.name)
+    };
+    format!("{}, {}", called(&subject.name), description)
+}
+
+fn issue(issue: &Issue) -> String {
+    match issue {
+        Issue::Cycle(people) => format!("{} turned out to be their own ancestors.", people.join(", ")),
+        Issue::TooManyParents(child, count) => format!("{} has {} parents now, but a person can't have more than {}.", child, count, MAX_PARENTS),
+        Issue::ChildBornBeforeParent(parent, child) => format!("{} was born before their parent {}.", child, parent),
+        Issue::ParentTooYoung(parent, child, age) => format!("{} was only {} when {} was born.", parent, age, child),
+    }
+}
+
+fn role(kinship: Kinship, sex: Option<Sex>) -> &'static str {
+    match (kinship, sex) {
+        (Kinship::Parent, Some(Sex::Female)) => "mother",
+        (Kinship::Parent, Some(Sex::Male)) => "father",
+        (Kinship::Parent, None) => "parent",
+        (Kinship::Child, Some(Sex::Female)) => "daughter",
+        (Kinship::Child, Some(Sex::Male)) => "son",
+        (Kinship::Child, None) => "child",
+        (Kinship::Grandparent, Some(Sex::Female)) => "grandmother",
+        (Kinship::Grandparent, Some(Sex::Male)) => "grandfather",
+        (Kinship::Grandparent, None) => "grandparent",
+        (Kinship::Grandchild, Some(Sex::Female)) => "granddaughter",
+        (Kinship::Grandchild, Some(Sex::Male)) => "grandson",
+        (Kinship::Grandchild, None) => "grandchild",
+        (Kinship::Sibling, Some(Sex::Female)) => "sister",
+        (Kinship::Sibling, Some(Sex::Male)) => "brother",
+        (Kinship::Sibling, None) => "sibling",
+        (Kinship::Spouse, Some(Sex::Female)) => "wife",
+        (Kinship::Spouse, Some(Sex::Male)) => "husband",
+        (Kinship::Spouse, None) => "spouse",
+    }
+}
+
+fn proposal(proposal: &Proposal) -> String {
+    let mut text = match (proposal.kinship, proposal.year) {
+        (Kinship::Spouse, Some(year)) => format!("{} and {} married in {}.", proposal.person, proposal.relative, year),
+        (Kinship::Spouse, None) => format!("{} and {} are married.", proposal.person, proposal.relative),
+        (kinship, _) => format!("{} is {}'s {}.", proposal.person, proposal.relative, role(kinship, proposal.sex))
+    };
+    if !proposal.new_people.is_empty() {
+        text.push_str(&format!(" New in the tree: {}.", names(&proposal.new_people)));
+    }
+    if proposal.through_unknown {
+        text.push_str(" They are linked through a parent whose name is unknown.");
+    }
+    text
+}
+
+fn row_error(error: &RowError) -> String {
+    let problem = match &error.problem {
+        RowProblem::UnclosedQuote => "a quote is not closed".to_string(),
+        RowProblem::MissingColumn(column) => format!("column \"{}\" is missing", column),
+        RowProblem::MissingId => "id is empty".to_string(),
+        RowProblem::DuplicateId(id) => format!("id \"{}\" is used twice", id),
+        RowProblem::MissingName => "given name is empty".to_string(),
+        RowProblem::BadYear(year) => format!("\"{}\" is not a year", year),
+        RowProblem::BadSex(sex) => format!("sex \"{}\" should be F or M", sex),
+        RowProblem::BadPlaceholder(mark) => format!("placeholder \"{}\" should be yes or empty", mark),
+        RowProblem::UnknownPerson(id) => format!("there's nobody with id \"{}\" in people.csv", id),
+        RowProblem::UnknownRelation(relation) => format!("type \"{}\" should be parent or child", relation),
+        RowProblem::SelfLink => "a person can't be their own parent".to_string(),
+    };
+    format!("{}, row {}: {}", error.table.file_name(), error.row, problem)
+}
+
+fn import_failed(errors: &[RowError], total: usize) -> String {
+    let mut text = String::from("Nothing was imported. Please fix these rows and send both files again:");
+    for error in errors {
+        text.push_str(&format!("\n{}", row_error(error)));
+    }
+    if total > errors.len() {
+        text.push_str(&format!("\n…and {} more", total - errors.len()));
+    }
+    text
+}
+
+fn people(count: usize) -> String {
+    format!("{} {}", count, if count == 1 { "person" } else { "people" })
+}
+
+fn hours(count: u64) -> String {
+    format!("{} {}", count, if count == 1 { "hour" } else { "hours" })
+}
+
+fn rights(can_edit: bool) -> &'static str {
+    if can_edit { "view and edit" } else { "view" }
+}
+
+pub fn text(msg: Msg) -> String {
+    match msg {
+        Msg::Help => "These commands are supported:
+/help — List all commands
+/start — Start/restart tree generation
+/finish [svg|pdf] [document] — Print your family tree to the screen
+/chart <name> [up] [down] [siblings] — Print relatives of one person
+/export mermaid|plantuml|csv|html [document] — Export the tree as text, tables or a page
+/edit <name> — Choose a person to send a photo of
+/new <name> — Create a new tree
+/trees — List your trees
+/switch <name> — Switch to another tree
+/rename_tree <new name> — Rename the current tree
+/delete_tree <name> — Delete a tree
+/share view or /share edit — Invite relatives to the current tree
+/theme — Change the chart style
+/retention — Choose how long unused trees are kept
+/language — Change the language".to_string(),
+        Msg::AskRoot => "Please add some person in your family tree or write your name".to_string(),
+        Msg::AskFirstParent(subject) => format!("Write then name of the 1st parent of {}. If you don't know the name, press the button.", describe(subject)),
+        Msg::AskSecondParent(subject) => format!("Write then name of the 2nd parent of {}. If you don't know the name, press the button.", describe(subject)),
+        Msg::AskIfSiblings(subject) => format!("Maybe {} has some siblings? Write the name of the first one that you know or press the button.", describe(subject)),
+        Msg::AskIfMoreSiblings(subject) => format!("Tell me the name of one more sibling of {} or press the button.", describe(subject)),
+        Msg::AskIfChildren(subject) => format!("Tell me if {} has any children. If so, tell me the name. If none or you don't know, press the button.", describe(subject)),
+        Msg::AskIfMoreChildren(subject) => format!("Maybe {} has any other kids? If there's none, press the button. If you know someone, write the name.", describe(subject)),
+        Msg::ConfirmSurname(child) => format!("I guess it's {}. Is the surname right? Press the button to confirm or write the right surname.", child),
+        Msg::ChartUsage => "Type /finish for a picture, /finish svg or /finish pdf for a zoomable chart, add document to get the picture uncompressed.".to_string(),
+        Msg::FocusUsage => "Type /chart followed by the name of the person, e.g. /chart Anna 2 1. Numbers are generations up and down, add siblings to show brothers and sisters too.".to_string(),
+        Msg::PersonNotFound(name) => format!("There's nobody called \"{}\" in the tree.", name),
+        Msg::PersonAmbiguous(names) => format!("Several people match: {}. Please write the full name.", names.join(", ")),
+        Msg::ExportUsage => "Type /export mermaid or /export plantuml to get the tree as text for a wiki, add document to get a file. Type /export csv to get tables for a spreadsheet, send both of them back to replace the tree. Type /export html to get a page with photos.".to_string(),
+        Msg::TableWaiting(file) => format!("Got it! Now send {} to finish the import.", file),
+        Msg::UnknownTable => "I can't read this file. Send people.csv and relationships.csv made with /export csv and saved as CSV UTF-8.".to_string(),
+        Msg::TableTooBig => "This file is too big to import.".to_string(),
+        Msg::ImportFailed(errors, total) => import_failed(errors, total),
+        Msg::Imported(count, name) => format!("Imported {} into \"{}\".", people(count), name),
+        Msg::TreeImportedBy(author, name) => format!("{} replaced the shared tree \"{}\" with an imported one.", author, name),
+        Msg::EditUsage => "Type /edit followed by the name of the person, e.g. /edit Anna Smith.".to_string(),
+        Msg::EditingPerson(name) => format!("Send a photo of {}.", name),
+        Msg::PhotoAttached(name) => format!("The photo of {} is saved. Type /finish to see it on the chart.", name),
+        Msg::AnswerWithName => "Sorry, I can't use this. Please answer with a name or press a button.".to_string(),
+        Msg::NoPersonForPhoto => "Whose photo is it? Type /edit followed by the name of the person, then send the photo again.".to_string(),
+        Msg::NotifyError => "Some error occured :( Please restart the bot!".to_string(),
+        Msg::TreeFinished => "Everybody in the tree is described, there's nothing left to answer. Type /finish to get the chart or undo your last answer if it was a mistake.".to_string(),
+        Msg::InvalidAnswer => "I can't use this answer. Please write one name (or both parents at once) or press a button.".to_string(),
+        Msg::PersonGone => "The person I asked you about isn't in the tree anymore, somebody has undone adding them.".to_string(),
+        Msg::InterviewConfused => "Sorry, I got confused about whom to ask you about.".to_string(),
+        Msg::NothingToUndo => "There's nothing to undo: you haven't answered yet or a relative has changed the tree since.".to_string(),
+        Msg::Undone => "Your last answer is undone.".to_string(),
+        Msg::ContinueInterview => "Continue".to_string(),
+        Msg::UndoAnswer => "Undo my last answer".to_string(),
+        Msg::PickAnotherPerson => "Ask about someone else".to_string(),
+        Msg::ChartFailed => "Sorry, I couldn't draw the chart. Please try again later or get the tree as text with /export.".to_string(),
+        Msg::TelegramFailed => "Telegram didn't accept my answer. Please try again in a minute.".to_string(),
+        Msg::DownloadFailed => "I couldn't download the file from Telegram. Please send it again.".to_string(),
+        Msg::NotifyComplete => "We asked enough! you can get your pedigree chart by performing /finish command".to_string(),
+        Msg::DontKnow => "Don't know".to_string(),
+        Msg::GoBack => "« Back".to_string(),
+        Msg::SkipPerson => "Skip person".to_string(),
+        Msg::StopHere => "Stop here".to_string(),
+        Msg::NamesUnderstood(names) => format!("I've added {} people: {}.", names.len(), names.join("; ")),
+        Msg::EditNames => "✏️ Edit".to_string(),
+        Msg::ConfirmStatement(statement) => format!("{}\nAdd this to the tree?", proposal(statement)),
+        Msg::UnknownParent => "👤 Name unknown".to_string(),
+        Msg::ChoosePlaceholder => "Whom do you want to name?".to_string(),
+        Msg::PersonLabel(person) => capitalized(called(person)),
+        Msg::NamePlaceholder(person @ Called::Unknown(..)) => format!("Write the name of the {} or send their photo.", called(person)),
+        Msg::NamePlaceholder(person) => format!("Write the name of {} or send their photo.", called(person)),
+        Msg::ApplyStatement => "✅ Add".to_string(),
+        Msg::DiscardStatement => "❌ Cancel".to_string(),
+        Msg::StatementApplied => "The tree is updated.".to_string(),
+        Msg::SamePerson => "A person can't be their own relative.".to_string(),
+        Msg::AlreadyKnown => "The tree already has this.".to_string(),
+        Msg::ParentUnclear(name) => format!("Both parents of {} are known. Please say which of them is the child, e.g. \"Maria is Anna's mother\".", name),
+        Msg::NothingPending => "This suggestion is outdated, please write the sentence again.".to_string(),
+        Msg::TreeExpiring(name, left) => format!("Nobody has used the tree \"{}\" for a while, so it will be deleted in {}. Keep it or export it now to import later. Type /retention to keep your trees longer.", name, hours(left)),
+        Msg::KeepTree => "Keep my tree".to_string(),
+        Msg::ExportNow => "Export now".to_string(),
+        Msg::TreeKept(name) => format!("The tree \"{}\" is kept.", name),
+        Msg::NoSiblings => "No siblings".to_string(),
+        Msg::NoMoreSiblings => "No more siblings".to_string(),
+        Msg::NoChildren => "No children".to_string(),
+        Msg::NoMoreChildren => "No".to_string(),
+        Msg::YesSurname(surname) => format!("Yes, {}", surname),
+        Msg::NoSurname => "No surname".to_string(),
+        Msg::Started => "Let's start! Please add some person in your family tree or write your name".to_string(),
+        Msg::NoTree => "You have no trees yet. Type /start or /new <name> to create one.".to_string(),
+        Msg::TreeList => "Your trees. Press a button to switch to another one.".to_string(),
+        Msg::CurrentTreeLabel(name) => format!("{} (current)", name),
+        Msg::ActiveTree(name) => format!("The current tree is \"{}\".", name),
+        Msg::TreeCreated(name) => format!("Tree \"{}\" is created! Please add some person in your family tree or write your name", name),
+        Msg::TreeRenamed(name) => format!("The current tree is now called \"{}\".", name),
+        Msg::SwitchedTo(name) => format!("Switched to \"{}\".", name),
+        Msg::TreeGone => "This tree doesn't exist anymore. Type /trees to see your trees.".to_string(),
+        Msg::EmptyTreeName => "Please add a name for the tree after the command.".to_string(),
+        Msg::TreeNameTaken(name) => format!("You already have a tree called \"{}\". Please choose another name.", name),
+        Msg::TreeNotFound(name) => format!("There's no tree called \"{}\". Type /trees to see your trees.", name),
+        Msg::TreeDeleted(name) => format!("Tree \"{}\" is deleted.", name),
+        Msg::TreeLeft(name) => format!("You left the shared tree \"{}\".", name),
+        Msg::OnlyOwnerRestarts(name) => format!("Only the owner can restart the shared tree \"{}\". Type /new <name> to start your own tree.", name),
+        Msg::ViewOnly(name) => format!("You can only view \"{}\". Ask its owner for an edit invite.", name),
+        Msg::ShareUsage => "Type /share view to let relatives see the tree or /share edit to let them add relatives too.".to_string(),
+        Msg::ShareLink(name, can_edit, link) => format!("Send this link to relatives who should {} \"{}\":\n{}", rights(can_edit), name, link),
+        Msg::OnlyOwnerShares(name) => format!("Only the owner can share \"{}\".", name),
+        Msg::Joined(name, can_edit) => format!("You joined the tree \"{}\" and can {} it. Type /finish to see the chart.", name, rights(can_edit)),
+        Msg::InviteInvalid => "This invite link is not valid anymore. Ask for a new one.".to_string(),
+        Msg::PeopleAdded(author, added, name) => format!("{} added {} to the shared tree \"{}\".", author, added.join(", "), name),
+        Msg::MemberJoined(author, name) => format!("{} joined the tree \"{}\".", author, name),
+        Msg::TreeDeletedBy(author, name) => format!("{} deleted the shared tree \"{}\".", author, name),
+        Msg::TreeLeftBy(author, name) => format!("{} left the shared tree \"{}\".", author, name),
+        Msg::Warning(problem) => format!("⚠️ {} Probably one of the answers was wrong.", issue(problem)),
+        Msg::RemoveLink => "Remove the latest link".to_string(),
+        Msg::KeepAsIs => "Keep as is".to_string(),
+        Msg::Unlinked(parent, child) => format!("{} is not a parent of {} anymore.", parent, child),
+        Msg::AlreadyUnlinked => "This link is already removed.".to_string(),
+        Msg::WarningOutdated => "The tree has changed since this warning, so the link is kept.".to_string(),
+        Msg::NotYourQuestion => "This question was asked to someone else".to_string(),
+        Msg::UnknownButton => "Can't recognise the command".to_string(),
+        Msg::ChooseTheme => "Choose the style of the chart.".to_string(),
+        Msg::ThemeChanged(name) => format!("Charts will use the \"{}\" theme now. Type /finish to see it.", name),
+        Msg::UnknownTheme(name) => format!("There's no theme called \"{}\". Choose one of the themes below.", name),
+        Msg::ChooseRetention => "Choose how long to keep your trees when nobody uses them. A shared tree is kept as long as any of its members wants.".to_string(),
+        Msg::RetentionOption(retention) => match retention {
+            Retention::Default => "As usual",
+            Retention::Month => "A month",
+            Retention::Forever => "Until I delete them",
+        }.to_string(),
+        Msg::RetentionChanged(retention) => match retention {
+            Retention::Default => "Your trees are deleted as usual when nobody uses them.",
+            Retention::Month => "Your trees are kept for a month after anyone last used them.",
+            Retention::Forever => "Your trees are kept until you delete them with /delete_tree.",
+        }.to_string(),
+        Msg::UnknownRetention(text) => format!("\"{}\" is not one of the choices. Choose how long to keep your trees below.", text),
+        Msg::ChooseLanguage => "Choose the language of the bot.".to_string(),
+        Msg::LanguageChanged => "I'll speak English from now on.".to_string(),
+        Msg::UnknownLanguage(language) => format!("I don't speak \"{}\" yet. Choose one of the languages below.", language),
+        Msg::Someone => "Someone".to_string(),
+    }
+}
@@ -0,0 +1,252 @@
+mod de;
+mod en;
+mod ru;
+
+use crate::session::Retention;
+use crate::updater::model::{Called, Subject};
+use crate::updater::name::PersonName;
+use crate::updater::statement::Proposal;
+use crate::updater::table::RowError;
+use crate::updater::validation::Issue;
+
+/// Language of the bot's messages. Every catalog matches all messages, so a missing translation doesn't compile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lang {
+    En,
+    Ru,
+    De,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 3] = [Lang::En, Lang::Ru, Lang::De];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Ru => "ru",
+            Lang::De => "de",
+        }
+    }
+
+    pub fn native_name(&self) -> &'static str {
+        match self {
+            Lang::En => "English",
+            Lang::Ru => "Русский",
+            Lang::De => "Deutsch",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Lang> {
+        Lang::ALL.iter().copied().find(|lang| lang.code() == code)
+    }
+
+    /// Picks the language for Telegram's `language_code` like "ru" or "de-AT", English when there's no translation
+    pub fn detect(language_code: Option<&str>) -> Lang {
+        language_code
+            .and_then(|code| code.split(['-', '_']).next())
+            .and_then(|code| Lang::from_code(&code.to_lowercase()))
+            .unwrap_or(Lang::En)
+    }
+
+    /// Reads the argument of /language: either the code or the native name
+    pub fn parse(text: &str) -> Option<Lang> {
+        let text = text.trim().to_lowercase();
+        Lang::ALL.iter().copied().find(|lang| lang.code() == text || lang.native_name().to_lowercase() == text)
+    }
+}
+
+/// Labels start with a capital letter even when they begin with a word like "unknown"
+fn capitalized(text: String) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => text
+    }
+}
+
+/// Every text the bot sends along with the values it mentions
+pub enum Msg<'a> {
+    Help,
+    AskRoot,
+    AskFirstParent(&'a Subject),
+    AskSecondParent(&'a Subject),
+    AskIfSiblings(&'a Subject),
+    AskIfMoreSiblings(&'a Subject),
+    AskIfChildren(&'a Subject),
+    AskIfMoreChildren(&'a Subject),
+    ConfirmSurname(&'a PersonName),
+    ChartUsage,
+    FocusUsage,
+    ExportUsage,
+    /// File name of the table which is still missing
+    TableWaiting(&'a str),
+    UnknownTable,
+    TableTooBig,
+    /// Rows to show and the number of all wrong rows
+    ImportFailed(&'a [RowError], usize),
+    /// Number of people and tree name
+    Imported(usize, &'a str),
+    TreeImportedBy(&'a str, &'a str),
+    EditUsage,
+    EditingPerson(&'a str),
+    PhotoAttached(&'a str),
+    NoPersonForPhoto,
+    AnswerWithName,
+    PersonNotFound(&'a str),
+    PersonAmbiguous(&'a [String]),
+    NotifyError,
+    TreeFinished,
+    InvalidAnswer,
+    PersonGone,
+    InterviewConfused,
+    NothingToUndo,
+    Undone,
+    ContinueInterview,
+    UndoAnswer,
+    PickAnotherPerson,
+    ChartFailed,
+    TelegramFailed,
+    DownloadFailed,
+    NotifyComplete,
+    DontKnow,
+    GoBack,
+    SkipPerson,
+    StopHere,
+    NamesUnderstood(&'a [String]),
+    EditNames,
+    UnknownParent,
+    ChoosePlaceholder,
+    /// Label on charts and buttons: the name or, for placeholders, "Unknown father of Robert"
+    PersonLabel(&'a Called),
+    NamePlaceholder(&'a Called),
+    ConfirmStatement(&'a Proposal),
+    ApplyStatement,
+    DiscardStatement,
+    StatementApplied,
+    SamePerson,
+    AlreadyKnown,
+    ParentUnclear(&'a str),
+    NothingPending,
+    /// Tree name and hours left
+    TreeExpiring(&'a str, u64),
+    KeepTree,
+    ExportNow,
+    TreeKept(&'a str),
+    NoSiblings,
+    NoMoreSiblings,
+    NoChildren,
+    NoMoreChildren,
+    YesSurname(&'a str),
+    NoSurname,
+    Started,
+    NoTree,
+    TreeList,
+    CurrentTreeLabel(&'a str),
+    ActiveTree(&'a str),
+    TreeCreated(&'a str),
+    TreeRenamed(&'a str),
+    SwitchedTo(&'a str),
+    TreeGone,
+    EmptyTreeName,
+    TreeNameTaken(&'a str),
+    TreeNotFound(&'a str),
+    TreeDeleted(&'a str),
+    TreeLeft(&'a str),
+    OnlyOwnerRestarts(&'a str),
+    ViewOnly(&'a str),
+    ShareUsage,
+    /// Tree name, whether the invite allows editing and the link
+    ShareLink(&'a str, bool, &'a str),
+    OnlyOwnerShares(&'a str),
+    /// Tree name and whether the user can edit it
+    Joined(&'a str, bool),
+    InviteInvalid,
+    /// Author, added names and tree name
+    PeopleAdded(&'a str, &'a [String], &'a str),
+    MemberJoined(&'a str, &'a str),
+    TreeDeletedBy(&'a str, &'a str),
+    TreeLeftBy(&'a str, &'a str),
+    Warning(&'a Issue),
+    RemoveLink,
+    KeepAsIs,
+    Unlinked(&'a str, &'a str),
+    AlreadyUnlinked,
+    WarningOutdated,
+    NotYourQuestion,
+    UnknownButton,
+    ChooseTheme,
+    ThemeChanged(&'a str),
+    UnknownTheme(&'a str),
+    ChooseRetention,
+    RetentionOption(Retention),
+    RetentionChanged(Retention),
+    UnknownRetention(&'a str),
+    ChooseLanguage,
+    LanguageChanged,
+    UnknownLanguage(&'a str),
+    Someone,
+}
+
+pub fn tr(lang: Lang, msg: Msg) -> String {
+    match lang {
+        Lang::En => en::text(msg),
+        Lang::Ru => ru::text(msg),
+        Lang::De => de::text(msg),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::updater::model::Sex;
+
+    #[test]
+    fn language_is_detected_from_telegram_code() {
+        assert_eq!(Lang::detect(Some("ru")), Lang::Ru);
+        assert_eq!(Lang::detect(Some("de-AT")), Lang::De);
+        assert_eq!(Lang::detect(Some("fr")), Lang::En, "Should fall back to English");
+        assert_eq!(Lang::detect(None), Lang::En);
+        assert_eq!(Lang::parse(" Deutsch"), Some(Lang::De));
+    }
+
+    #[test]
+    fn names_are_declined_in_descriptions() {
+        let subject = Subject::new(
+            PersonName::parse("Иван Петров"),
+            vec![PersonName::parse("Анна Петрова (урожд. Смирнова)").into()],
+            vec![PersonName::parse("Ольга").into(), PersonName::parse("Игорь").into()],
+        );
+        assert_eq!(
+            tr(Lang::Ru, Msg::AskFirstParent(&Subject::named(PersonName::parse("Мария Шевчук")))),
+            "Напишите имя первого родителя Марии Шевчук. Если вы не знаете имени, нажмите кнопку."
+        );
+        assert_eq!(
+            tr(Lang::Ru, Msg::AskSecondParent(&subject)),
+            "Напишите имя второго родителя Ивана Петрова (родитель Ольги, Игоря; ребёнок Анны Петровой (урожд. Смирнова)). Если вы не знаете имени, нажмите кнопку."
+        );
+        assert_eq!(
+            tr(Lang::En, Msg::AskSecondParent(&subject)),
+            "Write then name of the 2nd parent of Иван Петров, who is parent of Ольга, Игорь and also child of Анна Петрова (née Смирнова). If you don't know the name, press the button."
+        );
+        assert_eq!(
+            tr(Lang::De, Msg::AskIfChildren(&subject)),
+            "Hat Иван Петров (Elternteil von Ольга, Игорь; Kind von Анна Петрова (geb. Смирнова)) Kinder? Wenn ja, schreibe den Namen. Wenn nicht oder du es nicht weißt, drücke den Knopf."
+        );
+    }
+
+    #[test]
+    fn placeholders_are_called_by_their_children() {
+        let father = Called::Unknown(Some(Sex::Male), vec![PersonName::parse("Роберт").into()]);
+        let grandparent = Called::Unknown(None, vec![father.clone()]);
+        assert_eq!(tr(Lang::En, Msg::PersonLabel(&father)), "Unknown father of Роберт");
+        assert_eq!(tr(Lang::En, Msg::PersonLabel(&grandparent)), "Unknown parent of unknown father of Роберт");
+        assert_eq!(tr(Lang::Ru, Msg::PersonLabel(&grandparent)), "Неизвестный родитель неизвестного отца Роберта");
+        assert_eq!(tr(Lang::De, Msg::PersonLabel(&father)), "Unbekannter Vater von Роберт");
+        assert_eq!(
+            tr(Lang::Ru, Msg::AskFirstParent(&Subject::named(father.clone()))),
+            "Напишите имя первого родителя неизвестного отца Роберта. Если вы не знаете имени, нажмите кнопку."
+        );
+        assert_eq!(tr(Lang::En, Msg::NamePlaceholder(&father)), "Write the name of the unknown father of Роберт or send their photo.");
+    }
+}
@@ -0,0 +1,453 @@
+use super::{capitalized, Msg};
+use crate::session::Retention;
+use crate::updater::model::{Called, Sex, Subject};
+use crate::updater::statement::{Kinship, Proposal};
+use crate::updater::name::PersonName;
+use crate::updater::table::{RowError, RowProblem};
+use crate::updater::validation::{Issue, MAX_PARENTS};
+
+/// Given names ending with "а" or "я" which belong to men
+const MALE_NAMES_ENDING_WITH_A: [&str; 10] = ["никита", "илья", "кузьма", "фома", "лука", "савва", "данила", "гаврила", "мина", "ерёма"];
+const FEMALE_SURNAME_ENDINGS: [&str; 7] = ["ова", "ева", "ёва", "ина", "ына", "ая", "яя"];
+const MALE_SURNAME_ENDINGS: [&str; 8] = ["ов", "ев", "ёв", "ин", "ын", "ий", "ый", "ой"];
+/// After these letters genitive ending "ы" is spelled as "и"
+const HUSHING_AND_VELAR: [char; 7] = ['г', 'к', 'х', 'ж', 'ч', 'ш', 'щ'];
+const UNDECLINED_ENDINGS: [char; 7] = ['о', 'е', 'ё', 'и', 'у', 'ю', 'э'];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Case {
+    Nominative,
+    Genitive,
+}
+
+fn is_cyrillic(word: &str) -> bool {
+    word.chars().any(|c| c.is_alphabetic()) && word.chars().filter(|c| c.is_alphabetic()).all(|c| ('\u{0400}'..='\u{04FF}').contains(&c))
+}
+
+fn is_female(name: &PersonName) -> bool {
+    if let Some(surname) = name.surname.as_deref().map(str::to_lowercase) {
+        if FEMALE_SURNAME_ENDINGS.iter().any(|ending| surname.ends_with(ending)) {
+            return true;
+        }
+        if MALE_SURNAME_ENDINGS.iter().any(|ending| surname.ends_with(ending)) {
+            return false;
+        }
+    }
+    let given = name.given.split_whitespace().next().unwrap_or_default().to_lowercase();
+    (given.ends_with('а') || given.ends_with('я')) && !MALE_NAMES_ENDING_WITH_A.contains(&given.as_str())
+}
+
+/// Splits the word into the stem and the lowercase last letter
+fn split_ending(word: &str) -> Option<(&str, char)> {
+    let last = word.chars().last()?;
+    Some((&word[..word.len() - last.len_utf8()], last.to_lowercase().next()?))
+}
+
+fn genitive_of_a_ending(stem: &str) -> String {
+    let hushing = stem.chars().last().map(|c| HUSHING_AND_VELAR.contains(&c.to_lowercase().next().unwrap_or(c))).unwrap_or(false);
+    format!("{}{}", stem, if hushing { 'и' } else { 'ы' })
+}
+
+fn given_genitive(word: &str, female: bool) -> String {
+    let (stem, last) = match split_ending(word) {
+        Some(split) if is_cyrillic(word) => split,
+        _ => return word.to_string()
+    };
+    match last {
+        'а' => genitive_of_a_ending(stem),
+        'я' => format!("{}и", stem),
+        'й' => format!("{}я", stem),
+        'ь' if female => format!("{}и", stem),
+        'ь' => format!("{}я", stem),
+        c if UNDECLINED_ENDINGS.contains(&c) => word.to_string(),
+        _ if female => word.to_string(),
+        _ => format!("{}а", word)
+    }
+}
+
+fn surname_genitive(word: &str, female: bool) -> String {
+    if !is_cyrillic(word) {
+        return word.to_string();
+    }
+    let lower = word.to_lowercase();
+    let stem = |letters: usize| {
+        let cut = word.char_indices().rev().nth(letters - 1).map(|(i, _)| i).unwrap_or(0);
+        &word[..cut]
+    };
+    if female {
+        if lower.ends_with("ая") {
+            format!("{}ой", stem(2))
+        } else if lower.ends_with("яя") {
+            format!("{}ей", stem(2))
+        } else if FEMALE_SURNAME_ENDINGS.iter().any(|ending| lower.ends_with(ending)) {
+            format!("{}ой", stem(1))
+        } else if lower.ends_with('а') || lower.ends_with('я') {
+            given_genitive(word, true)
+        } else {
+            //female surnames ending with a consonant don't change
+            word.to_string()
+        }
+    } else if lower.ends_with("ий") || lower.ends_with("ый") || lower.ends_with("ой") {
+        format!("{}ого", stem(2))
+    } else {
+        given_genitive(word, false)
+    }
+}
+
+fn name(name: &PersonName, case: Case) -> String {
+    let female = is_female(name);
+    let decline = |text: &str, word_case: fn(&str, bool) -> String| match case {
+        Case::Nominative => text.to_string(),
+        Case::Genitive => text.split(' ')
+            .map(|word| word.split('-').map(|part| word_case(part, female)).collect::<Vec<String>>().join("-"))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+    let mut text = decline(&name.given, given_genitive);
+    for nickname in &name.nicknames {
+        text.push_str(&format!(" «{}»", nickname));
+    }
+    if let Some(surname) = &name.surname {
+        text.push_str(&format!(" {}", decline(surname, surname_genitive)));
+    }
+    if let Some(birth_surname) = &name.birth_surname {
+        text.push_str(&format!(" (урожд. {})", birth_surname));
+    }
+    text
+}
+
+/// Placeholders are called by their children in the genitive: "неизвестный отец Роберта"
+fn called(called: &Called, case: Case) -> String {
+    match called {
+        Called::Name(person) => name(person, case),
+        Called::Unknown(sex, children) => {
+            let unknown = match (sex, case) {
+                (Some(Sex::Female), Case::Nominative) => "неизвестная мать",
+                (Some(Sex::Female), Case::Genitive) => "неизвестной матери",
+                (Some(Sex::Male), Case::Nominative) => "неизвестный отец",
+                (Some(Sex::Male), Case::Genitive) => "неизвестного отца",
+                (None, Case::Nominative) => "неизвестный родитель",
+                (None, Case::Genitive) => "неизвестного родителя",
+            };
+            format!("{} {}", unknown, names(children, Case::Genitive))
+        }
+    }
+}
+
+fn names(names: &[Called], case: Case) -> String {
+    names.iter().map(|person| called(person, case)).collect::<Vec<String>>().join(", ")
+}
+
+/// "родитель" and "ребёнок" are followed by the genitive: "ребёнок Анны"
+fn describe(subject: &Subject, case: Case) -> String {
+    let mut relations = vec![];
+    if !subject.children.is_empty() {
+        relations.push(format!("родитель {}", names(&subject.children, Case::Genitive)));
+    }
+    if !subject.parents.is_empty() {
+        relations.push(format!("ребёнок {}", names(&subject.parents, Case::Genitive)));
+    }
+    if relations.is_empty() {
+        called(&subject.name, case)
+    } else {
+        format!("{} ({})", called(&subject.name, case), relations.join("; "))
+    }
+}
+
+/// Picks the word agreeing with the number from the forms for 1, 2 and 5: "1 год", "2 года", "5 лет"
+fn plural(count: i64, forms: [&str; 3]) -> String {
+    let word = match (count % 10, count % 100) {
+        (_, 11..=14) => forms[2],
+        (1, _) => forms[0],
+        (2..=4, _) => forms[1],
+        _ => forms[2]
+    };
+    format!("{} {}", count, word)
+}
+
+fn years(count: i32) -> String {
+    plural(count as i64, ["год", "года", "лет"])
+}
+
+fn hours(count: u64) -> String {
+    plural(count as i64, ["час", "часа", "часов"])
+}
+
+fn people(count: usize) -> String {
+    plural(count as i64, ["человек", "человека", "человек"])
+}
+
+fn issue(issue: &Issue) -> String {
+    match issue {
+        Issue::Cycle(people) => format!("{} оказались собственными предками.", people.join(", ")),
+        Issue::TooManyParents(child, count) => format!("Родителей у человека {} теперь {}, а больше {} быть не может.", child, count, MAX_PARENTS),
+        Issue::ChildBornBeforeParent(parent, child) => format!("Ребёнок ({}) родился раньше родителя ({}).", child, parent),
+        Issue::ParentTooYoung(parent, child, age) => format!("Родителю ({}) было всего {}, когда родился ребёнок ({}).", parent, years(*age), child),
+    }
+}
+
+fn role(kinship: Kinship, sex: Option<Sex>) -> &'static str {
+    match (kinship, sex) {
+        (Kinship::Parent, Some(Sex::Female)) => "мать",
+        (Kinship::Parent, Some(Sex::Male)) => "отец",
+        (Kinship::Parent, None) => "родитель",
+        (Kinship::Child, Some(Sex::Female)) => "дочь",
+        (Kinship::Child, Some(Sex::Male)) => "сын",
+        (Kinship::Child, None) => "ребёнок",
+        (Kinship::Grandparent, Some(Sex::Female)) => "бабушка",
+        (Kinship::Grandparent, Some(Sex::Male)) => "дедушка",
+        (Kinship::Grandparent, None) => "бабушка или дедушка",
+        (Kinship::Grandchild, Some(Sex::Female)) => "внучка",
+        (Kinship::Grandchild, Some(Sex::Male)) => "внук",
+        (Kinship::Grandchild, None) => "внук или внучка",
+        (Kinship::Sibling, Some(Sex::Female)) => "сестра",
+        (Kinship::Sibling, Some(Sex::Male)) => "брат",
+        (Kinship::Sibling, None) => "брат или сестра",
+        (Kinship::Spouse, Some(Sex::Female)) => "жена",
+        (Kinship::Spouse, Some(Sex::Male)) => "муж",
+        (Kinship::Spouse, None) => "супруг",
+    }
+}
+
+/// The role is followed by the genitive: "бабушка Роберта"
+fn proposal(proposal: &Proposal) -> String {
+    let person = name(&proposal.person, Case::Nominative);
+    let mut text = match (proposal.kinship, proposal.year) {
+        (Kinship::Spouse, Some(year)) => format!("{} и {} поженились в {} году.", person, name(&proposal.relative, Case::Nominative), year),
+        (Kinship::Spouse, None) => format!("{} и {} состоят в браке.", person, name(&proposal.relative, Case::Nominative)),
+        (kinship, _) => format!("{} — {} {}.", person, role(kinship, proposal.sex), name(&proposal.relative, Case::Genitive))
+    };
+    if !proposal.new_people.is_empty() {
+        text.push_str(&format!(" Новые в дереве: {}.", proposal.new_people.join(", ")));
+    }
+    if proposal.through_unknown {
+        text.push_str(" Их связывает родитель, чьё имя неизвестно.");
+    }
+    text
+}
+
+fn row_error(error: &RowError) -> String {
+    let problem = match &error.problem {
+        RowProblem::UnclosedQuote => "не закрыта кавычка".to_string(),
+        RowProblem::MissingColumn(column) => format!("нет столбца «{}»", column),
+        RowProblem::MissingId => "пустой id".to_string(),
+        RowProblem::DuplicateId(id) => format!("id «{}» встречается дважды", id),
+        RowProblem::MissingName => "пустое имя".to_string(),
+        RowProblem::BadYear(year) => format!("«{}» — это не год", year),
+        RowProblem::BadSex(sex) => format!("пол «{}» должен быть F или M", sex),
+        RowProblem::BadPlaceholder(mark) => format!("в столбце placeholder должно быть yes или пусто, а не «{}»", mark),
+        RowProblem::UnknownPerson(id) => format!("в people.csv нет человека с id «{}»", id),
+        RowProblem::UnknownRelation(relation) => format!("тип «{}» должен быть parent или child", relation),
+        RowProblem::SelfLink => "человек не может быть своим родителем".to_string(),
+    };
+    format!("{}, строка {}: {}", error.table.file_name(), error.row, problem)
+}
+
+fn import_failed(errors: &[RowError], total: usize) -> String {
+    let mut text = String::from("Ничего не импортировано. Исправьте эти строки и пришлите оба файла снова:");
+    for error in errors {
+        text.push_str(&format!("\n{}", row_error(error)));
+    }
+    if total > errors.len() {
+        text.push_str(&format!("\n…и ещё {}", total - errors.len()));
+    }
+    text
+}
+
+fn rights(can_edit: bool) -> &'static str {
+    if can_edit { "просматривать и редактировать" } else { "просматривать" }
+}
+
+pub fn text(msg: Msg) -> String {
+    match msg {
+        Msg::Help => "Поддерживаются такие команды:
+/help — Список команд
+/start — Начать дерево заново
+/finish [svg|pdf] [document] — Показать родословную
+/chart <имя> [вверх] [вниз] [siblings] — Показать родных одного человека
+/export mermaid|plantuml|csv|html [document] — Выгрузить дерево текстом, таблицами или страницей
+/edit <имя> — Выбрать человека, чтобы прислать его фото
+/new <название> — Создать новое дерево
+/trees — Список ваших деревьев
+/switch <название> — Перейти к другому дереву
+/rename_tree <новое название> — Переименовать текущее дерево
+/delete_tree <название> — Удалить дерево
+/share view или /share edit — Пригласить родственников в текущее дерево
+/theme — Сменить оформление схемы
+/retention — Выбрать, сколько хранить неиспользуемые деревья
+/language — Сменить язык".to_string(),
+        Msg::AskRoot => "Добавьте в дерево кого-нибудь из семьи или напишите своё имя".to_string(),
+        Msg::AskFirstParent(subject) => format!("Напишите имя первого родителя {}. Если вы не знаете имени, нажмите кнопку.", describe(subject, Case::Genitive)),
+        Msg::AskSecondParent(subject) => format!("Напишите имя второго родителя {}. Если вы не знаете имени, нажмите кнопку.", describe(subject, Case::Genitive)),
+        Msg::AskIfSiblings(subject) => format!("Может быть, у {} есть братья или сёстры? Напишите имя первого из них или нажмите кнопку.", describe(subject, Case::Genitive)),
+        Msg::AskIfMoreSiblings(subject) => format!("Напишите имя ещё одного брата или сестры {} или нажмите кнопку.", describe(subject, Case::Genitive)),
+        Msg::AskIfChildren(subject) => format!("Есть ли дети у {}? Если да, напишите имя. Если нет или вы не знаете, нажмите кнопку.", describe(subject, Case::Genitive)),
+        Msg::AskIfMoreChildren(subject) => format!("Может быть, у {} есть ещё дети? Если нет, нажмите кнопку. Если знаете, напишите имя.", describe(subject, Case::Genitive)),
+        Msg::ConfirmSurname(child) => format!("Полагаю, это {}. Фамилия верная? Нажмите кнопку, чтобы подтвердить, или напишите правильную фамилию.", name(child, Case::Nominative)),
+        Msg::ChartUsage => "Напишите /finish, чтобы получить картинку, /finish svg или /finish pdf — схему, которую можно увеличивать. Добавьте document, чтобы получить картинку без сжатия.".to_string(),
+        Msg::FocusUsage => "Напишите /chart и имя человека, например /chart Анна 2 1. Числа — сколько поколений показать выше и ниже, добавьте siblings, чтобы показать братьев и сестёр.".to_string(),
+        Msg::PersonNotFound(name) => format!("В дереве нет никого по имени «{}».", name),
+        Msg::PersonAmbiguous(names) => format!("Подходят несколько человек: {}. Напишите имя полностью.", names.join(", ")),
+        Msg::ExportUsage => "Напишите /export mermaid или /export plantuml, чтобы получить дерево текстом для вики. Добавьте document, чтобы получить файл. Напишите /export csv, чтобы получить таблицы для редактирования, и пришлите обе обратно, чтобы заменить дерево. Напишите /export html, чтобы получить страницу с фотографиями.".to_string(),
+        Msg::TableWaiting(file) => format!("Получил! Теперь пришлите {}, чтобы закончить импорт.", file),
+        Msg::UnknownTable => "Не могу прочитать этот файл. Пришлите people.csv и relationships.csv, полученные командой /export csv и сохранённые как CSV UTF-8.".to_string(),
+        Msg::TableTooBig => "Этот файл слишком большой для импорта.".to_string(),
+        Msg::ImportFailed(errors, total) => import_failed(errors, total),
+        Msg::Imported(count, name) => format!("В дерево «{}» импортировано: {}.", name, people(count)),
+        Msg::TreeImportedBy(author, name) => format!("{} заменяет общее дерево «{}» импортированным.", author, name),
+        Msg::EditUsage => "Напишите /edit и имя человека, например /edit Анна Смирнова.".to_string(),
+        Msg::EditingPerson(name) => format!("Пришлите фотографию, и я добавлю её к человеку: {}.", name),
+        Msg::PhotoAttached(name) => format!("Фотография сохранена: {}. Напишите /finish, чтобы увидеть её на схеме.", name),
+        Msg::AnswerWithName => "Простите, такие сообщения я не понимаю. Пожалуйста, ответьте именем или нажмите кнопку.".to_string(),
+        Msg::NoPersonForPhoto => "Чья это фотография? Напишите /edit и имя человека, а потом пришлите фотографию ещё раз.".to_string(),
+        Msg::NotifyError => "Произошла ошибка :( Пожалуйста, перезапустите бота!".to_string(),
+        Msg::TreeFinished => "Все в дереве уже описаны, отвечать больше не на что. Напишите /finish, чтобы получить схему, или отмените последний ответ, если он был ошибкой.".to_string(),
+        Msg::InvalidAnswer => "Не получается использовать этот ответ. Пожалуйста, напишите одно имя (или сразу обоих родителей) или нажмите кнопку.".to_string(),
+        Msg::PersonGone => "Человека, о котором я спрашивал, больше нет в дереве: кто-то отменил его добавление.".to_string(),
+        Msg::InterviewConfused => "Извините, я запутался, о ком вас спрашивать.".to_string(),
+        Msg::NothingToUndo => "Отменять нечего: вы ещё не отвечали или кто-то из родственников с тех пор изменил дерево.".to_string(),
+        Msg::Undone => "Ваш последний ответ отменён.".to_string(),
+        Msg::ContinueInterview => "Продолжить".to_string(),
+        Msg::UndoAnswer => "Отменить последний ответ".to_string(),
+        Msg::PickAnotherPerson => "Спросить о другом человеке".to_string(),
+        Msg::ChartFailed => "Не получилось нарисовать схему. Попробуйте позже или получите дерево текстом через /export.".to_string(),
+        Msg::TelegramFailed => "Telegram не принял мой ответ. Попробуйте ещё раз через минуту.".to_string(),
+        Msg::DownloadFailed => "Не получилось скачать файл из Telegram. Пожалуйста, отправьте его ещё раз.".to_string(),
+        Msg::NotifyComplete => "Мы спросили достаточно! Получить родословную можно командой /finish".to_string(),
+        Msg::DontKnow => "Не знаю".to_string(),
+        Msg::GoBack => "« Назад".to_string(),
+        Msg::SkipPerson => "Пропустить".to_string(),
+        Msg::StopHere => "Хватит об этой ветви".to_string(),
+        Msg::NamesUnderstood(names) => format!("Добавлено человек: {}. {}.", names.len(), names.join("; ")),
+        Msg::EditNames => "✏️ Исправить".to_string(),
+        Msg::ConfirmStatement(statement) => format!("{}\nДобавить это в дерево?", proposal(statement)),
+        Msg::UnknownParent => "👤 Имя неизвестно".to_string(),
+        Msg::ChoosePlaceholder => "Кого вы хотите назвать?".to_string(),
+        Msg::PersonLabel(person) => capitalized(called(person, Case::Nominative)),
+        Msg::NamePlaceholder(person) => format!("Напишите имя {} или пришлите фото.", called(person, Case::Genitive)),
+        Msg::ApplyStatement => "✅ Добавить".to_string(),
+        Msg::DiscardStatement => "❌ Отмена".to_string(),
+        Msg::StatementApplied => "Дерево обновлено.".to_string(),
+        Msg::SamePerson => "Человек не может быть родственником самому себе.".to_string(),
+        Msg::AlreadyKnown => "Это уже есть в дереве.".to_string(),
+        Msg::ParentUnclear(person) => format!("Оба родителя ({}) уже известны. Напишите, кто из них ребёнок, например \"Maria is Anna's mother\".", person),
+        Msg::NothingPending => "Это предложение устарело, напишите фразу ещё раз.".to_string(),
+        Msg::TreeExpiring(name, left) => format!("Дерево «{}» давно не открывали, через {} оно будет удалено. Сохраните его или экспортируйте сейчас, чтобы импортировать позже. Напишите /retention, чтобы хранить деревья дольше.", name, hours(left)),
+        Msg::KeepTree => "Сохранить дерево".to_string(),
+        Msg::ExportNow => "Экспортировать".to_string(),
+        Msg::TreeKept(name) => format!("Дерево «{}» сохранено.", name),
+        Msg::NoSiblings => "Нет братьев и сестёр".to_string(),
+        Msg::NoMoreSiblings => "Больше нет".to_string(),
+        Msg::NoChildren => "Нет детей".to_string(),
+        Msg::NoMoreChildren => "Нет".to_string(),
+        Msg::YesSurname(surname) => format!("Да, {}", surname),
+        Msg::NoSurname => "Без фамилии".to_string(),
+        Msg::Started => "Начнём! Добавьте в дерево кого-нибудь из семьи или напишите своё имя".to_string(),
+        Msg::NoTree => "У вас пока нет деревьев. Напишите /start или /new <название>, чтобы создать дерево.".to_string(),
+        Msg::TreeList => "Ваши деревья. Нажмите кнопку, чтобы перейти к другому.".to_string(),
+        Msg::CurrentTreeLabel(name) => format!("{} (текущее)", name),
+        Msg::ActiveTree(name) => format!("Текущее дерево — «{}».", name),
+        Msg::TreeCreated(name) => format!("Дерево «{}» создано! Добавьте в дерево кого-нибудь из семьи или напишите своё имя", name),
+        Msg::TreeRenamed(name) => format!("Текущее дерево теперь называется «{}».", name),
+        Msg::SwitchedTo(name) => format!("Текущее дерево — «{}».", name),
+        Msg::TreeGone => "Этого дерева больше нет. Напишите /trees, чтобы увидеть свои деревья.".to_string(),
+        Msg::EmptyTreeName => "Напишите название дерева после команды.".to_string(),
+        Msg::TreeNameTaken(name) => format!("У вас уже есть дерево «{}». Выберите другое название.", name),
+        Msg::TreeNotFound(name) => format!("Дерева «{}» нет. Напишите /trees, чтобы увидеть свои деревья.", name),
+        Msg::TreeDeleted(name) => format!("Дерево «{}» удалено.", name),
+        Msg::TreeLeft(name) => format!("Вы покинули общее дерево «{}».", name),
+        Msg::OnlyOwnerRestarts(name) => format!("Начать заново общее дерево «{}» может только владелец. Напишите /new <название>, чтобы создать своё дерево.", name),
+        Msg::ViewOnly(name) => format!("Дерево «{}» можно только просматривать. Попросите владельца прислать приглашение на редактирование.", name),
+        Msg::ShareUsage => "Напишите /share view, чтобы родственники видели дерево, или /share edit, чтобы они тоже могли добавлять родных.".to_string(),
+        Msg::ShareLink(name, can_edit, link) => format!("Отправьте эту ссылку родственникам, которые будут {} «{}»:\n{}", rights(can_edit), name, link),
+        Msg::OnlyOwnerShares(name) => format!("Поделиться деревом «{}» может только владелец.", name),
+        Msg::Joined(name, can_edit) => format!("Вы присоединились к дереву «{}» и можете {} его. Напишите /finish, чтобы увидеть схему.", name, rights(can_edit)),
+        Msg::InviteInvalid => "Эта ссылка-приглашение больше не действует. Попросите новую.".to_string(),
+        Msg::PeopleAdded(author, added, name) => format!("{} добавляет в общее дерево «{}»: {}.", author, name, added.join(", ")),
+        Msg::MemberJoined(author, name) => format!("{} присоединяется к дереву «{}».", author, name),
+        Msg::TreeDeletedBy(author, name) => format!("{} удаляет общее дерево «{}».", author, name),
+        Msg::TreeLeftBy(author, name) => format!("{} покидает общее дерево «{}».", author, name),
+        Msg::Warning(problem) => format!("⚠️ {} Вероятно, один из ответов был неверным.", issue(problem)),
+        Msg::RemoveLink => "Удалить последнюю связь".to_string(),
+        Msg::KeepAsIs => "Оставить как есть".to_string(),
+        Msg::Unlinked(parent, child) => format!("{} больше не родитель: {}.", parent, child),
+        Msg::AlreadyUnlinked => "Эта связь уже удалена.".to_string(),
+        Msg::WarningOutdated => "С момента этого предупреждения дерево изменилось, поэтому связь оставлена.".to_string(),
+        Msg::NotYourQuestion => "Этот вопрос задан другому участнику".to_string(),
+        Msg::UnknownButton => "Не удалось распознать команду".to_string(),
+        Msg::ChooseTheme => "Выберите оформление схемы.".to_string(),
+        Msg::ThemeChanged(name) => format!("Теперь схемы оформлены в теме «{}». Напишите /finish, чтобы посмотреть.", name),
+        Msg::UnknownTheme(name) => format!("Темы «{}» нет. Выберите одну из тем ниже.", name),
+        Msg::ChooseRetention => "Выберите, сколько хранить ваши деревья, если их никто не открывает. Общее дерево хранится столько, сколько хочет любой из его участников.".to_string(),
+        Msg::RetentionOption(retention) => match retention {
+            Retention::Default => "Как обычно",
+            Retention::Month => "Месяц",
+            Retention::Forever => "Пока не удалю",
+        }.to_string(),
+        Msg::RetentionChanged(retention) => match retention {
+            Retention::Default => "Ваши деревья удаляются как обычно, если их никто не открывает.",
+            Retention::Month => "Ваши деревья хранятся месяц после того, как их кто-нибудь открывал.",
+            Retention::Forever => "Ваши деревья хранятся, пока вы не удалите их командой /delete_tree.",
+        }.to_string(),
+        Msg::UnknownRetention(text) => format!("Варианта «{}» нет. Выберите, сколько хранить деревья, ниже.", text),
+        Msg::ChooseLanguage => "Выберите язык бота.".to_string(),
+        Msg::LanguageChanged => "Теперь я говорю по-русски.".to_string(),
+        Msg::UnknownLanguage(language) => format!("Я пока не говорю на языке «{}». Выберите один из языков ниже.", language),
+        Msg::Someone => "Кто-то".to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn genitive(text: &str) -> String {
+        name(&PersonName::parse(text), Case::Genitive)
+    }
+
+    #[test]
+    fn given_names() {
+        assert_eq!(genitive("Анна"), "Анны");
+        assert_eq!(genitive("Ольга"), "Ольги");
+        assert_eq!(genitive("Мария"), "Марии");
+        assert_eq!(genitive("Андрей"), "Андрея");
+        assert_eq!(genitive("Игорь"), "Игоря");
+        assert_eq!(genitive("Никита"), "Никиты");
+        assert_eq!(genitive("Robert"), "Robert", "Should keep names in latin letters");
+    }
+
+    #[test]
+    fn statements_decline_the_relative() {
+        let statement = Proposal {
+            person: PersonName::parse("Мария"),
+            kinship: Kinship::Grandparent,
+            relative: PersonName::parse("Роберт Смирнов"),
+            sex: Some(Sex::Female),
+            year: None,
+            new_people: vec!["Мария".to_string()],
+            through_unknown: true,
+        };
+        assert_eq!(proposal(&statement), "Мария — бабушка Роберта Смирнова. Новые в дереве: Мария. Их связывает родитель, чьё имя неизвестно.");
+    }
+
+    #[test]
+    fn surnames_follow_gender() {
+        assert_eq!(genitive("Иван Петров"), "Ивана Петрова");
+        assert_eq!(genitive("Анна Петрова"), "Анны Петровой");
+        assert_eq!(genitive("Алексей Толстой"), "Алексея Толстого", "Should decline adjective surnames");
+        assert_eq!(genitive("Софья Ковалевская"), "Софьи Ковалевской");
+        assert_eq!(genitive("Мария Шевчук"), "Марии Шевчук", "Should keep female surnames ending with a consonant");
+        assert_eq!(genitive("Тарас Шевченко"), "Тараса Шевченко");
+        assert_eq!(genitive("Анна Римская-Корсакова"), "Анны Римской-Корсаковой");
+    }
+
+    #[test]
+    fn years_agree_with_number() {
+        assert_eq!(years(1), "1 год");
+        assert_eq!(years(3), "3 года");
+        assert_eq!(years(11), "11 лет");
+        assert_eq!(years(21), "21 год");
+        assert_eq!(people(3), "3 человека");
+    }
+}
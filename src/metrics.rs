@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::error::BotError;
+
+/// Upper bounds of the render latency buckets in seconds, graphviz takes seconds on big trees
+const RENDER_BUCKETS: [f64; 7] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// Renders up to each bound, not cumulative
+    buckets: [u64; RENDER_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// Counters exposed on /metrics in the Prometheus text format.
+/// Locks are held only to bump a number, never across awaits.
+#[derive(Default)]
+pub struct Metrics {
+    updates: AtomicU64,
+    rejected: AtomicU64,
+    commands: Mutex<BTreeMap<&'static str, u64>>,
+    errors: Mutex<BTreeMap<&'static str, u64>>,
+    renders: Mutex<Histogram>,
+}
+
+fn bump(counters: &Mutex<BTreeMap<&'static str, u64>>, label: &'static str) {
+    *counters.lock().expect("metrics lock is never poisoned").entry(label).or_insert(0) += 1;
+}
+
+fn write_counters(out: &mut String, name: &str, help: &str, label: &str, counters: &Mutex<BTreeMap<&'static str, u64>>) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+    for (value, count) in counters.lock().expect("metrics lock is never poisoned").iter() {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, value, count);
+    }
+}
+
+impl Metrics {
+    pub fn update_received(&self) {
+        self.updates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts the webhook request with a wrong secret, returning how many there were
+    pub fn webhook_rejected(&self) -> u64 {
+        self.rejected.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn command_handled(&self, command: &'static str) {
+        bump(&self.commands, command);
+    }
+
+    pub fn error(&self, error: &BotError) {
+        bump(&self.errors, error.kind());
+    }
+
+    pub fn chart_rendered(&self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        let mut renders = self.renders.lock().expect("metrics lock is never poisoned");
+        if let Some(bucket) = RENDER_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            renders.buckets[bucket] += 1;
+        }
+        renders.count += 1;
+        renders.sum += seconds;
+    }
+
+    /// Text for Prometheus to scrape, the number of trees in memory is passed in since it's counted by the storage
+    pub fn render(&self, trees: usize) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP pedigree_updates_received_total Updates received from Telegram\n# TYPE pedigree_updates_received_total counter");
+        let _ = writeln!(out, "pedigree_updates_received_total {}", self.updates.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# HELP pedigree_webhook_rejected_total Webhook requests with a wrong secret token\n# TYPE pedigree_webhook_rejected_total counter");
+        let _ = writeln!(out, "pedigree_webhook_rejected_total {}", self.rejected.load(Ordering::Relaxed));
+        write_counters(&mut out, "pedigree_commands_total", "Commands handled", "command", &self.commands);
+        write_counters(&mut out, "pedigree_errors_total", "Updates not handled because of an error", "kind", &self.errors);
+
+        let renders = self.renders.lock().expect("metrics lock is never poisoned");
+        let _ = writeln!(out, "# HELP pedigree_render_seconds Time graphviz took to draw a chart\n# TYPE pedigree_render_seconds histogram");
+        let mut cumulative = 0;
+        for (bound, count) in RENDER_BUCKETS.iter().zip(renders.buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(out, "pedigree_render_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+        }
+        let _ = writeln!(out, "pedigree_render_seconds_bucket{{le=\"+Inf\"}} {}", renders.count);
+        let _ = writeln!(out, "pedigree_render_seconds_sum {}", renders.sum);
+        let _ = writeln!(out, "pedigree_render_seconds_count {}", renders.count);
+
+        let _ = writeln!(out, "# HELP pedigree_trees Trees kept in memory\n# TYPE pedigree_trees gauge");
+        let _ = writeln!(out, "pedigree_trees {}", trees);
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_exposed() {
+        let metrics = Metrics::default();
+        metrics.update_received();
+        metrics.update_received();
+        metrics.command_handled("start");
+        metrics.command_handled("finish");
+        metrics.command_handled("start");
+        metrics.error(&BotError::Render("dot exited".to_string()));
+        let text = metrics.render(3);
+        for line in ["pedigree_updates_received_total 2", "pedigree_commands_total{command=\"finish\"} 1", "pedigree_commands_total{command=\"start\"} 2",
+                     "pedigree_errors_total{kind=\"render\"} 1", "pedigree_trees 3"] {
+            assert!(text.lines().any(|exposed| exposed == line), "Should expose {}", line);
+        }
+    }
+
+    #[test]
+    fn render_buckets_are_cumulative() {
+        let metrics = Metrics::default();
+        metrics.chart_rendered(Duration::from_millis(200));
+        metrics.chart_rendered(Duration::from_millis(700));
+        metrics.chart_rendered(Duration::from_secs(30));
+        let text = metrics.render(0);
+        for line in ["pedigree_render_seconds_bucket{le=\"0.1\"} 0", "pedigree_render_seconds_bucket{le=\"0.25\"} 1", "pedigree_render_seconds_bucket{le=\"1\"} 2",
+                     "pedigree_render_seconds_bucket{le=\"10\"} 2", "pedigree_render_seconds_bucket{le=\"+Inf\"} 3", "pedigree_render_seconds_count 3"] {
+            assert!(text.lines().any(|exposed| exposed == line), "Should expose {}", line);
+        }
+    }
+}
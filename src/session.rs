@@ -0,0 +1,323 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::updater::graph_updater::GraphUpdater;
+
+pub const DEFAULT_TREE_NAME: &str = "My tree";
+
+pub type TreeId = u64;
+
+const MONTH: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Access {
+    View,
+    Edit,
+    Owner,
+}
+
+impl Access {
+    pub fn can_edit(&self) -> bool {
+        *self >= Access::Edit
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Access::View => "view",
+            Access::Edit => "edit",
+            Access::Owner => "owner",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        [Access::View, Access::Edit, Access::Owner].into_iter().find(|access| access.code() == code)
+    }
+}
+
+/// How long the trees of a chat are kept while nobody uses them, a tree follows the longest choice of its members
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Retention {
+    /// For `CLEAR_SESSION_HOURS`
+    #[default]
+    Default,
+    Month,
+    /// Until the owner deletes the tree
+    Forever,
+}
+
+impl Retention {
+    pub const ALL: [Retention; 3] = [Retention::Default, Retention::Month, Retention::Forever];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Retention::Default => "default",
+            Retention::Month => "month",
+            Retention::Forever => "forever",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().to_lowercase();
+        Retention::ALL.into_iter().find(|retention| retention.code() == text)
+    }
+
+    /// How long an unused tree is kept, None if it's never cleared
+    pub fn max_age(&self, default: Duration) -> Option<Duration> {
+        match self {
+            Retention::Default => Some(default),
+            Retention::Month => Some(default.max(MONTH)),
+            Retention::Forever => None,
+        }
+    }
+}
+
+/// Tree shared between all its members
+pub struct Tree {
+    pub graph_updater: GraphUpdater,
+    pub members: BTreeMap<i64, Access>,
+}
+
+impl Tree {
+    pub fn new(owner: i64) -> Self {
+        let mut members = BTreeMap::new();
+        members.insert(owner, Access::Owner);
+        Self { graph_updater: GraphUpdater::new(), members }
+    }
+
+    pub fn restart(&mut self) {
+        self.graph_updater.clear();
+    }
+
+    pub fn collaborators(&self, except: i64) -> Vec<i64> {
+        self.members.keys().copied().filter(|member| *member != except).collect()
+    }
+}
+
+/// Chat's own view of a tree: how the chat called it and what its members may do with it
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeEntry {
+    pub name: String,
+    pub access: Access,
+    /// Last question message sent to each user of the chat
+    pub questions: BTreeMap<i64, i32>,
+}
+
+impl TreeEntry {
+    pub fn new(name: String, access: Access) -> Self { Self { name, access, questions: BTreeMap::new() } }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TreeIndexError {
+    EmptyName,
+    NameTaken(String),
+    NotFound(String),
+}
+
+/// Trees available to a private or group chat with the one currently being filled in
+pub struct UserTrees {
+    trees: BTreeMap<TreeId, TreeEntry>,
+    active: Option<TreeId>,
+}
+
+impl UserTrees {
+    pub fn new() -> Self { Self { trees: BTreeMap::new(), active: None } }
+
+    /// Trimmed name unless it's empty or another tree than `renamed` is called so
+    fn validate_name(&self, name: &str, renamed: Option<TreeId>) -> Result<String, TreeIndexError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(TreeIndexError::EmptyName);
+        }
+        if self.find(name).is_some_and(|id| Some(id) != renamed) {
+            return Err(TreeIndexError::NameTaken(name.to_string()));
+        }
+        Ok(name.to_string())
+    }
+
+    /// First of "My tree", "My tree 2", ... that the user doesn't have yet
+    pub fn default_name(&self) -> String {
+        (1..)
+            .map(|i| if i == 1 { DEFAULT_TREE_NAME.to_string() } else { format!("{} {}", DEFAULT_TREE_NAME, i) })
+            .find(|name| self.find(name).is_none())
+            .expect("there's always a free default name")
+    }
+
+    pub fn add(&mut self, id: TreeId, name: &str, access: Access) -> Result<&mut TreeEntry, TreeIndexError> {
+        let name = self.validate_name(name, None)?;
+        self.active = Some(id);
+        Ok(self.trees.entry(id).or_insert_with(|| TreeEntry::new(name, access)))
+    }
+
+    /// Adds a tree shared by someone else, renaming it if the user already has a tree with such name.
+    /// Joining a tree the user already has only upgrades the access.
+    pub fn add_shared(&mut self, id: TreeId, name: &str, access: Access) -> &TreeEntry {
+        let name = (1..)
+            .map(|i| if i == 1 { name.to_string() } else { format!("{} ({})", name, i) })
+            .find(|name| self.find(name).is_none())
+            .expect("there's always a free name");
+        self.active = Some(id);
+        let entry = self.trees.entry(id).or_insert_with(|| TreeEntry::new(name, access));
+        if entry.access < access {
+            entry.access = access;
+        }
+        entry
+    }
+
+    pub fn find(&self, name: &str) -> Option<TreeId> {
+        let name = name.trim().to_lowercase();
+        self.trees.iter()
+            .find(|(_, tree)| tree.name.to_lowercase() == name)
+            .map(|(id, _)| *id)
+    }
+
+    pub fn get(&self, id: TreeId) -> Option<&TreeEntry> {
+        self.trees.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: TreeId) -> Option<&mut TreeEntry> {
+        self.trees.get_mut(&id)
+    }
+
+    pub fn active(&self) -> Option<(TreeId, &TreeEntry)> {
+        self.active.and_then(|id| self.trees.get(&id).map(|tree| (id, tree)))
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut TreeEntry> {
+        match self.active {
+            Some(id) => self.trees.get_mut(&id),
+            None => None
+        }
+    }
+
+    pub fn is_active(&self, id: TreeId) -> bool {
+        self.active == Some(id)
+    }
+
+    pub fn switch(&mut self, id: TreeId) -> Option<&mut TreeEntry> {
+        let tree = self.trees.get_mut(&id)?;
+        self.active = Some(id);
+        Some(tree)
+    }
+
+    pub fn rename_active(&mut self, name: &str) -> Result<&TreeEntry, TreeIndexError> {
+        let name = self.validate_name(name, self.active)?;
+        match self.active_mut() {
+            Some(tree) => {
+                tree.name = name;
+                Ok(tree)
+            }
+            None => Err(TreeIndexError::NotFound(name))
+        }
+    }
+
+    /// Removes the tree from the index and switches to the oldest remaining one if the removed tree was active
+    pub fn remove(&mut self, name: &str) -> Result<(TreeId, TreeEntry), TreeIndexError> {
+        let id = self.find(name).ok_or_else(|| TreeIndexError::NotFound(name.trim().to_string()))?;
+        Ok((id, self.forget(id).expect("found tree is indexed")))
+    }
+
+    pub fn forget(&mut self, id: TreeId) -> Option<TreeEntry> {
+        let tree = self.trees.remove(&id)?;
+        if self.active == Some(id) {
+            self.active = self.trees.keys().next().copied();
+        }
+        Some(tree)
+    }
+
+    /// Forgets trees that don't exist anymore, e.g. deleted by their owners or expired
+    pub fn retain<F: Fn(TreeId) -> bool>(&mut self, exists: F) {
+        let gone: Vec<TreeId> = self.trees.keys().copied().filter(|id| !exists(*id)).collect();
+        for id in gone {
+            self.forget(id);
+        }
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = (TreeId, &TreeEntry)> {
+        self.trees.iter().map(|(id, tree)| (*id, tree))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trees.is_empty()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_ages() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert_eq!(Retention::parse(" Month"), Some(Retention::Month));
+        assert_eq!(Retention::parse("year"), None);
+        assert_eq!(Retention::Default.max_age(day), Some(day));
+        assert_eq!(Retention::Month.max_age(day), Some(MONTH));
+        assert_eq!(Retention::Month.max_age(day * 60), Some(day * 60), "Should not keep trees shorter than by default");
+        assert_eq!(Retention::Forever.max_age(day), None);
+    }
+
+    #[test]
+    fn add_and_switch() {
+        let mut trees = UserTrees::new();
+        trees.add(0, "Mother's side", Access::Owner).unwrap();
+        trees.add(1, "Father's side", Access::Owner).unwrap();
+        assert_eq!(trees.active().unwrap().1.name, "Father's side", "Should switch to the new tree");
+        let id = trees.find("mother's side").unwrap();
+        trees.switch(id).unwrap();
+        assert_eq!(trees.active().unwrap().1.name, "Mother's side", "Should switch back by id");
+        assert!(trees.is_active(id));
+    }
+
+    #[test]
+    fn names_are_unique_and_not_empty() {
+        let mut trees = UserTrees::new();
+        trees.add(0, "Smiths", Access::Owner).unwrap();
+        assert_eq!(trees.add(1, "  ", Access::Owner).err(), Some(TreeIndexError::EmptyName));
+        assert_eq!(trees.add(1, "smiths", Access::Owner).err(), Some(TreeIndexError::NameTaken("smiths".to_string())));
+        trees.add(1, "Browns", Access::Owner).unwrap();
+        assert_eq!(trees.rename_active("Smiths").err(), Some(TreeIndexError::NameTaken("Smiths".to_string())));
+        assert_eq!(trees.rename_active("Client").unwrap().name, "Client");
+        assert_eq!(trees.rename_active("CLIENT").unwrap().name, "CLIENT", "Should let the tree change the case of its own name");
+    }
+
+    #[test]
+    fn default_names() {
+        let mut trees = UserTrees::new();
+        assert_eq!(trees.default_name(), DEFAULT_TREE_NAME);
+        trees.add(0, DEFAULT_TREE_NAME, Access::Owner).unwrap();
+        assert_eq!(trees.default_name(), format!("{} 2", DEFAULT_TREE_NAME));
+    }
+
+    #[test]
+    fn shared_trees_keep_names_unique() {
+        let mut trees = UserTrees::new();
+        trees.add(0, "Smiths", Access::Owner).unwrap();
+        assert_eq!(trees.add_shared(1, "Smiths", Access::View).name, "Smiths (2)");
+        assert_eq!(trees.add_shared(1, "Smiths", Access::Edit), &TreeEntry::new("Smiths (2)".to_string(), Access::Edit), "Should upgrade access of the known tree");
+        assert_eq!(trees.add_shared(1, "Smiths", Access::View).access, Access::Edit, "Should never downgrade access");
+        assert!(trees.is_active(1));
+    }
+
+    #[test]
+    fn remove_switches_to_remaining_tree() {
+        let mut trees = UserTrees::new();
+        trees.add(0, "Smiths", Access::Owner).unwrap();
+        trees.add(1, "Browns", Access::Owner).unwrap();
+        assert_eq!(trees.remove("Unknown").err(), Some(TreeIndexError::NotFound("Unknown".to_string())));
+        assert_eq!(trees.remove("browns").unwrap().1.name, "Browns");
+        assert_eq!(trees.active().unwrap().1.name, "Smiths");
+        trees.retain(|id| id != 0);
+        assert!(trees.active().is_none());
+        assert!(trees.is_empty());
+    }
+
+    #[test]
+    fn only_owners_and_editors_can_edit() {
+        assert!(!Access::View.can_edit());
+        assert!(Access::Edit.can_edit());
+        assert!(Access::Owner.can_edit());
+        let tree = Tree::new(1);
+        assert_eq!(tree.collaborators(1), Vec::<i64>::new());
+    }
+}
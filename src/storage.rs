@@ -0,0 +1,584 @@
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use chashmap::{CHashMap, WriteGuard};
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+
+use crate::locale::Lang;
+use crate::updater::chart::Theme;
+use crate::updater::graph_updater::GraphUpdater;
+use crate::updater::table::{read_members, write_members, Table, MEMBERS_FILE_NAME};
+use crate::session::{Access, Retention, Tree, TreeEntry, TreeId, TreeIndexError, UserTrees};
+
+const INVITE_TOKEN_LENGTH: usize = 24;
+
+/// Random alphanumeric string for links and secrets nobody should guess
+pub fn random_token(length: usize) -> String {
+    let mut rng = rand::thread_rng();
+    iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(length)
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct Invite {
+    pub tree_id: TreeId,
+    pub access: Access,
+    pub name: String,
+    pub inviter: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum JoinError {
+    UnknownInvite,
+    TreeDeleted,
+}
+
+/// Language detected from Telegram settings unless the user chose one with /language
+#[derive(Clone, Copy, Debug)]
+struct LanguageChoice {
+    lang: Lang,
+    chosen: bool,
+}
+
+/// When the tree was last used and whether its members were told it's about to expire
+#[derive(Clone, Copy, Debug)]
+struct Activity {
+    last: Instant,
+    warned: bool,
+}
+
+impl Activity {
+    fn now() -> Self { Self { last: Instant::now(), warned: false } }
+}
+
+/// Directory next to `dir` with the suffix added to its name
+fn sibling(dir: &Path, suffix: &str) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    dir.with_file_name(name)
+}
+
+fn copy_files(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        fs::copy(entry.path(), to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Moves `from` in place of `to`, the previous `to` is removed only once the new one is there
+fn replace_dir(from: &Path, to: &Path) -> io::Result<()> {
+    let old = sibling(to, "old");
+    if old.exists() {
+        fs::remove_dir_all(&old)?;
+    }
+    if to.exists() {
+        fs::rename(to, &old)?;
+    }
+    fs::rename(from, to)?;
+    if old.exists() {
+        fs::remove_dir_all(&old)?;
+    }
+    Ok(())
+}
+
+/// Chat id, access, the name the chat gave the tree and the retention the chat chose
+type Member = (i64, Access, String, Retention);
+
+/// Tree saved by `Storage::persist` along with its members, None if any of its files can't be read
+fn read_tree(dir: &Path) -> Option<(Tree, Vec<Member>)> {
+    let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+    let graph_updater = GraphUpdater::from_tables(&read(Table::People.file_name())?, &read(Table::Relationships.file_name())?).ok()?;
+    let members = read_members(&read(MEMBERS_FILE_NAME)?)?.into_iter()
+        .map(|(chat_id, access, name, retention)| Some((chat_id, Access::from_code(&access)?, name, Retention::parse(&retention).unwrap_or_default())))
+        .collect::<Option<Vec<_>>>()?;
+    let access = members.iter().map(|(chat_id, access, _, _)| (*chat_id, *access)).collect();
+    Some((Tree { graph_updater, members: access }, members))
+}
+
+/// Tables uploaded for import, the first one waits until the other arrives
+#[derive(Default)]
+struct PendingImport {
+    people: Option<String>,
+    relationships: Option<String>,
+}
+
+/// In-memory storage of all trees, tree indexes of chats and share invites.
+/// Chashmap guards are never held across awaits, tree is locked by its own async mutex instead
+/// so edits of a shared tree are serialized.
+pub struct Storage {
+    users: CHashMap<i64, UserTrees>,
+    trees: CHashMap<TreeId, Arc<Mutex<Tree>>>,
+    invites: CHashMap<String, Invite>,
+    languages: CHashMap<i64, LanguageChoice>,
+    themes: CHashMap<i64, Theme>,
+    retentions: CHashMap<i64, Retention>,
+    imports: CHashMap<i64, PendingImport>,
+    activity: CHashMap<TreeId, Activity>,
+    next_tree_id: AtomicU64,
+}
+
+impl Storage {
+    pub fn new() -> Self { Self { users: CHashMap::new(), trees: CHashMap::new(), invites: CHashMap::new(), languages: CHashMap::new(), themes: CHashMap::new(), retentions: CHashMap::new(), imports: CHashMap::new(), activity: CHashMap::new(), next_tree_id: AtomicU64::new(0) } }
+
+    /// Tree index of the private or group chat, created on the first access
+    pub fn user(&self, chat_id: i64) -> WriteGuard<'_, i64, UserTrees> {
+        self.users.upsert(chat_id, UserTrees::new, |_| {});
+        let mut user = self.users.get_mut(&chat_id).expect("user trees were just inserted");
+        user.retain(|id| self.trees.contains_key(&id));
+        user
+    }
+
+    pub fn tree_count(&self) -> usize {
+        self.trees.len()
+    }
+
+    /// Tree used by one of its members, which postpones its expiry
+    pub fn tree(&self, id: TreeId) -> Option<Arc<Mutex<Tree>>> {
+        let tree = self.trees.get(&id).map(|tree| tree.clone())?;
+        self.activity.insert(id, Activity::now());
+        Some(tree)
+    }
+
+    pub fn active_tree(&self, chat_id: i64) -> Option<(TreeId, TreeEntry, Arc<Mutex<Tree>>)> {
+        let user = self.user(chat_id);
+        let (id, entry) = user.active()?;
+        Some((id, entry.clone(), self.tree(id)?))
+    }
+
+    /// Tree the chat is a member of, others can't use it or postpone its expiry
+    pub fn member_tree(&self, chat_id: i64, id: TreeId) -> Option<(TreeEntry, Arc<Mutex<Tree>>)> {
+        let entry = self.user(chat_id).get(id)?.clone();
+        Some((entry, self.tree(id)?))
+    }
+
+    pub fn create_tree(&self, chat_id: i64, name: &str) -> Result<TreeEntry, TreeIndexError> {
+        let id = self.next_tree_id.fetch_add(1, Ordering::SeqCst);
+        let mut user = self.user(chat_id);
+        let entry = user.add(id, name, Access::Owner)?.clone();
+        self.trees.insert(id, Arc::new(Mutex::new(Tree::new(chat_id))));
+        self.activity.insert(id, Activity::now());
+        Ok(entry)
+    }
+
+    pub fn create_default_tree(&self, chat_id: i64) -> TreeEntry {
+        let name = self.user(chat_id).default_name();
+        self.create_tree(chat_id, &name).expect("default tree name is unique")
+    }
+
+    /// Removes the tree along with its invites. Members forget it on the next access.
+    pub fn delete_tree(&self, id: TreeId) -> Option<Arc<Mutex<Tree>>> {
+        self.invites.retain(|_, invite| invite.tree_id != id);
+        self.activity.remove(&id);
+        self.trees.remove(&id)
+    }
+
+    pub fn set_question(&self, chat_id: i64, tree_id: TreeId, user_id: i64, msg_id: i32) {
+        if let Some(entry) = self.user(chat_id).get_mut(tree_id) {
+            entry.questions.insert(user_id, msg_id);
+        }
+    }
+
+    pub fn share(&self, tree_id: TreeId, access: Access, name: &str, inviter: i64) -> String {
+        let token = random_token(INVITE_TOKEN_LENGTH);
+        self.invites.insert(token.clone(), Invite { tree_id, access, name: name.to_string(), inviter });
+        token
+    }
+
+    pub async fn join(&self, token: &str, chat_id: i64) -> Result<(Invite, TreeEntry), JoinError> {
+        let invite = self.invites.get(token).map(|invite| invite.clone()).ok_or(JoinError::UnknownInvite)?;
+        let tree = self.tree(invite.tree_id).ok_or(JoinError::TreeDeleted)?;
+        {
+            let mut tree = tree.lock().await;
+            let access = tree.members.entry(chat_id).or_insert(invite.access);
+            if *access < invite.access {
+                *access = invite.access;
+            }
+        }
+        let entry = self.user(chat_id).add_shared(invite.tree_id, &invite.name, invite.access).clone();
+        Ok((invite, entry))
+    }
+
+    /// Language of the user, following their Telegram `language_code` until they choose one.
+    /// Without the code, e.g. for notifications, the last known language is used.
+    pub fn language(&self, user_id: i64, language_code: Option<&str>) -> Lang {
+        match (self.languages.get(&user_id).map(|choice| *choice), language_code) {
+            (Some(choice), _) if choice.chosen => choice.lang,
+            (_, Some(code)) => {
+                let lang = Lang::detect(Some(code));
+                self.languages.insert(user_id, LanguageChoice { lang, chosen: false });
+                lang
+            }
+            (Some(choice), None) => choice.lang,
+            (None, None) => Lang::En
+        }
+    }
+
+    pub fn set_language(&self, user_id: i64, lang: Lang) {
+        self.languages.insert(user_id, LanguageChoice { lang, chosen: true });
+    }
+
+    /// Chart theme of the chat
+    pub fn theme(&self, chat_id: i64) -> Theme {
+        self.themes.get(&chat_id).map(|theme| *theme).unwrap_or_default()
+    }
+
+    pub fn set_theme(&self, chat_id: i64, theme: Theme) {
+        self.themes.insert(chat_id, theme);
+    }
+
+    /// How long the chat wants its trees kept while nobody uses them
+    pub fn retention(&self, chat_id: i64) -> Retention {
+        self.retentions.get(&chat_id).map(|retention| *retention).unwrap_or_default()
+    }
+
+    pub fn set_retention(&self, chat_id: i64, retention: Retention) {
+        self.retentions.insert(chat_id, retention);
+    }
+
+    /// How long a tree with these members is kept unused, the longest retention among them wins
+    fn max_age(&self, members: &[i64], default: Duration) -> Option<Duration> {
+        members.iter().map(|chat_id| self.retention(*chat_id)).max().unwrap_or_default().max_age(default)
+    }
+
+    /// Keeps the uploaded table, returning people and relationships once the chat has sent both
+    pub fn add_import_table(&self, chat_id: i64, table: Table, text: String) -> Option<(String, String)> {
+        self.imports.upsert(chat_id, PendingImport::default, |_| {});
+        let complete = {
+            let mut pending = self.imports.get_mut(&chat_id)?;
+            match table {
+                Table::People => pending.people = Some(text),
+                Table::Relationships => pending.relationships = Some(text),
+            }
+            pending.people.is_some() && pending.relationships.is_some()
+        };
+        if !complete {
+            return None;
+        }
+        let pending = self.imports.remove(&chat_id)?;
+        Some((pending.people?, pending.relationships?))
+    }
+
+    /// Writes every tree as the tables of /export csv along with its members, one directory per tree.
+    /// The trees are written next to `dir` and replace what the previous shutdown wrote only once all of them are saved.
+    /// A tree an abandoned handler still holds after `wait` keeps its previous copy.
+    pub async fn persist(&self, dir: &Path, wait: Duration) -> io::Result<usize> {
+        let written = sibling(dir, "new");
+        if written.exists() {
+            fs::remove_dir_all(&written)?;
+        }
+        fs::create_dir_all(&written)?;
+        let mut persisted = 0;
+        for (id, tree) in self.trees.clone() {
+            let tree_dir = written.join(id.to_string());
+            let tree = match timeout(wait, tree.lock()).await {
+                Ok(tree) => tree,
+                Err(_) => {
+                    let previous = dir.join(id.to_string());
+                    if previous.exists() {
+                        log::warn!("Tree {} is still locked, its copy saved before is kept", id);
+                        copy_files(&previous, &tree_dir)?;
+                    } else {
+                        log::warn!("Tree {} is still locked, it's not saved", id);
+                    }
+                    continue;
+                }
+            };
+            let (people, relationships) = tree.graph_updater.export_tables();
+            // looked up without `user`, which would add chats while the bot is stopping
+            let names: Vec<(i64, &str, String, &str)> = tree.members.iter()
+                .map(|(chat_id, access)| {
+                    let name = self.users.get(chat_id).and_then(|user| user.get(id).map(|entry| entry.name.clone()));
+                    (*chat_id, access.code(), name.unwrap_or_default(), self.retention(*chat_id).code())
+                })
+                .collect();
+            fs::create_dir(&tree_dir)?;
+            fs::write(tree_dir.join(Table::People.file_name()), people)?;
+            fs::write(tree_dir.join(Table::Relationships.file_name()), relationships)?;
+            fs::write(tree_dir.join(MEMBERS_FILE_NAME), write_members(names.iter().map(|(chat_id, access, name, retention)| (*chat_id, *access, name.as_str(), *retention))))?;
+            persisted += 1;
+        }
+        replace_dir(&written, dir)?;
+        Ok(persisted)
+    }
+
+    /// Puts back the trees `persist` has saved with their ids, members, the names members gave them and their retention.
+    /// A tree whose files can't be read is left out, so the rest of them still come back.
+    pub fn restore(&self, dir: &Path) -> io::Result<usize> {
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let mut ids = vec![];
+        for entry in fs::read_dir(dir)? {
+            if let Some(id) = entry?.file_name().to_str().and_then(|name| name.parse::<TreeId>().ok()) {
+                ids.push(id);
+            }
+        }
+        // the chats end up in their latest tree
+        ids.sort_unstable();
+        let mut restored = 0;
+        for id in ids {
+            let (tree, members) = match read_tree(&dir.join(id.to_string())) {
+                Some(tree) => tree,
+                None => {
+                    log::warn!("Tree {} can't be read, it's not restored", id);
+                    continue;
+                }
+            };
+            self.trees.insert(id, Arc::new(Mutex::new(tree)));
+            self.activity.insert(id, Activity::now());
+            self.next_tree_id.fetch_max(id + 1, Ordering::SeqCst);
+            for (chat_id, access, name, retention) in members {
+                self.user(chat_id).add_shared(id, &name, access);
+                self.retentions.insert(chat_id, retention);
+            }
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    /// Trees unused for longer than their retention minus `warning`, at most half of it, along with their members and the time left.
+    /// Trees follow the longest retention chosen by their members, `max_age` by default.
+    /// Every tree is returned once, then it's marked as warned until someone uses it again or a member keeps their trees longer.
+    /// Trees being edited right now are skipped since they are about to be used.
+    pub fn expiring(&self, max_age: Duration, warning: Duration) -> Vec<(TreeId, Vec<i64>, Duration)> {
+        let mut expiring = vec![];
+        for (id, tree) in self.trees.clone() {
+            let members: Vec<i64> = match tree.try_lock() {
+                Ok(tree) => tree.members.keys().copied().collect(),
+                Err(_) => continue
+            };
+            let max_age = self.max_age(&members, max_age);
+            if let Some(mut activity) = self.activity.get_mut(&id) {
+                let idle = activity.last.elapsed();
+                let due = max_age.is_some_and(|max_age| idle + warning.min(max_age / 2) >= max_age);
+                if due && !activity.warned {
+                    expiring.push((id, members, max_age.unwrap_or_default().saturating_sub(idle)));
+                }
+                activity.warned = due;
+            }
+        }
+        expiring
+    }
+
+    /// Drops trees unused for their retention once their members have been warned, unless someone is editing them right now
+    pub fn clear_expired(&self, max_age: Duration) {
+        self.trees.retain(|id, tree| {
+            let members: Vec<i64> = match tree.try_lock() {
+                Ok(tree) => tree.members.keys().copied().collect(),
+                Err(_) => return true
+            };
+            let expired = match self.max_age(&members, max_age) {
+                Some(max_age) => self.activity.get(id).is_none_or(|activity| activity.warned && activity.last.elapsed() >= max_age),
+                None => false
+            };
+            !expired
+        });
+        self.activity.retain(|id, _| self.trees.contains_key(id));
+        self.invites.retain(|_, invite| self.trees.contains_key(&invite.tree_id));
+        self.users.retain(|_, user| user.list().any(|(id, _)| self.trees.contains_key(&id)));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use petgraph::graph::NodeIndex;
+    use crate::updater::model::InputAction;
+
+    #[tokio::test]
+    async fn shared_tree_is_visible_to_invited_user() {
+        let storage = Storage::new();
+        let entry = storage.create_tree(1, "Smiths").unwrap();
+        let (tree_id, _, _) = storage.active_tree(1).unwrap();
+        let token = storage.share(tree_id, Access::Edit, &entry.name, 1);
+
+        let (invite, joined) = storage.join(&token, 2).await.unwrap();
+        assert_eq!(invite.inviter, 1);
+        assert_eq!(joined, TreeEntry::new("Smiths".to_string(), Access::Edit));
+        let (joined_id, _, tree) = storage.active_tree(2).unwrap();
+        assert_eq!(joined_id, tree_id, "Should switch to the shared tree");
+        assert_eq!(tree.lock().await.collaborators(2), vec![1]);
+        assert_eq!(storage.join("wrong", 2).await.err(), Some(JoinError::UnknownInvite));
+    }
+
+    #[tokio::test]
+    async fn deleted_tree_is_forgotten_by_members() {
+        let storage = Storage::new();
+        storage.create_default_tree(1);
+        let (tree_id, _, _) = storage.active_tree(1).unwrap();
+        let token = storage.share(tree_id, Access::View, "Smiths", 1);
+        storage.join(&token, 2).await.unwrap();
+
+        storage.delete_tree(tree_id);
+        assert!(storage.active_tree(2).is_none());
+        assert!(storage.user(2).is_empty());
+        assert_eq!(storage.join(&token, 3).await.err(), Some(JoinError::UnknownInvite), "Should remove invites of deleted tree");
+    }
+
+    #[test]
+    fn chosen_language_overrides_telegram_settings() {
+        let storage = Storage::new();
+        assert_eq!(storage.language(1, None), Lang::En);
+        assert_eq!(storage.language(1, Some("ru")), Lang::Ru);
+        assert_eq!(storage.language(1, None), Lang::Ru, "Should remember detected language for notifications");
+        storage.set_language(1, Lang::De);
+        assert_eq!(storage.language(1, Some("ru")), Lang::De);
+    }
+
+    #[test]
+    fn import_waits_for_both_tables() {
+        let storage = Storage::new();
+        assert_eq!(storage.add_import_table(1, Table::Relationships, "old".to_string()), None);
+        assert_eq!(storage.add_import_table(1, Table::Relationships, "relationships".to_string()), None);
+        assert_eq!(storage.add_import_table(2, Table::People, "other chat".to_string()), None);
+        assert_eq!(storage.add_import_table(1, Table::People, "people".to_string()), Some(("people".to_string(), "relationships".to_string())));
+        assert_eq!(storage.add_import_table(1, Table::People, "people".to_string()), None, "Should start over after the import");
+    }
+
+    #[test]
+    fn expired_trees_are_cleared_after_warning() {
+        let storage = Storage::new();
+        storage.create_default_tree(1);
+        storage.clear_expired(Duration::from_secs(0));
+        assert!(storage.active_tree(1).is_some(), "Should warn before clearing");
+        assert!(storage.expiring(Duration::from_secs(60), Duration::from_secs(30)).is_empty());
+        let expiring = storage.expiring(Duration::from_secs(0), Duration::from_secs(0));
+        assert_eq!(expiring.iter().map(|(_, members, _)| members.clone()).collect::<Vec<_>>(), vec![vec![1]]);
+        assert!(storage.expiring(Duration::from_secs(0), Duration::from_secs(0)).is_empty(), "Should warn once");
+        storage.clear_expired(Duration::from_secs(0));
+        assert!(storage.active_tree(1).is_none());
+    }
+
+    #[tokio::test]
+    async fn trees_are_persisted_with_members() {
+        let storage = Storage::new();
+        storage.create_tree(1, "Smiths").unwrap();
+        let (tree_id, _, _) = storage.active_tree(1).unwrap();
+        let token = storage.share(tree_id, Access::Edit, "Smiths", 1);
+        storage.join(&token, 2).await.unwrap();
+        let dir = std::env::temp_dir().join("pedigree-bot-persist-test");
+        fs::create_dir_all(dir.join("99")).unwrap();
+
+        assert_eq!(storage.persist(&dir, Duration::from_secs(1)).await.unwrap(), 1);
+        let tree_dir = dir.join(tree_id.to_string());
+        assert!(tree_dir.join("people.csv").exists());
+        assert!(tree_dir.join("relationships.csv").exists());
+        assert_eq!(fs::read_to_string(tree_dir.join(MEMBERS_FILE_NAME)).unwrap(), "\u{FEFF}chat,access,tree_name,retention\r\n1,owner,Smiths,default\r\n2,edit,Smiths,default\r\n");
+        assert!(!dir.join("99").exists(), "Should replace trees saved earlier");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn persisted_trees_are_restored() {
+        let storage = Storage::new();
+        storage.create_tree(1, "Smiths").unwrap();
+        let (tree_id, _, tree) = storage.active_tree(1).unwrap();
+        tree.lock().await.graph_updater.handle_command(1, InputAction::Text("Robert")).unwrap();
+        let token = storage.share(tree_id, Access::View, "Smiths", 1);
+        storage.join(&token, 2).await.unwrap();
+        storage.user(2).rename_active("Cousins").unwrap();
+        storage.set_retention(2, Retention::Forever);
+        let dir = std::env::temp_dir().join("pedigree-bot-restore-test");
+        storage.persist(&dir, Duration::from_secs(1)).await.unwrap();
+
+        let restored = Storage::new();
+        assert_eq!(restored.restore(&dir).unwrap(), 1);
+        let (id, entry, tree) = restored.active_tree(2).unwrap();
+        assert_eq!((id, entry), (tree_id, TreeEntry::new("Cousins".to_string(), Access::View)), "Should keep the name the member gave the tree");
+        assert_eq!(restored.active_tree(1).unwrap().1, TreeEntry::new("Smiths".to_string(), Access::Owner));
+        assert_eq!(tree.lock().await.graph_updater.person_name(NodeIndex::new(0)), "Robert");
+        assert_eq!((restored.retention(1), restored.retention(2)), (Retention::Default, Retention::Forever));
+        restored.create_tree(1, "Browns").unwrap();
+        assert_ne!(restored.active_tree(1).unwrap().0, tree_id, "Should not give the restored id to a new tree");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn locked_tree_keeps_its_saved_copy() {
+        let storage = Storage::new();
+        storage.create_tree(1, "Smiths").unwrap();
+        let (tree_id, _, tree) = storage.active_tree(1).unwrap();
+        let dir = std::env::temp_dir().join("pedigree-bot-locked-test");
+        storage.persist(&dir, Duration::from_secs(1)).await.unwrap();
+        storage.create_tree(3, "Browns").unwrap();
+        fs::write(dir.join(tree_id.to_string()).join("marker"), "saved before").unwrap();
+
+        let _editing = tree.lock().await;
+        assert_eq!(storage.persist(&dir, Duration::from_millis(10)).await.unwrap(), 1);
+        assert_eq!(fs::read_to_string(dir.join(tree_id.to_string()).join("marker")).unwrap(), "saved before", "Should keep the previous copy of the locked tree");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        assert!(!sibling(&dir, "new").exists() && !sibling(&dir, "old").exists(), "Should leave nothing next to the trees");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn persisting_adds_no_chats() {
+        let storage = Storage::new();
+        storage.create_tree(1, "Smiths").unwrap();
+        let (tree_id, _, tree) = storage.active_tree(1).unwrap();
+        tree.lock().await.members.insert(2, Access::View);
+        let dir = std::env::temp_dir().join("pedigree-bot-no-chats-test");
+        storage.persist(&dir, Duration::from_secs(1)).await.unwrap();
+        assert!(storage.users.get(&2).is_none(), "Should only read the names of the members");
+        assert!(fs::read_to_string(dir.join(tree_id.to_string()).join(MEMBERS_FILE_NAME)).unwrap().ends_with("\r\n2,view,,default\r\n"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shared_tree_follows_the_longest_retention() {
+        let storage = Storage::new();
+        storage.create_tree(1, "Smiths").unwrap();
+        let (tree_id, _, _) = storage.active_tree(1).unwrap();
+        assert_eq!(storage.expiring(Duration::from_secs(0), Duration::from_secs(0)).len(), 1);
+        let token = storage.share(tree_id, Access::View, "Smiths", 1);
+        storage.join(&token, 2).await.unwrap();
+        storage.set_retention(2, Retention::Forever);
+        assert!(storage.expiring(Duration::from_secs(0), Duration::from_secs(0)).is_empty());
+        storage.clear_expired(Duration::from_secs(0));
+        assert!(storage.member_tree(1, tree_id).is_some(), "Should keep the tree a member wants forever despite the warning");
+
+        storage.set_retention(2, Retention::Month);
+        assert!(storage.expiring(Duration::from_secs(0), Duration::from_secs(0)).is_empty(), "Should warn only when the month is almost over");
+        storage.set_retention(2, Retention::Default);
+        assert_eq!(storage.expiring(Duration::from_secs(0), Duration::from_secs(0)).len(), 1, "Should warn again once the retention is shorter");
+        storage.clear_expired(Duration::from_secs(0));
+        assert!(storage.member_tree(1, tree_id).is_none());
+    }
+
+    #[test]
+    fn using_tree_postpones_expiry() {
+        let storage = Storage::new();
+        storage.create_default_tree(1);
+        let (tree_id, _, _) = storage.active_tree(1).unwrap();
+        assert_eq!(storage.expiring(Duration::from_secs(0), Duration::from_secs(0)).len(), 1);
+        storage.tree(tree_id).unwrap();
+        storage.clear_expired(Duration::from_secs(0));
+        assert!(storage.tree(tree_id).is_some(), "Should not clear the tree used after the warning");
+        assert_eq!(storage.expiring(Duration::from_secs(0), Duration::from_secs(0)).len(), 1, "Should warn again");
+    }
+
+    #[test]
+    fn only_members_get_the_tree() {
+        let storage = Storage::new();
+        storage.create_default_tree(1);
+        let (tree_id, _, _) = storage.active_tree(1).unwrap();
+        assert_eq!(storage.expiring(Duration::from_secs(0), Duration::from_secs(0)).len(), 1);
+        assert!(storage.member_tree(2, tree_id).is_none(), "Should not give the tree to another chat");
+        storage.clear_expired(Duration::from_secs(0));
+        assert!(storage.member_tree(1, tree_id).is_none(), "Should not postpone expiry for another chat");
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::{Directed, Direction, Graph};
+
+use super::model::{Marriage, Person};
+use super::utility::escape_html;
+
+/// The first person added to the tree, everyone else is colored and ranked relative to them
+pub const ROOT: usize = 0;
+/// Side of the square the photo is scaled into, in points
+const PHOTO_SIZE: u32 = 72;
+
+/// Placeholders are drawn in grey with a dashed border on every theme
+const PLACEHOLDER_COLOR: &str = "gray60";
+
+/// Downloaded photos by their Telegram file id
+pub type PhotoFiles = HashMap<String, PathBuf>;
+/// People of a focused chart with their marriages and the index of the person in focus
+pub type Focused = (Graph<Person, &'static str, Directed, u32>, Vec<Marriage>, NodeIndex<u32>);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Theme {
+    #[default]
+    Classic,
+    Pastel,
+    Dark,
+    Plain,
+}
+
+/// Colors of a theme, `None` fill leaves the boxes empty
+struct Palette {
+    background: &'static str,
+    line: &'static str,
+    font: &'static str,
+    root: Option<&'static str>,
+    first_lineage: Option<&'static str>,
+    second_lineage: Option<&'static str>,
+    other: Option<&'static str>,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 4] = [Theme::Classic, Theme::Pastel, Theme::Dark, Theme::Plain];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Classic => "classic",
+            Theme::Pastel => "pastel",
+            Theme::Dark => "dark",
+            Theme::Plain => "plain",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Theme> {
+        let text = text.trim().to_lowercase();
+        Theme::ALL.iter().copied().find(|theme| theme.name() == text)
+    }
+
+    fn palette(&self) -> Palette {
+        match self {
+            Theme::Classic => Palette { background: "white", line: "gray30", font: "black", root: Some("gold"), first_lineage: Some("lightblue"), second_lineage: Some("lightpink"), other: Some("whitesmoke") },
+            Theme::Pastel => Palette { background: "ivory", line: "gray50", font: "gray20", root: Some("#fde2a7"), first_lineage: Some("#cfe3f7"), second_lineage: Some("#f7d6e0"), other: Some("#e8f3e8") },
+            Theme::Dark => Palette { background: "gray12", line: "gray70", font: "white", root: Some("darkgoldenrod"), first_lineage: Some("steelblue4"), second_lineage: Some("maroon"), other: Some("gray25") },
+            Theme::Plain => Palette { background: "white", line: "black", font: "black", root: None, first_lineage: None, second_lineage: None, other: None },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Lineage {
+    Root,
+    /// Ancestors through the first parent of the root
+    First,
+    /// Ancestors through the second parent of the root
+    Second,
+    Other,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn label(person: &Person) -> String {
+    let name = escape(&person.name.to_string());
+    if person.lifespan.is_empty() { name } else { format!("{}\\n{}", name, person.lifespan) }
+}
+
+/// HTML-like label with the photo above the name, Graphviz embeds the image into PNG and PDF
+fn photo_label(person: &Person, photo: &Path) -> String {
+    let mut rows = format!(
+        "<TR><TD FIXEDSIZE=\"TRUE\" WIDTH=\"{0}\" HEIGHT=\"{0}\"><IMG SRC=\"{1}\" SCALE=\"TRUE\"/></TD></TR><TR><TD>{2}</TD></TR>",
+        PHOTO_SIZE, escape_html(&photo.to_string_lossy()), escape_html(&person.name.to_string())
+    );
+    if !person.lifespan.is_empty() {
+        write!(rows, "<TR><TD>{}</TD></TR>", person.lifespan).unwrap();
+    }
+    format!("<<TABLE BORDER=\"0\" CELLSPACING=\"2\">{}</TABLE>>", rows)
+}
+
+/// The person along with relatives up to `depth` generations away in the direction
+fn relatives(graph: &Graph<Person, &str, Directed, u32>, ix: NodeIndex<u32>, direction: Direction, depth: usize) -> Vec<NodeIndex<u32>> {
+    let mut found = vec![(ix, 0)];
+    let mut i = 0;
+    while i < found.len() {
+        let (person, distance) = found[i];
+        if distance < depth {
+            for relative in graph.neighbors_directed(person, direction) {
+                if !found.iter().any(|(known, _)| *known == relative) {
+                    found.push((relative, distance + 1));
+                }
+            }
+        }
+        i += 1;
+    }
+    found.into_iter().map(|(person, _)| person).collect()
+}
+
+fn lineages(graph: &Graph<Person, &str, Directed, u32>, root: NodeIndex<u32>) -> HashMap<NodeIndex<u32>, Lineage> {
+    let mut lineages = HashMap::new();
+    if graph.node_count() == 0 {
+        return lineages;
+    }
+    //parents in the order they were added
+    let mut parents: Vec<_> = graph.edges_directed(root, Direction::Incoming).map(|edge| (edge.id(), edge.source())).collect();
+    parents.sort();
+    for (lineage, (_, parent)) in [Lineage::First, Lineage::Second].iter().zip(parents) {
+        for ancestor in relatives(graph, parent, Direction::Incoming, usize::MAX) {
+            lineages.entry(ancestor).or_insert(*lineage);
+        }
+    }
+    lineages.insert(root, Lineage::Root);
+    lineages
+}
+
+/// Generation of every person relative to the root: parents are one generation above their children.
+/// People not connected to the root are aligned relative to the first person of their part of the tree.
+pub fn generations(graph: &Graph<Person, &str, Directed, u32>, root: NodeIndex<u32>) -> HashMap<NodeIndex<u32>, i32> {
+    let mut generations = HashMap::new();
+    if graph.node_count() == 0 {
+        return generations;
+    }
+    for start in iter::once(root).chain(graph.node_indices()) {
+        if generations.contains_key(&start) {
+            continue;
+        }
+        generations.insert(start, 0);
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some(ix) = queue.pop_front() {
+            let generation = generations[&ix];
+            let parents = graph.neighbors_directed(ix, Direction::Incoming).map(|parent| (parent, generation - 1));
+            let children = graph.neighbors_directed(ix, Direction::Outgoing).map(|child| (child, generation + 1));
+            for (relative, relative_generation) in parents.chain(children).collect::<Vec<_>>() {
+                if let Entry::Vacant(entry) = generations.entry(relative) {
+                    entry.insert(relative_generation);
+                    queue.push_back(relative);
+                }
+            }
+        }
+    }
+    //paths of different length disagree when relatives married each other, push such children down.
+    //The number of rounds is limited as a cycle in the tree would never settle.
+    for _ in 0..graph.node_count() {
+        let mut changed = false;
+        for edge in graph.edge_references() {
+            let parent_generation = generations[&edge.source()];
+            if generations[&edge.target()] <= parent_generation {
+                generations.insert(edge.target(), parent_generation + 1);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    generations
+}
+
+/// Groups of people who have children together, so they are drawn side by side
+fn partners(graph: &Graph<Person, &str, Directed, u32>) -> Vec<Vec<NodeIndex<u32>>> {
+    let mut groups: Vec<Vec<NodeIndex<u32>>> = vec![];
+    for child in graph.node_indices() {
+        let parents: Vec<NodeIndex<u32>> = graph.neighbors_directed(child, Direction::Incoming).collect();
+        if parents.len() < 2 {
+            continue;
+        }
+        //merge every group which already has one of the parents
+        let (joined, rest): (Vec<_>, Vec<_>) = groups.into_iter().partition(|group| parents.iter().any(|parent| group.contains(parent)));
+        let mut group: Vec<NodeIndex<u32>> = joined.into_iter().flatten().chain(parents).collect();
+        group.sort_unstable();
+        group.dedup();
+        groups = rest;
+        groups.push(group);
+    }
+    groups.sort_unstable();
+    groups
+}
+
+fn node_list(people: &[NodeIndex<u32>]) -> String {
+    people.iter().map(|ix| ix.index().to_string()).collect::<Vec<String>>().join("; ")
+}
+
+/// How far from the chosen person the focused chart goes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Focus {
+    pub up: usize,
+    pub down: usize,
+    pub siblings: bool,
+}
+
+/// Extracts ancestors within `up` and descendants within `down` generations of the person,
+/// returning the subgraph and the index of the person in it
+pub fn focus(graph: &Graph<Person, &'static str, Directed, u32>, marriages: &[Marriage], person: NodeIndex<u32>, focus: &Focus) -> Focused {
+    let mut kept = relatives(graph, person, Direction::Incoming, focus.up);
+    kept.extend(relatives(graph, person, Direction::Outgoing, focus.down));
+    if focus.siblings {
+        for parent in graph.neighbors_directed(person, Direction::Incoming) {
+            kept.extend(graph.neighbors_directed(parent, Direction::Outgoing));
+        }
+    }
+    let subgraph = graph.filter_map(
+        |ix, person| if kept.contains(&ix) { Some(person.clone()) } else { None },
+        |_, edge| Some(*edge)
+    );
+    let is_kept = |ix: NodeIndex<u32>| kept.contains(&ix);
+    (subgraph, kept_marriages(graph, marriages, is_kept), reindex(graph, is_kept, person))
+}
+
+/// Index of the person in the graph filtered with `filter_map`, which keeps the order of nodes,
+/// so the new index is the number of kept people before them
+fn reindex<F: Fn(NodeIndex<u32>) -> bool>(graph: &Graph<Person, &'static str, Directed, u32>, kept: F, person: NodeIndex<u32>) -> NodeIndex<u32> {
+    NodeIndex::new(graph.node_indices().take_while(|ix| *ix != person).filter(|ix| kept(*ix)).count())
+}
+
+/// Marriages of the kept people with their indices in the graph filtered down to them
+pub fn kept_marriages<F: Fn(NodeIndex<u32>) -> bool>(graph: &Graph<Person, &'static str, Directed, u32>, marriages: &[Marriage], kept: F) -> Vec<Marriage> {
+    marriages.iter()
+        .filter(|marriage| marriage.spouses.iter().all(|spouse| kept(*spouse)))
+        .map(|marriage| Marriage { spouses: marriage.spouses.map(|spouse| reindex(graph, &kept, spouse)), year: marriage.year })
+        .collect()
+}
+
+/// Writes the graph as DOT with boxes, lineage colors, highlighted root and one row per generation.
+/// People whose photo is among `photos` get it above their name.
+pub fn write_dot(graph: &Graph<Person, &str, Directed, u32>, marriages: &[Marriage], root: NodeIndex<u32>, theme: Theme, photos: &PhotoFiles) -> String {
+    let palette = theme.palette();
+    let lineages = lineages(graph, root);
+    let mut dot = String::new();
+    let style = if palette.other.is_some() { "rounded,filled" } else { "rounded" };
+
+    writeln!(dot, "digraph {{").unwrap();
+    writeln!(dot, "    graph [ rankdir = TB, bgcolor = \"{}\" ]", palette.background).unwrap();
+    writeln!(dot, "    node [ shape = box, style = \"{}\", color = \"{}\", fontcolor = \"{}\", fontname = \"Helvetica\" ]", style, palette.line, palette.font).unwrap();
+    writeln!(dot, "    edge [ color = \"{}\", arrowsize = 0.6 ]", palette.line).unwrap();
+
+    for ix in graph.node_indices() {
+        let lineage = lineages.get(&ix).copied().unwrap_or(Lineage::Other);
+        let fill = match lineage {
+            Lineage::Root => palette.root,
+            Lineage::First => palette.first_lineage,
+            Lineage::Second => palette.second_lineage,
+            Lineage::Other => palette.other,
+        };
+        let person = &graph[ix];
+        let mut attributes = match person.photo.as_ref().and_then(|photo| photos.get(photo)) {
+            Some(photo) => format!("label = {}", photo_label(person, photo)),
+            None => format!("label = \"{}\"", label(person))
+        };
+        if person.placeholder {
+            write!(attributes, ", style = \"rounded,dashed\", color = \"{0}\", fontcolor = \"{0}\"", PLACEHOLDER_COLOR).unwrap();
+        } else if let Some(fill) = fill {
+            write!(attributes, ", fillcolor = \"{}\"", fill).unwrap();
+        }
+        if lineage == Lineage::Root {
+            attributes.push_str(", penwidth = 2");
+        }
+        writeln!(dot, "    {} [ {} ]", ix.index(), attributes).unwrap();
+    }
+
+    for edge in graph.edge_references() {
+        if edge.weight().is_empty() {
+            writeln!(dot, "    {} -> {}", edge.source().index(), edge.target().index()).unwrap();
+        } else {
+            writeln!(dot, "    {} -> {} [ label = \"{}\" ]", edge.source().index(), edge.target().index(), escape(edge.weight())).unwrap();
+        }
+    }
+
+    //spouses are linked sideways, so the line doesn't move them to other rows
+    for marriage in marriages {
+        let [first, second] = marriage.spouses;
+        match marriage.year {
+            Some(year) => writeln!(dot, "    {} -> {} [ dir = none, style = dashed, constraint = false, label = \"⚭ {}\" ]", first.index(), second.index(), year).unwrap(),
+            None => writeln!(dot, "    {} -> {} [ dir = none, style = dashed, constraint = false ]", first.index(), second.index()).unwrap()
+        }
+    }
+
+    //one row per generation, an invisible spine keeps rows in order even for people without links.
+    //Partners in a row are chained with invisible edges which keep them side by side,
+    //dot ignores rows of people who are also in a cluster, so clusters can't be used for that.
+    let partners = partners(graph);
+    let mut ranks: BTreeMap<i32, Vec<NodeIndex<u32>>> = BTreeMap::new();
+    for (ix, generation) in generations(graph, root) {
+        ranks.entry(generation).or_default().push(ix);
+    }
+    if ranks.len() > 1 {
+        for (generation, people) in ranks.iter_mut() {
+            people.sort_unstable();
+            let mut row = format!("rank = same; \"generation {}\" [ style = invis, shape = point ]; {};", generation, node_list(people));
+            for group in &partners {
+                let side_by_side: Vec<String> = group.iter().filter(|ix| people.contains(ix)).map(|ix| ix.index().to_string()).collect();
+                if side_by_side.len() > 1 {
+                    write!(row, " {} [ style = invis ];", side_by_side.join(" -> ")).unwrap();
+                }
+            }
+            writeln!(dot, "    {{ {} }}", row).unwrap();
+        }
+        let spine: Vec<String> = ranks.keys().map(|generation| format!("\"generation {}\"", generation)).collect();
+        writeln!(dot, "    {} [ style = invis ]", spine.join(" -> ")).unwrap();
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::updater::model::NodeCompleteness;
+
+    fn family() -> Graph<Person, &'static str, Directed, u32> {
+        let mut graph = Graph::new();
+        let root = graph.add_node(Person::parse("Robert 1980", NodeCompleteness::Plain));
+        let mom = graph.add_node(Person::parse("Alexandra \"Sasha\" Smith", NodeCompleteness::Plain));
+        let dad = graph.add_node(Person::parse("Bernard", NodeCompleteness::Plain));
+        let grandma = graph.add_node(Person::parse("Clara", NodeCompleteness::Plain));
+        let child = graph.add_node(Person::parse("Anna", NodeCompleteness::Plain));
+        graph.add_edge(mom, root, "");
+        graph.add_edge(dad, root, "");
+        graph.add_edge(grandma, dad, "");
+        graph.add_edge(root, child, "");
+        graph
+    }
+
+    #[test]
+    fn plain_chart() {
+        assert_eq!(write_dot(&family(), &[], NodeIndex::new(ROOT), Theme::Plain, &PhotoFiles::new()),
+"digraph {
+    graph [ rankdir = TB, bgcolor = \"white\" ]
+    node [ shape = box, style = \"rounded\", color = \"black\", fontcolor = \"black\", fontname = \"Helvetica\" ]
+    edge [ color = \"black\", arrowsize = 0.6 ]
+    0 [ label = \"Robert\\nb. 1980\", penwidth = 2 ]
+    1 [ label = \"Alexandra \\\"Sasha\\\" Smith\" ]
+    2 [ label = \"Bernard\" ]
+    3 [ label = \"Clara\" ]
+    4 [ label = \"Anna\" ]
+    1 -> 0
+    2 -> 0
+    3 -> 2
+    0 -> 4
+    { rank = same; \"generation -2\" [ style = invis, shape = point ]; 3; }
+    { rank = same; \"generation -1\" [ style = invis, shape = point ]; 1; 2; 1 -> 2 [ style = invis ]; }
+    { rank = same; \"generation 0\" [ style = invis, shape = point ]; 0; }
+    { rank = same; \"generation 1\" [ style = invis, shape = point ]; 4; }
+    \"generation -2\" -> \"generation -1\" -> \"generation 0\" -> \"generation 1\" [ style = invis ]
+}
+");
+    }
+
+    #[test]
+    fn partners_are_grouped() {
+        let mut graph = family();
+        let spouse = graph.add_node(Person::parse("Marie", NodeCompleteness::Plain));
+        let second_spouse = graph.add_node(Person::parse("Nina", NodeCompleteness::Plain));
+        let second_child = graph.add_node(Person::parse("Bruce", NodeCompleteness::Plain));
+        graph.add_edge(spouse, NodeIndex::new(4), "");
+        graph.add_edge(NodeIndex::new(ROOT), second_child, "");
+        graph.add_edge(second_spouse, second_child, "");
+        assert_eq!(partners(&graph), vec![
+            vec![NodeIndex::new(0), spouse, second_spouse],
+            vec![NodeIndex::new(1), NodeIndex::new(2)],
+        ], "Should keep all partners of a person together");
+    }
+
+    #[test]
+    fn graphviz_draws_rows_and_partners_without_warnings() {
+        use std::io::{ErrorKind, Write};
+        use std::process::{Command, Stdio};
+
+        let mut graph = family();
+        let spouse = graph.add_node(Person::parse("Marie", NodeCompleteness::Plain));
+        graph.add_edge(spouse, NodeIndex::new(4), "");
+        let marriages = [Marriage { spouses: [NodeIndex::new(1), NodeIndex::new(2)], year: Some(1970) }];
+        let dot = write_dot(&graph, &marriages, NodeIndex::new(ROOT), Theme::Classic, &PhotoFiles::new());
+        let mut graphviz = match Command::new("dot").arg("-Tcanon").stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn() {
+            Ok(graphviz) => graphviz,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                eprintln!("graphviz is not installed, the chart is not drawn");
+                return;
+            }
+            Err(error) => panic!("cannot run dot: {}", error)
+        };
+        graphviz.stdin.take().unwrap().write_all(dot.as_bytes()).unwrap();
+        let output = graphviz.wait_with_output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "", "Should keep both the rows and the partners");
+    }
+
+    #[test]
+    fn generations_stay_consistent() {
+        let mut graph = family();
+        //Clara turns out to be a grandmother through both parents, and a stranger stays apart
+        graph.add_edge(NodeIndex::new(3), NodeIndex::new(1), "");
+        graph.add_edge(NodeIndex::new(1), NodeIndex::new(2), "");
+        let stranger = graph.add_node(Person::parse("Zoe", NodeCompleteness::Plain));
+        let generations = generations(&graph, NodeIndex::new(ROOT));
+        for edge in graph.edge_references() {
+            assert!(generations[&edge.source()] < generations[&edge.target()], "Parents should be above children");
+        }
+        assert_eq!(generations[&stranger], 0);
+    }
+
+    #[test]
+    fn lineages_follow_parents_of_root() {
+        let dot = write_dot(&family(), &[], NodeIndex::new(ROOT), Theme::Classic, &PhotoFiles::new());
+        assert!(dot.contains("0 [ label = \"Robert\\nb. 1980\", fillcolor = \"gold\", penwidth = 2 ]"), "Should highlight root");
+        assert!(dot.contains("1 [ label = \"Alexandra \\\"Sasha\\\" Smith\", fillcolor = \"lightblue\" ]"));
+        assert!(dot.contains("3 [ label = \"Clara\", fillcolor = \"lightpink\" ]"), "Should color grandparents by their child's lineage");
+        assert!(dot.contains("4 [ label = \"Anna\", fillcolor = \"whitesmoke\" ]"));
+    }
+
+    #[test]
+    fn photos_are_shown_above_names() {
+        let mut graph = family();
+        graph[NodeIndex::new(ROOT)].photo = Some("robert".to_string());
+        graph[NodeIndex::new(4)].photo = Some("not downloaded".to_string());
+        let photos = PhotoFiles::from([("robert".to_string(), PathBuf::from("/tmp/robert & co.jpg"))]);
+        let dot = write_dot(&graph, &[], NodeIndex::new(ROOT), Theme::Plain, &photos);
+        assert!(dot.contains("0 [ label = <<TABLE BORDER=\"0\" CELLSPACING=\"2\"><TR><TD FIXEDSIZE=\"TRUE\" WIDTH=\"72\" HEIGHT=\"72\"><IMG SRC=\"/tmp/robert &amp; co.jpg\" SCALE=\"TRUE\"/></TD></TR><TR><TD>Robert</TD></TR><TR><TD>b. 1980</TD></TR></TABLE>>, penwidth = 2 ]"));
+        assert!(dot.contains("4 [ label = \"Anna\" ]"), "Should keep the plain label without the downloaded photo");
+    }
+
+    #[test]
+    fn focus_on_person() {
+        let graph = family();
+        let dad = NodeIndex::new(2);
+        let (subgraph, _, person) = focus(&graph, &[], dad, &Focus { up: 1, down: 1, siblings: false });
+        let names: Vec<String> = subgraph.node_weights().map(|person| person.name.to_string()).collect();
+        assert_eq!(names, vec!["Robert", "Bernard", "Clara"]);
+        assert_eq!(subgraph[person].name.to_string(), "Bernard");
+        assert_eq!(subgraph.edge_count(), 2);
+
+        let (subgraph, _, _) = focus(&graph, &[], NodeIndex::new(4), &Focus { up: 1, down: 0, siblings: false });
+        assert_eq!(subgraph.node_count(), 2, "Should stop after one generation up");
+        let (subgraph, _, person) = focus(&graph, &[], dad, &Focus { up: 0, down: 0, siblings: true });
+        assert_eq!(subgraph.node_count(), 1, "Should find no siblings without parents");
+        assert_eq!(person, NodeIndex::new(0));
+    }
+
+    #[test]
+    fn marriages_are_dashed() {
+        let graph = family();
+        let marriages = [Marriage { spouses: [NodeIndex::new(1), NodeIndex::new(2)], year: Some(1970) }];
+        let dot = write_dot(&graph, &marriages, NodeIndex::new(ROOT), Theme::Plain, &PhotoFiles::new());
+        assert!(dot.contains("    1 -> 2 [ dir = none, style = dashed, constraint = false, label = \"⚭ 1970\" ]"));
+
+        let (_, kept, _) = focus(&graph, &marriages, NodeIndex::new(3), &Focus { up: 0, down: 2, siblings: false });
+        assert!(kept.is_empty(), "Should leave out marriages of people not on the chart");
+        let (_, kept, _) = focus(&graph, &marriages, NodeIndex::new(4), &Focus { up: 2, down: 0, siblings: false });
+        assert_eq!(kept[0].spouses, [NodeIndex::new(1), NodeIndex::new(2)]);
+        let (_, kept, _) = focus(&graph, &marriages, NodeIndex::new(ROOT), &Focus { up: 1, down: 0, siblings: false });
+        assert_eq!(kept, marriages.to_vec());
+    }
+
+    #[test]
+    fn themes_by_name() {
+        assert_eq!(Theme::parse(" Dark"), Some(Theme::Dark));
+        assert_eq!(Theme::parse("neon"), None);
+    }
+}
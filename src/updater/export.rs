@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::{Directed, Direction, Graph};
+
+use super::chart::{generations, ROOT};
+use super::model::{Marriage, Person};
+use super::utility::escape_html;
+
+const HTML_STYLE: &str = "body { font-family: Helvetica, Arial, sans-serif; margin: 2em; }
+section { display: flex; flex-wrap: wrap; gap: 1em; margin-bottom: 2em; }
+article { width: 12em; padding: 0.8em; border: 1px solid #ccc; border-radius: 0.6em; }
+article img { width: 100%; border-radius: 0.4em; }
+h2 { font-size: 1.1em; margin: 0.3em 0; }
+p { margin: 0.3em 0; }";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Mermaid,
+    PlantUml,
+}
+
+impl ExportFormat {
+    pub fn parse(text: &str) -> Option<ExportFormat> {
+        match text.trim().to_lowercase().as_str() {
+            "mermaid" => Some(ExportFormat::Mermaid),
+            "plantuml" | "puml" => Some(ExportFormat::PlantUml),
+            _ => None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Mermaid => "mmd",
+            ExportFormat::PlantUml => "puml",
+        }
+    }
+
+    /// Language name for syntax highlighting of code blocks
+    pub fn language(&self) -> &'static str {
+        match self {
+            ExportFormat::Mermaid => "mermaid",
+            ExportFormat::PlantUml => "plantuml",
+        }
+    }
+}
+
+/// Mermaid labels can't contain quotes, brackets break the node shape, entity codes are safe inside of any shape
+fn escape_mermaid(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '#' => "#35;".to_string(),
+            '"' => "#quot;".to_string(),
+            '[' => "#91;".to_string(),
+            ']' => "#93;".to_string(),
+            '(' => "#40;".to_string(),
+            ')' => "#41;".to_string(),
+            '{' => "#123;".to_string(),
+            '}' => "#125;".to_string(),
+            '<' => "#lt;".to_string(),
+            '>' => "#gt;".to_string(),
+            c => c.to_string()
+        })
+        .collect()
+}
+
+/// PlantUML strings have no escape for quotes, the unicode notation is used instead
+fn escape_plantuml(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "<U+0022>")
+}
+
+/// Label of the line between spouses, the chart shows the year of the wedding like DOT charts do
+fn wedding(marriage: &Marriage) -> Option<String> {
+    marriage.year.map(|year| format!("⚭ {}", year))
+}
+
+/// Spouses are linked with dotted lines without arrows
+pub fn to_mermaid(graph: &Graph<Person, &str, Directed, u32>, marriages: &[Marriage]) -> String {
+    let mut text = String::from("flowchart TD\n");
+    for ix in graph.node_indices() {
+        writeln!(text, "    p{}[\"{}\"]", ix.index(), escape_mermaid(&graph[ix].to_string())).unwrap();
+    }
+    for edge in graph.edge_references() {
+        writeln!(text, "    p{} --> p{}", edge.source().index(), edge.target().index()).unwrap();
+    }
+    for marriage in marriages {
+        let [first, second] = marriage.spouses;
+        match wedding(marriage) {
+            Some(label) => writeln!(text, "    p{} -.-|{}| p{}", first.index(), label, second.index()).unwrap(),
+            None => writeln!(text, "    p{} -.- p{}", first.index(), second.index()).unwrap()
+        }
+    }
+    text
+}
+
+pub fn to_plantuml(graph: &Graph<Person, &str, Directed, u32>, marriages: &[Marriage]) -> String {
+    let mut text = String::from("@startuml\n");
+    for ix in graph.node_indices() {
+        writeln!(text, "rectangle \"{}\" as p{}", escape_plantuml(&graph[ix].to_string()), ix.index()).unwrap();
+    }
+    for edge in graph.edge_references() {
+        writeln!(text, "p{} --> p{}", edge.source().index(), edge.target().index()).unwrap();
+    }
+    for marriage in marriages {
+        let [first, second] = marriage.spouses;
+        match wedding(marriage) {
+            Some(label) => writeln!(text, "p{} .. p{} : {}", first.index(), second.index(), label).unwrap(),
+            None => writeln!(text, "p{} .. p{}", first.index(), second.index()).unwrap()
+        }
+    }
+    text.push_str("@enduml\n");
+    text
+}
+
+fn link(graph: &Graph<Person, &str, Directed, u32>, relative: NodeIndex<u32>) -> String {
+    format!("<a href=\"#p{}\">{}</a>", relative.index(), escape_html(&graph[relative].name.to_string()))
+}
+
+fn links(graph: &Graph<Person, &str, Directed, u32>, ix: NodeIndex<u32>, direction: Direction) -> String {
+    graph.neighbors_directed(ix, direction)
+        .map(|relative| link(graph, relative))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// People the person married along with the year of each wedding
+fn spouses(graph: &Graph<Person, &str, Directed, u32>, marriages: &[Marriage], ix: NodeIndex<u32>) -> String {
+    marriages.iter()
+        .filter_map(|marriage| {
+            let spouse = match marriage.spouses {
+                [first, second] if first == ix => second,
+                [first, second] if second == ix => first,
+                _ => return None
+            };
+            Some(match marriage.year {
+                Some(year) => format!("{} ({})", link(graph, spouse), year),
+                None => link(graph, spouse)
+            })
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Self-contained page with a card per person and a row per generation, photos are embedded by their file id
+pub fn to_html(graph: &Graph<Person, &str, Directed, u32>, marriages: &[Marriage], title: &str, photos: &HashMap<String, Vec<u8>>) -> String {
+    let mut rows: BTreeMap<i32, Vec<NodeIndex<u32>>> = BTreeMap::new();
+    if graph.node_count() > 0 {
+        for (ix, generation) in generations(graph, NodeIndex::new(ROOT)) {
+            rows.entry(generation).or_default().push(ix);
+        }
+    }
+
+    let title = escape_html(title);
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>\n{1}\n</style>\n</head>\n<body>\n<h1>{0}</h1>\n", title, HTML_STYLE);
+    for people in rows.values_mut() {
+        people.sort_unstable();
+        html.push_str("<section>\n");
+        for ix in people.iter().copied() {
+            let person = &graph[ix];
+            writeln!(html, "<article id=\"p{}\">", ix.index()).unwrap();
+            if let Some(photo) = person.photo.as_ref().and_then(|photo| photos.get(photo)) {
+                writeln!(html, "<img src=\"data:image/jpeg;base64,{}\" alt=\"\">", base64::encode(photo)).unwrap();
+            }
+            writeln!(html, "<h2>{}</h2>", escape_html(&person.name.to_string())).unwrap();
+            if !person.lifespan.is_empty() {
+                writeln!(html, "<p>{}</p>", person.lifespan).unwrap();
+            }
+            if let Some(notes) = &person.notes {
+                writeln!(html, "<p>{}</p>", escape_html(notes)).unwrap();
+            }
+            let parents = links(graph, ix, Direction::Incoming);
+            if !parents.is_empty() {
+                writeln!(html, "<p>Parents: {}</p>", parents).unwrap();
+            }
+            let spouses = spouses(graph, marriages, ix);
+            if !spouses.is_empty() {
+                writeln!(html, "<p>Spouses: {}</p>", spouses).unwrap();
+            }
+            let children = links(graph, ix, Direction::Outgoing);
+            if !children.is_empty() {
+                writeln!(html, "<p>Children: {}</p>", children).unwrap();
+            }
+            html.push_str("</article>\n");
+        }
+        html.push_str("</section>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+pub fn export(graph: &Graph<Person, &str, Directed, u32>, marriages: &[Marriage], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Mermaid => to_mermaid(graph, marriages),
+        ExportFormat::PlantUml => to_plantuml(graph, marriages),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::updater::model::NodeCompleteness;
+
+    fn family() -> Graph<Person, &'static str, Directed, u32> {
+        let mut graph = Graph::new();
+        let child = graph.add_node(Person::parse("Robert \"Bob\" Smith 1980", NodeCompleteness::Plain));
+        let mom = graph.add_node(Person::parse("Anna [Annie] Smith (née Brown)", NodeCompleteness::Plain));
+        graph.add_edge(mom, child, "");
+        graph
+    }
+
+    #[test]
+    fn mermaid() {
+        assert_eq!(to_mermaid(&family(), &[]),
+"flowchart TD
+    p0[\"Robert #quot;Bob#quot; Smith, b. 1980\"]
+    p1[\"Anna #91;Annie#93; Smith #40;née Brown#41;\"]
+    p1 --> p0
+");
+    }
+
+    #[test]
+    fn html() {
+        let mut graph = family();
+        graph[NodeIndex::new(1)].photo = Some("anna".to_string());
+        graph[NodeIndex::new(0)].photo = Some("not downloaded".to_string());
+        let photos = HashMap::from([("anna".to_string(), b"jpeg".to_vec())]);
+        let html = to_html(&graph, &[], "Smiths & co", &photos);
+        assert!(html.contains("<title>Smiths &amp; co</title>"));
+        assert!(html.contains("<section>
+<article id=\"p1\">
+<img src=\"data:image/jpeg;base64,anBlZw==\" alt=\"\">
+<h2>Anna [Annie] Smith (née Brown)</h2>
+<p>Children: <a href=\"#p0\">Robert &quot;Bob&quot; Smith</a></p>
+</article>
+</section>
+<section>
+<article id=\"p0\">
+<h2>Robert &quot;Bob&quot; Smith</h2>
+<p>b. 1980</p>
+<p>Parents: <a href=\"#p1\">Anna [Annie] Smith (née Brown)</a></p>
+</article>
+</section>"), "Should put parents above children and skip photos which weren't downloaded");
+    }
+
+    #[test]
+    fn plantuml() {
+        assert_eq!(to_plantuml(&family(), &[]),
+"@startuml
+rectangle \"Robert <U+0022>Bob<U+0022> Smith, b. 1980\" as p0
+rectangle \"Anna [Annie] Smith (née Brown)\" as p1
+p1 --> p0
+@enduml
+");
+    }
+}
@@ -1,30 +1,232 @@
 use petgraph::{Directed};
-use petgraph::dot::Dot;
+use std::collections::HashMap;
 use std::string::ToString;
 use petgraph::{graph::{NodeIndex}, Direction};
 use petgraph::prelude::Graph;
-use super::{model::{Person, DescribedNodeInfo, NodeCompleteness, OutputAction, InputAction, NEW_NODE_STATUS}, utility::get_node_description};
+use petgraph::visit::{Dfs, Reversed};
+use super::chart::{focus, kept_marriages, write_dot, Focus, PhotoFiles, Theme, ROOT};
+use super::export::{export, to_html, ExportFormat};
+use super::table::{read_tables, write_people, write_relationships, RowError};
+use super::validation::{validate, Warning, MAX_PARENTS};
+use super::name::split_names;
+use super::statement::{Kinship, Proposal, Statement, StatementError};
+use super::name::PersonName;
+use super::{model::{Author, Called, Marriage, Person, DescribedNodeInfo, NodeCompleteness, OutputAction, InputAction, StateError, Subject, NEW_NODE_STATUS}, utility::{called, get_node_subject}};
+use crate::locale::{tr, Lang, Msg};
 
 pub struct GraphUpdater {
     graph: Graph<Person, &'static str, Directed, u32>,
-    described_ix: DescribedNodeInfo,
+    /// Node each user is currently asked about, so several users can fill in the tree at once
+    cursors: HashMap<i64, DescribedNodeInfo>,
+    /// Children whose surname was guessed from the parent and waits for the user to confirm it
+    surname_checks: HashMap<i64, NodeIndex<u32>>,
+    /// Warnings the users already know about
+    reported: Vec<Warning>,
+    /// People chosen with /edit, the user's next photo goes to them instead of the person being asked about
+    editing: HashMap<i64, NodeIndex<u32>>,
+    /// Identifies the state of the tree, so an answer is only undone while nobody has changed anything after it
+    revision: u64,
+    /// Revisions given out so far, a change never gets the revision of a state which was undone
+    revisions: u64,
+    /// States before each user's last answers, the latest at the end
+    snapshots: HashMap<i64, Vec<Snapshot>>,
+    /// People skipped by users, asked about once everybody else is described
+    postponed: Vec<NodeIndex<u32>>,
+    /// Children the user named in one answer, asked about one after another
+    queues: HashMap<i64, Vec<NodeIndex<u32>>>,
+    marriages: Vec<Marriage>,
+    /// Statements shown to users and waiting for them to confirm
+    statements: HashMap<i64, Statement>,
+}
+
+/// Answers a user can go back through
+const UNDO_DEPTH: usize = 20;
+
+/// What a user's answer can change
+struct Snapshot {
+    graph: Graph<Person, &'static str, Directed, u32>,
+    marriages: Vec<Marriage>,
+    cursor: Option<DescribedNodeInfo>,
+    surname_check: Option<NodeIndex<u32>>,
+    queue: Option<Vec<NodeIndex<u32>>>,
+    /// Revision right after the answer
+    revision: u64,
+    /// Revision the tree is back at once the answer is undone
+    previous: u64,
 }
 
 impl GraphUpdater {
-    pub fn new() -> Self { Self { described_ix : DescribedNodeInfo::new(None), graph: Graph::new() } }
+    pub fn new() -> Self { Self { cursors: HashMap::new(), surname_checks: HashMap::new(), reported: vec![], editing: HashMap::new(), revision: 0, revisions: 0, snapshots: HashMap::new(), postponed: vec![], queues: HashMap::new(), marriages: vec![], statements: HashMap::new(), graph: Graph::new() } }
+
+    pub fn print_dot(&self, theme: Theme, photos: &PhotoFiles, lang: Lang) -> String {
+        write_dot(&self.labelled(&self.graph, lang), &self.marriages, NodeIndex::new(ROOT), theme, photos)
+    }
+
+    /// The graph with placeholders named for people to read, e.g. "Unknown father of Robert".
+    /// Labels are made from the whole tree, so they keep the children a chart of some relatives may leave out.
+    fn labelled(&self, graph: &Graph<Person, &'static str, Directed, u32>, lang: Lang) -> Graph<Person, &'static str, Directed, u32> {
+        graph.map(
+            |ix, person| {
+                let mut person = person.clone();
+                if person.placeholder {
+                    person.name = PersonName::new(tr(lang, Msg::PersonLabel(&called(graph, ix))));
+                }
+                person
+            },
+            |_, edge| *edge
+        )
+    }
+
+    /// The tree with its marriages but without placeholders which link nobody anymore, e.g. after their links were removed.
+    /// Root is never a placeholder, so it keeps its index.
+    fn exported(&self) -> (Graph<Person, &'static str, Directed, u32>, Vec<Marriage>) {
+        let kept = |ix: NodeIndex<u32>| {
+            let linked = self.graph.neighbors_undirected(ix).next().is_some() || self.marriages.iter().any(|marriage| marriage.spouses.contains(&ix));
+            linked || !self.graph[ix].placeholder
+        };
+        let graph = self.graph.filter_map(
+            |ix, person| if kept(ix) { Some(person.clone()) } else { None },
+            |_, edge| Some(*edge)
+        );
+        (graph, kept_marriages(&self.graph, &self.marriages, kept))
+    }
+
+    pub fn export(&self, format: ExportFormat, lang: Lang) -> String {
+        let (graph, marriages) = self.exported();
+        export(&self.labelled(&graph, lang), &marriages, format)
+    }
+
+    /// Page with all people and the downloaded photos
+    pub fn export_html(&self, title: &str, photos: &HashMap<String, Vec<u8>>, lang: Lang) -> String {
+        let (graph, marriages) = self.exported();
+        to_html(&self.labelled(&graph, lang), &marriages, title, photos)
+    }
+
+    /// Telegram file ids of all photos in the tree
+    pub fn photos(&self) -> Vec<String> {
+        self.graph.node_weights().filter_map(|person| person.photo.clone()).collect()
+    }
+
+    pub fn edit(&mut self, user_id: i64, ix: NodeIndex<u32>) {
+        self.editing.insert(user_id, ix);
+    }
+
+    /// Gives the photo to the person chosen with /edit or, if there's none, to the person the user is asked about.
+    /// Returns how the person is called.
+    pub fn attach_photo(&mut self, user_id: i64, file_id: &str) -> Option<Called> {
+        let ix = self.editing.remove(&user_id).or_else(|| self.cursor(user_id).ok().flatten())?;
+        let person = self.graph.node_weight_mut(ix)?;
+        person.photo = Some(file_id.to_string());
+        self.next_revision();
+        Some(called(&self.graph, ix))
+    }
+
+    /// People and relationships tables for spreadsheets
+    pub fn export_tables(&self) -> (String, String) {
+        let (graph, marriages) = self.exported();
+        (write_people(&graph), write_relationships(&graph, &marriages))
+    }
+
+    /// Tree read back from the tables it was saved as, people without both parents are asked about again
+    pub fn from_tables(people: &str, relationships: &str) -> Result<Self, Vec<RowError>> {
+        let (graph, marriages) = read_tables(people, relationships)?;
+        Ok(Self { graph, marriages, ..Self::new() })
+    }
+
+    /// Replaces the tree with the one from the tables, returning the number of imported people
+    pub fn import_tables(&mut self, people: &str, relationships: &str, author: &Author) -> Result<usize, Vec<RowError>> {
+        let (mut graph, marriages) = read_tables(people, relationships)?;
+        for ix in graph.node_indices() {
+            graph[ix].author = Some(author.clone());
+        }
+        self.replace(graph);
+        self.marriages = marriages;
+        Ok(self.graph.node_count())
+    }
+
+    /// Starts the tree over
+    pub fn clear(&mut self) {
+        self.replace(Graph::new());
+    }
+
+    /// Forgets everything about the tree but the revisions given out, so buttons sent for it are outdated
+    fn replace(&mut self, graph: Graph<Person, &'static str, Directed, u32>) {
+        *self = Self { graph, revisions: self.revisions, ..Self::new() };
+        self.next_revision();
+    }
+
+    /// Chart of the person's closest relatives along with the number of people on it
+    pub fn print_focus_dot(&self, person: NodeIndex<u32>, chart_focus: &Focus, theme: Theme, photos: &PhotoFiles, lang: Lang) -> (String, usize) {
+        let (graph, marriages, person) = focus(&self.labelled(&self.graph, lang), &self.marriages, person, chart_focus);
+        (write_dot(&graph, &marriages, person, theme, photos), graph.node_count())
+    }
+
+    /// People called exactly like the query or, if there are none, people whose name contains it
+    pub fn find_people(&self, query: &str) -> Vec<NodeIndex<u32>> {
+        let query = query.trim().to_lowercase();
+        let names: Vec<(NodeIndex<u32>, String)> = self.graph.node_indices()
+            .map(|ix| (ix, self.graph[ix].name.to_string().to_lowercase()))
+            .collect();
+        let exact: Vec<NodeIndex<u32>> = names.iter().filter(|(_, name)| *name == query).map(|(ix, _)| *ix).collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+        names.iter().filter(|(_, name)| name.contains(&query)).map(|(ix, _)| *ix).collect()
+    }
+
+    pub fn person_name(&self, ix: NodeIndex<u32>) -> String {
+        self.graph[ix].name.to_string()
+    }
 
-    pub fn print_dot(&self) -> String {
-        Dot::new(&self.graph).to_string()
+    pub fn node_count(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    /// Names of people added after the graph had `count` nodes
+    pub fn names_since(&self, count: usize) -> Vec<String> {
+        self.graph.node_indices()
+            .skip(count)
+            .map(|ix| self.graph[ix].name.to_string())
+            .collect()
+    }
+
+    /// Validates the graph and returns problems which appeared since the last call
+    pub fn take_new_warnings(&mut self) -> Vec<Warning> {
+        let warnings = validate(&self.graph);
+        let new = warnings.iter().filter(|warning| !self.reported.contains(warning)).cloned().collect();
+        self.reported = warnings;
+        new
+    }
+
+    /// Validates the graph again and returns all its problems, known ones included
+    pub fn warnings(&mut self) -> Vec<Warning> {
+        self.reported = validate(&self.graph);
+        self.reported.clone()
+    }
+
+    /// Removes the parent-child link a warning at the revision was about, returning names of the people it connected.
+    /// Once the tree has changed the indices may belong to other people, so the link is kept.
+    pub fn unlink(&mut self, revision: u64, parent: NodeIndex<u32>, child: NodeIndex<u32>) -> Result<Option<(String, String)>, StateError> {
+        if revision != self.revision {
+            return Err(StateError::Outdated);
+        }
+        let edge = match self.graph.find_edge(parent, child) {
+            Some(edge) => edge,
+            None => return Ok(None)
+        };
+        self.graph.remove_edge(edge);
+        self.next_revision();
+        Ok(Some((self.graph[parent].name.to_string(), self.graph[child].name.to_string())))
     }
 
     fn add_parent(&mut self, ix: &NodeIndex<u32>, name: &str) {
-        let parent_ix = self.graph.add_node(Person::new(name.to_string(), NodeCompleteness::Plain));
+        let parent_ix = self.graph.add_node(Person::parse(name, NodeCompleteness::Plain));
         self.graph.add_edge(parent_ix, *ix, "");
     }
 
   
     fn add_sibling(&mut self, ix: &NodeIndex<u32>, name: &str) {
-        let sibling_ix = self.graph.add_node(Person::new(name.to_string(), NodeCompleteness::SiblingsComplete));
+        let sibling_ix = self.graph.add_node(Person::parse(name, NodeCompleteness::SiblingsComplete));
         let mut parents = self.graph.neighbors_directed(*ix, Direction::Incoming).detach();
         while let Some(parent) = parents.next_node(&self.graph) {
             self.graph.add_edge(parent, sibling_ix, "");
@@ -32,137 +234,578 @@ impl GraphUpdater {
     }
 
     fn add_child(&mut self, ix: &NodeIndex<u32>, name: &str) -> NodeIndex<u32> {
-        let child_ix = self.graph.add_node(Person::new(name.to_string(), NodeCompleteness::OneParent));
+        let child_ix = self.graph.add_node(Person::parse(name, NodeCompleteness::OneParent));
         self.graph.add_edge(*ix, child_ix, "");
         child_ix
     }
 
+    /// Gives the child the surname of the parent if the user didn't write any
+    fn infer_surname(&mut self, child_ix: NodeIndex<u32>, parent_ix: NodeIndex<u32>) -> Option<String> {
+        let surname = self.graph[parent_ix].name.surname.clone();
+        let child = &mut self.graph[child_ix].name;
+        match (&child.surname, surname) {
+            (None, Some(surname)) => {
+                child.surname = Some(surname.clone());
+                Some(surname)
+            }
+            _ => None
+        }
+    }
+
     fn has_children(&self, ix: &NodeIndex<u32>) -> bool {
          self.graph.neighbors_directed(*ix, Direction::Outgoing).count() > 0
     }
 
-    fn get_description(&self, ix: &NodeIndex<u32>) -> Option<String> {
-        get_node_description(&self.graph, ix)
+    fn named(&self, ix: NodeIndex<u32>) -> Subject {
+        Subject::named(called(&self.graph, ix))
+    }
+
+    /// The person the user is asked about, an error if somebody's undo has removed them
+    fn cursor(&self, user_id: i64) -> Result<Option<NodeIndex<u32>>, StateError> {
+        match self.cursors.get(&user_id).and_then(|cursor| cursor.ix) {
+            Some(ix) if self.graph.node_weight(ix).is_none() => Err(StateError::UnknownPerson(ix)),
+            cursor => Ok(cursor)
+        }
     }
 
-    fn get_next_node(&self) -> Option<NodeIndex<u32>> {
-        let described_ix = self.graph.node_indices().find(|i| {
-            [NodeCompleteness::Plain, NodeCompleteness::OneParent, NodeCompleteness::ParentsComplete].contains(&self.graph[*i].completeness)
+    fn find_next_node<F: Fn(&NodeIndex<u32>) -> bool>(&self, available: F) -> Option<NodeIndex<u32>> {
+        let described_ix = self.graph.node_indices().filter(&available).find(|i| {
+            let person = &self.graph[*i];
+            [NodeCompleteness::Plain, NodeCompleteness::OneParent].contains(&person.completeness)
+                || (person.completeness == NodeCompleteness::ParentsComplete && !person.placeholder)
         });
         if let Some(ix) = described_ix {
             Some(ix)
         } else {
-            self.graph.node_indices().find(|i| {
-                self.graph[*i].completeness == NodeCompleteness::SiblingsComplete
+            self.graph.node_indices().filter(&available).find(|i| {
+                self.graph[*i].completeness == NodeCompleteness::SiblingsComplete && !self.graph[*i].placeholder
             })
         }
     }
 
-    fn switch_next_relative(&mut self) -> OutputAction {
-        match self.get_next_node() {
-            Some(node_ix) => {
-                self.described_ix = DescribedNodeInfo::new(Some(node_ix));
-                let name = &self.graph[self.described_ix.ix.unwrap()].name;
-                let completeness = &self.graph[self.described_ix.ix.unwrap()].completeness;
-                let info: String;
-                if let Some(description) = self.get_description(&node_ix) { 
-                    info = format!("{}, {}", name, description);
+    /// Prefers people nobody else is asked about at the moment, skipped people come last in the order they were skipped.
+    /// The `left` person isn't picked at all.
+    fn get_next_node(&self, user_id: i64, left: Option<NodeIndex<u32>>) -> Option<NodeIndex<u32>> {
+        let claimed: Vec<NodeIndex<u32>> = self.cursors.iter()
+            .filter(|(user, _)| **user != user_id)
+            .filter_map(|(_, cursor)| cursor.ix)
+            .collect();
+        let other = |ix: &NodeIndex<u32>| Some(*ix) != left;
+        self.find_next_node(|ix| other(ix) && !claimed.contains(ix) && !self.postponed.contains(ix))
+            .or_else(|| self.find_next_node(|ix| other(ix) && !self.postponed.contains(ix)))
+            .or_else(|| self.postponed.iter().copied().filter(other).find(|ix| {
+                self.graph.node_weight(*ix).is_some_and(|person| person.completeness != NodeCompleteness::ChildrenComplete)
+            }))
+    }
+
+    fn skip(&mut self, user_id: i64, ix: NodeIndex<u32>) -> Result<OutputAction, StateError> {
+        self.postponed.retain(|postponed| *postponed != ix);
+        self.postponed.push(ix);
+        self.switch_next_relative(user_id)
+    }
+
+    /// Marks the person and everyone above them as described, the interview goes on with the other lines of the family
+    fn stop_branch(&mut self, user_id: i64, ix: NodeIndex<u32>) -> Result<OutputAction, StateError> {
+        let mut branch = vec![];
+        let mut ancestors = Dfs::new(Reversed(&self.graph), ix);
+        while let Some(ancestor) = ancestors.next(Reversed(&self.graph)) {
+            branch.push(ancestor);
+        }
+        for ancestor in branch {
+            self.graph[ancestor].completeness = NodeCompleteness::ChildrenComplete;
+        }
+        self.switch_next_relative(user_id)
+    }
+
+    fn ask_about(&self, node_ix: NodeIndex<u32>) -> Result<OutputAction, StateError> {
+        let completeness = &self.graph[node_ix].completeness;
+        let info = get_node_subject(&self.graph, &node_ix);
+
+        let output_action = match completeness {
+            NodeCompleteness::Plain => {
+                OutputAction::AskFirstParent(info)
+            },
+            NodeCompleteness::OneParent => {
+                OutputAction::AskSecondParent(info)
+            },
+            NodeCompleteness::ParentsComplete => {
+                OutputAction::AskIfSiblings(info)
+            },
+            NodeCompleteness::SiblingsComplete => {
+                if self.has_children(&node_ix) {
+                    OutputAction::AskIfMoreChildren(info)
                 }
                 else {
-                    info = name.to_string();
-                }
-                
-                match completeness {
-                    NodeCompleteness::Plain => {
-                        OutputAction::AskFirstParent(info)
-                    },
-                    NodeCompleteness::OneParent => {
-                        OutputAction::AskSecondParent(info)
-                    },
-                    NodeCompleteness::ParentsComplete => {
-                        OutputAction::AskIfSiblings(info)
-                    },
-                    NodeCompleteness::SiblingsComplete => {
-                        if self.has_children(&node_ix) {
-                            OutputAction::AskIfMoreChildren(info)
-                        }
-                        else {
-                            OutputAction::AskIfChildren(info)
-                        }
-                    },
-                    NodeCompleteness::ChildrenComplete => {
-                        OutputAction::NotifyError
-                    }
+                    OutputAction::AskIfChildren(info)
                 }
             },
+            NodeCompleteness::ChildrenComplete => {
+                return Err(StateError::Inconsistent(format!("asked about {}, who is described completely", self.graph[node_ix].name)));
+            }
+        };
+        Ok(output_action)
+    }
+
+    /// Takes the next child named along with the ones already described, skipping those somebody else has finished
+    fn next_queued(&mut self, user_id: i64) -> Option<NodeIndex<u32>> {
+        let queue = self.queues.get_mut(&user_id)?;
+        while !queue.is_empty() {
+            let ix = queue.remove(0);
+            if self.graph.node_weight(ix).is_some_and(|person| person.completeness != NodeCompleteness::ChildrenComplete) {
+                return Some(ix);
+            }
+        }
+        None
+    }
+
+    fn switch_next_relative(&mut self, user_id: i64) -> Result<OutputAction, StateError> {
+        match self.next_queued(user_id).or_else(|| self.get_next_node(user_id, None)) {
+            Some(node_ix) => {
+                self.cursors.insert(user_id, DescribedNodeInfo::new(Some(node_ix)));
+                self.ask_about(node_ix)
+            },
             None => {
-                OutputAction::NotifyComplete
+                Ok(OutputAction::NotifyComplete)
+            }
+        }
+    }
+
+    /// Repeats the question the user is currently asked, picking a person for users who have just joined
+    pub fn current_question(&mut self, user_id: i64) -> Result<OutputAction, StateError> {
+        if let Some(child_ix) = self.surname_checks.get(&user_id).copied() {
+            let child = &self.graph.node_weight(child_ix).ok_or(StateError::UnknownPerson(child_ix))?.name;
+            if let Some(surname) = &child.surname {
+                return Ok(OutputAction::ConfirmSurname(child.clone(), surname.clone()));
+            }
+        }
+        match self.cursor(user_id)? {
+            None if self.graph.node_count() == 0 => Ok(OutputAction::AskRoot),
+            Some(ix) if self.graph[ix].completeness != NodeCompleteness::ChildrenComplete => self.ask_about(ix),
+            _ => self.switch_next_relative(user_id)
+        }
+    }
+
+    /// Moves the user on from the person they are asked about, e.g. when that person is gone
+    pub fn pick_another(&mut self, user_id: i64) -> Result<OutputAction, StateError> {
+        let current = self.cursors.remove(&user_id).and_then(|cursor| cursor.ix);
+        self.surname_checks.remove(&user_id);
+        self.queues.remove(&user_id);
+        match self.get_next_node(user_id, current) {
+            Some(ix) => {
+                self.cursors.insert(user_id, DescribedNodeInfo::new(Some(ix)));
+                self.ask_about(ix)
+            }
+            None => self.current_question(user_id)
+        }
+    }
+
+    /// Current revision, an answer given now can be undone with it until the tree changes again
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn next_revision(&mut self) {
+        self.revisions += 1;
+        self.revision = self.revisions;
+    }
+
+    /// Takes back the answer which brought the tree to the revision, only if it's still the latest change
+    pub fn undo_answer(&mut self, user_id: i64, revision: u64) -> Result<OutputAction, StateError> {
+        if revision != self.revision {
+            return Err(StateError::NothingToUndo);
+        }
+        self.undo(user_id)
+    }
+
+    /// Takes back the user's last answer unless somebody has changed the tree since
+    pub fn undo(&mut self, user_id: i64) -> Result<OutputAction, StateError> {
+        let history = self.snapshots.entry(user_id).or_default();
+        if history.last().map(|snapshot| snapshot.revision) != Some(self.revision) {
+            return Err(StateError::NothingToUndo);
+        }
+        let snapshot = history.pop().ok_or(StateError::NothingToUndo)?;
+        self.graph = snapshot.graph;
+        self.marriages = snapshot.marriages;
+        match snapshot.cursor {
+            Some(cursor) => self.cursors.insert(user_id, cursor),
+            None => self.cursors.remove(&user_id)
+        };
+        match snapshot.surname_check {
+            Some(child_ix) => self.surname_checks.insert(user_id, child_ix),
+            None => self.surname_checks.remove(&user_id)
+        };
+        match snapshot.queue {
+            Some(queue) => self.queues.insert(user_id, queue),
+            None => self.queues.remove(&user_id)
+        };
+        // the tree is as it was before the answer, so answers given before it can be undone in turn
+        self.revision = snapshot.previous;
+        self.current_question(user_id)
+    }
+
+    /// Handles the command and attributes people added by it to the author
+    pub fn handle_command_by(&mut self, input_command: InputAction, author: &Author) -> Result<OutputAction, StateError> {
+        let count = self.graph.node_count();
+        let output_action = self.handle_command(author.user_id, input_command)?;
+        for ix in self.graph.node_indices().skip(count) {
+            self.graph[ix].author = Some(author.clone());
+        }
+        Ok(output_action)
+    }
+
+    fn check_surname(&mut self, child_ix: NodeIndex<u32>, input_command: InputAction) -> Result<OutputAction, StateError> {
+        let name = &mut self.graph.node_weight_mut(child_ix).ok_or(StateError::UnknownPerson(child_ix))?.name;
+        match input_command {
+            InputAction::No => name.surname = None,
+            InputAction::Text(surname) => name.surname = Some(surname.trim().to_string()).filter(|surname| !surname.is_empty()),
+            _ => {},
+        }
+        Ok(OutputAction::AskSecondParent(Subject::named(name.clone())))
+    }
+
+    /// Applies the answer, remembering the state before it so the user can go back
+    pub fn handle_command(&mut self, user_id: i64, input_command: InputAction) -> Result<OutputAction, StateError> {
+        if let InputAction::Back = input_command {
+            return self.undo(user_id);
+        }
+        if let InputAction::Text(text) = input_command {
+            let naming = self.editing.get(&user_id).copied().filter(|ix| self.graph.node_weight(*ix).is_some_and(|person| person.placeholder));
+            if let Some(ix) = naming {
+                return self.record(user_id, |updater| updater.name_placeholder(user_id, ix, text));
+            }
+        }
+        self.record(user_id, |updater| updater.advance(user_id, input_command))
+    }
+
+    /// Makes the change, remembering the state before it so the user can undo it
+    fn record<T, E, F: FnOnce(&mut Self) -> Result<T, E>>(&mut self, user_id: i64, change: F) -> Result<T, E> {
+        let before = Snapshot {
+            graph: self.graph.clone(),
+            marriages: self.marriages.clone(),
+            cursor: self.cursors.get(&user_id).cloned(),
+            surname_check: self.surname_checks.get(&user_id).copied(),
+            queue: self.queues.get(&user_id).cloned(),
+            revision: 0,
+            previous: self.revision,
+        };
+        let changed = change(self)?;
+        self.next_revision();
+        let history = self.snapshots.entry(user_id).or_default();
+        history.push(Snapshot { revision: self.revision, ..before });
+        if history.len() > UNDO_DEPTH {
+            history.remove(0);
+        }
+        Ok(changed)
+    }
+
+    /// Works out what the statement would change and keeps it until the user confirms
+    pub fn propose(&mut self, user_id: i64, statement: Statement) -> Result<Proposal, StatementError> {
+        let (graph, marriages) = (self.graph.clone(), self.marriages.clone());
+        let proposal = self.link(&statement);
+        self.graph = graph;
+        self.marriages = marriages;
+        if proposal.is_ok() {
+            self.statements.insert(user_id, statement);
+        }
+        proposal
+    }
+
+    /// Applies the statement the author has confirmed, it can be undone like an answer
+    pub fn apply_statement(&mut self, author: &Author) -> Result<Proposal, StatementError> {
+        let statement = self.statements.remove(&author.user_id).ok_or(StatementError::NothingPending)?;
+        let count = self.graph.node_count();
+        let proposal = self.record(author.user_id, |updater| updater.link(&statement))?;
+        for ix in self.graph.node_indices().skip(count) {
+            self.graph[ix].author = Some(author.clone());
+        }
+        Ok(proposal)
+    }
+
+    /// Person named in a statement, None if they aren't in the tree yet
+    fn resolve(&self, name: &str) -> Result<Option<NodeIndex<u32>>, StatementError> {
+        match self.find_people(name)[..] {
+            [] => Ok(None),
+            [person] => Ok(Some(person)),
+            ref people => Err(StatementError::Ambiguous(people.iter().map(|ix| self.person_name(*ix)).collect()))
+        }
+    }
+
+    fn parents(&self, ix: NodeIndex<u32>) -> Vec<NodeIndex<u32>> {
+        self.graph.neighbors_directed(ix, Direction::Incoming).collect()
+    }
+
+    fn link_parent(&mut self, parent: NodeIndex<u32>, child: NodeIndex<u32>) -> Result<bool, StatementError> {
+        if self.graph.find_edge(parent, child).is_some() {
+            return Err(StatementError::AlreadyKnown);
+        }
+        self.graph.add_edge(parent, child, "");
+        Ok(false)
+    }
+
+    /// Links through an unnamed parent of the grandchild: a placeholder who has room for one more parent
+    /// or a new one, as long as the grandchild has room for them
+    fn link_grandparent(&mut self, grandparent: NodeIndex<u32>, grandchild: NodeIndex<u32>) -> Result<bool, StatementError> {
+        let parents = self.parents(grandchild);
+        if parents.iter().any(|parent| self.graph.find_edge(grandparent, *parent).is_some()) {
+            return Err(StatementError::AlreadyKnown);
+        }
+        let placeholder = parents.iter().copied().find(|parent| self.graph[*parent].placeholder && self.parents(*parent).len() < MAX_PARENTS);
+        let parent = match placeholder {
+            Some(parent) => parent,
+            None if parents.len() < MAX_PARENTS => {
+                let parent = self.graph.add_node(Person::placeholder(NodeCompleteness::ChildrenComplete));
+                self.graph.add_edge(parent, grandchild, "");
+                parent
+            }
+            None => return Err(StatementError::ParentUnclear(self.person_name(grandchild)))
+        };
+        self.graph.add_edge(grandparent, parent, "");
+        Ok(true)
+    }
+
+    /// Gives one sibling the parents of the other, or both an unnamed parent if nobody's parents are known
+    fn link_siblings(&mut self, person: NodeIndex<u32>, sibling: NodeIndex<u32>) -> Result<bool, StatementError> {
+        let (parents, sibling_parents) = (self.parents(person), self.parents(sibling));
+        if parents.iter().any(|parent| sibling_parents.contains(parent)) {
+            return Err(StatementError::AlreadyKnown);
+        }
+        let (known, child) = match (parents.is_empty(), sibling_parents.is_empty()) {
+            (_, false) => (sibling_parents, person),
+            (false, true) => (parents, sibling),
+            (true, true) => {
+                let parent = self.graph.add_node(Person::placeholder(NodeCompleteness::ChildrenComplete));
+                self.graph.add_edge(parent, person, "");
+                self.graph.add_edge(parent, sibling, "");
+                return Ok(true);
+            }
+        };
+        for parent in known {
+            self.graph.add_edge(parent, child, "");
+        }
+        Ok(false)
+    }
+
+    /// Adds the people and links of the statement, the caller restores the tree if it's only a proposal
+    fn link(&mut self, statement: &Statement) -> Result<Proposal, StatementError> {
+        let (person, relative) = (self.resolve(&statement.person)?, self.resolve(&statement.relative)?);
+        let same_new_name = statement.person.trim().to_lowercase() == statement.relative.trim().to_lowercase();
+        if (person.is_some() && person == relative) || (person.is_none() && relative.is_none() && same_new_name) {
+            return Err(StatementError::SamePerson);
+        }
+        let count = self.graph.node_count();
+        let person = person.unwrap_or_else(|| self.graph.add_node(Person::parse(&statement.person, NEW_NODE_STATUS)));
+        let relative = relative.unwrap_or_else(|| self.graph.add_node(Person::parse(&statement.relative, NEW_NODE_STATUS)));
+        let new_people: Vec<NodeIndex<u32>> = self.graph.node_indices().skip(count).collect();
+        if let Some(sex) = statement.sex {
+            self.graph[person].sex.get_or_insert(sex);
+        }
+
+        let through_unknown = match statement.kinship {
+            Kinship::Parent => self.link_parent(person, relative)?,
+            Kinship::Child => self.link_parent(relative, person)?,
+            Kinship::Grandparent => self.link_grandparent(person, relative)?,
+            Kinship::Grandchild => self.link_grandparent(relative, person)?,
+            Kinship::Sibling => self.link_siblings(person, relative)?,
+            Kinship::Spouse => {
+                if self.marriages.iter().any(|marriage| marriage.spouses.contains(&person) && marriage.spouses.contains(&relative)) {
+                    return Err(StatementError::AlreadyKnown);
+                }
+                self.marriages.push(Marriage { spouses: [person, relative], year: statement.year });
+                false
+            }
+        };
+
+        //new people join the interview where the statement leaves off
+        for ix in &new_people {
+            self.graph[*ix].completeness = match (statement.kinship, self.parents(*ix).len()) {
+                (_, 0) => NodeCompleteness::Plain,
+                (Kinship::Sibling, _) => NodeCompleteness::SiblingsComplete,
+                (_, 1) => NodeCompleteness::OneParent,
+                _ => NodeCompleteness::ParentsComplete,
+            };
+        }
+        Ok(Proposal {
+            person: self.graph[person].name.clone(),
+            kinship: statement.kinship,
+            relative: self.graph[relative].name.clone(),
+            sex: statement.sex,
+            year: statement.year,
+            new_people: new_people.iter().map(|ix| self.person_name(*ix)).collect(),
+            through_unknown,
+        })
+    }
+
+    /// Gives the placeholder chosen with /edit the name from the answer
+    fn name_placeholder(&mut self, user_id: i64, ix: NodeIndex<u32>, text: &str) -> Result<OutputAction, StateError> {
+        let names = split_names(text);
+        let [name] = &names[..] else {
+            return Err(StateError::InvalidInput);
+        };
+        let named = Person::parse(name, NEW_NODE_STATUS);
+        let person = &mut self.graph[ix];
+        person.name = named.name;
+        person.lifespan = named.lifespan;
+        person.placeholder = false;
+        self.editing.remove(&user_id);
+        self.current_question(user_id)
+    }
+
+    /// Placeholders called by their children, to choose whom to name
+    pub fn placeholders(&self) -> Vec<(NodeIndex<u32>, Called)> {
+        self.graph.node_indices()
+            .filter(|ix| self.graph[*ix].placeholder)
+            .map(|ix| (ix, called(&self.graph, ix)))
+            .collect()
+    }
+
+    fn add_placeholder_parent(&mut self, ix: NodeIndex<u32>) {
+        let parent_ix = self.graph.add_node(Person::placeholder(NEW_NODE_STATUS));
+        self.graph.add_edge(parent_ix, ix, "");
+    }
+
+    fn advance(&mut self, user_id: i64, input_command: InputAction) -> Result<OutputAction, StateError> {
+        let asked = self.cursor(user_id)?;
+        let output_action = self.apply_answer(user_id, input_command)?;
+        //placeholders are only asked about their parents
+        let placeholder = asked.filter(|ix| self.graph.node_weight(*ix).is_some_and(|person| {
+            person.placeholder && [NodeCompleteness::ParentsComplete, NodeCompleteness::SiblingsComplete].contains(&person.completeness)
+        }));
+        if let Some(ix) = placeholder {
+            self.graph[ix].completeness = NodeCompleteness::ChildrenComplete;
+            if self.cursor(user_id)? == Some(ix) {
+                return self.switch_next_relative(user_id);
             }
         }
+        Ok(output_action)
     }
 
-    pub fn handle_command (&mut self, input_command: InputAction) -> OutputAction {
-        let described_ix = &self.described_ix; //todo rename
-        match (described_ix.ix, input_command) {
-            (None, InputAction::Text(name)) => {
-                let root_index = self.graph.add_node(Person::new(name.to_string(), NEW_NODE_STATUS));
-                self.described_ix = DescribedNodeInfo::new(Some(root_index));
-                OutputAction::AskFirstParent(name.to_string())
+    fn apply_answer(&mut self, user_id: i64, input_command: InputAction) -> Result<OutputAction, StateError> {
+        if let Some(child_ix) = self.surname_checks.remove(&user_id) {
+            //skipping or stopping keeps the guessed surname and applies to the child
+            if !matches!(input_command, InputAction::Skip | InputAction::Stop) {
+                return self.check_surname(child_ix, input_command);
+            }
+        }
+        match (self.cursor(user_id)?, input_command) {
+            (None, InputAction::Text(text)) if self.graph.node_count() == 0 => {
+                let names = split_names(text);
+                let [name] = &names[..] else {
+                    return Err(StateError::InvalidInput);
+                };
+                let person = Person::parse(name, NEW_NODE_STATUS);
+                let root_index = self.graph.add_node(person);
+                self.cursors.insert(user_id, DescribedNodeInfo::new(Some(root_index)));
+                Ok(OutputAction::AskFirstParent(self.named(root_index)))
+            }
+            (None, _) => {
+                //user joined the tree someone else has started, the answer can't belong to any question yet
+                self.current_question(user_id)
             }
+            (Some(ix), InputAction::Skip) => self.skip(user_id, ix),
+            (Some(ix), InputAction::Stop) => self.stop_branch(user_id, ix),
             (Some(ix), command) => {
-                let current_status: &NodeCompleteness;
-                let described_name: String;
+                let current_status = &self.graph[ix].completeness;
+                let described_name = self.named(ix);
                 let described_ix_copy = ix;
-                {
-                    let node = &self.graph[ix];
-                    current_status = &node.completeness;
-                    described_name = node.name.clone();
+                let names = match command {
+                    InputAction::Text(text) => split_names(text),
+                    _ => vec![],
+                };
+                if matches!(command, InputAction::Text(_)) && names.is_empty() && *current_status != NodeCompleteness::ChildrenComplete {
+                    return Err(StateError::InvalidInput);
                 }
 
                 match (&current_status, command) {
                     (NodeCompleteness::Plain, InputAction::No) => {
                         self.graph[described_ix_copy].completeness = NodeCompleteness::SiblingsComplete;
-                        self.switch_next_relative()
+                        self.switch_next_relative(user_id)
                     },
-                    (NodeCompleteness::Plain, InputAction::Text(text)) => {
-                        self.add_parent(&described_ix_copy, text);
+                    (NodeCompleteness::Plain, InputAction::Text(_)) => {
+                        match &names[..] {
+                            [parent] => {
+                                self.add_parent(&described_ix_copy, parent);
+                                self.graph[described_ix_copy].completeness = NodeCompleteness::OneParent;
+                                Ok(OutputAction::AskSecondParent(described_name))
+                            }
+                            [first, second] => {
+                                self.add_parent(&described_ix_copy, first);
+                                self.add_parent(&described_ix_copy, second);
+                                self.graph[described_ix_copy].completeness = NodeCompleteness::ParentsComplete;
+                                Ok(OutputAction::AskIfSiblings(described_name))
+                            }
+                            _ => Err(StateError::InvalidInput)
+                        }
+                    },
+                    (NodeCompleteness::Plain, InputAction::Unknown) => {
+                        self.add_placeholder_parent(described_ix_copy);
                         self.graph[described_ix_copy].completeness = NodeCompleteness::OneParent;
-                        OutputAction::AskSecondParent(described_name)
+                        Ok(OutputAction::AskSecondParent(described_name))
+                    },
+                    (NodeCompleteness::OneParent, InputAction::Unknown) => {
+                        self.add_placeholder_parent(described_ix_copy);
+                        self.graph[described_ix_copy].completeness = NodeCompleteness::ParentsComplete;
+                        Ok(OutputAction::AskIfSiblings(described_name))
                     },
                     (NodeCompleteness::OneParent, InputAction::No) => {
                         self.graph[described_ix_copy].completeness = NodeCompleteness::ParentsComplete;
-                        self.switch_next_relative()
+                        self.switch_next_relative(user_id)
                     },
-                    (NodeCompleteness::OneParent, InputAction::Text(text)) => {
-                        self.add_parent(&described_ix_copy, text);
+                    (NodeCompleteness::OneParent, InputAction::Text(_)) => {
+                        let [parent] = &names[..] else {
+                            return Err(StateError::InvalidInput);
+                        };
+                        self.add_parent(&described_ix_copy, parent);
                         self.graph[described_ix_copy].completeness = NodeCompleteness::ParentsComplete;
-                        OutputAction::AskIfSiblings(described_name)
+                        Ok(OutputAction::AskIfSiblings(described_name))
                     },
                     (NodeCompleteness::ParentsComplete, InputAction::No) => { //end siblings. switch to next
                         self.graph[described_ix_copy].completeness = NodeCompleteness::SiblingsComplete;
-                        self.switch_next_relative()
+                        self.switch_next_relative(user_id)
                     },
-                    (NodeCompleteness::ParentsComplete, InputAction::Text(text),) => { //add sibling 
-                        self.add_sibling(&described_ix_copy, text);
-                        OutputAction::AskIfMoreSiblings(described_name)
+                    (NodeCompleteness::ParentsComplete, InputAction::Text(_),) => { //add siblings
+                        for sibling in &names {
+                            self.add_sibling(&described_ix_copy, sibling);
+                        }
+                        Ok(OutputAction::AskIfMoreSiblings(described_name))
                     },
                     (NodeCompleteness::SiblingsComplete, InputAction::No) => { //end children. switch to next
                         self.graph[described_ix_copy].completeness = NodeCompleteness::ChildrenComplete;
-                        self.switch_next_relative()
+                        self.switch_next_relative(user_id)
                     },
-                    (NodeCompleteness::SiblingsComplete, InputAction::Text(text)) => { //add child 
-                        let child_id = self.add_child(&described_ix_copy, text);
-                        self.described_ix = DescribedNodeInfo::new(Some(child_id)); //switch describe child
-                        OutputAction::AskSecondParent(text.to_string())
+                    (NodeCompleteness::SiblingsComplete, InputAction::Text(_)) if names.len() > 1 => { //add children, describe them in turn
+                        let mut children: Vec<NodeIndex<u32>> = names.iter().map(|child| self.add_child(&described_ix_copy, child)).collect();
+                        for child_id in &children {
+                            //a list can't be confirmed child by child, the surname can be fixed with /edit
+                            self.infer_surname(*child_id, described_ix_copy);
+                        }
+                        let first_child = children.remove(0);
+                        self.cursors.insert(user_id, DescribedNodeInfo::new(Some(first_child)));
+                        self.queues.entry(user_id).or_default().splice(0..0, children);
+                        Ok(OutputAction::AskSecondParent(self.named(first_child)))
+                    },
+                    (NodeCompleteness::SiblingsComplete, InputAction::Text(_)) => { //add child
+                        let child_id = self.add_child(&described_ix_copy, &names[0]);
+                        self.cursors.insert(user_id, DescribedNodeInfo::new(Some(child_id))); //switch describe child
+                        match self.infer_surname(child_id, described_ix_copy) {
+                            Some(surname) => {
+                                self.surname_checks.insert(user_id, child_id);
+                                Ok(OutputAction::ConfirmSurname(self.graph[child_id].name.clone(), surname))
+                            }
+                            None => Ok(OutputAction::AskSecondParent(self.named(child_id)))
+                        }
                     },
                     (NodeCompleteness::ChildrenComplete, _) => {
-                        OutputAction::NotifyError
+                        //someone else might have added people while this user was done
+                        match self.get_next_node(user_id, None) {
+                            Some(_) => self.switch_next_relative(user_id),
+                            None => Err(StateError::TreeFinished)
+                        }
                     }
+                    (_, _) => {
+                        self.ask_about(described_ix_copy)
+                    },
                 }
             }
-            _ => {
-                OutputAction::NotifyError
-            }
         }
     }
 }
@@ -171,7 +814,25 @@ impl GraphUpdater {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::updater::model::Sex;
+    use crate::updater::name::PersonName;
+    use crate::updater::statement::parse_statement;
+
+    fn named(name: &str) -> Subject {
+        Subject::named(PersonName::parse(name))
+    }
 
+    fn unknown(children: &[&str]) -> Called {
+        Called::Unknown(None, children.iter().map(|name| PersonName::parse(name).into()).collect())
+    }
+
+    fn described(name: &str, parents: &[&str], children: &[&str]) -> Subject {
+        let names = |names: &[&str]| names.iter().map(|name| PersonName::parse(name).into()).collect();
+        Subject::new(PersonName::parse(name), names(parents), names(children))
+    }
+
+    const USER : i64 = 1;
+    const OTHER_USER : i64 = 2;
     const ROOT_NODE : &str = "Robert";
     const MOM_NODE : &str = "Alexandra";
     const DAD_NODE : &str = "Bernard";
@@ -182,8 +843,11 @@ mod tests {
     #[test]
     fn empty() {
         let updater = GraphUpdater::new();
-        assert_eq!(updater.print_dot(), 
+        assert_eq!(updater.print_dot(Theme::Plain, &PhotoFiles::new(), Lang::En),
 "digraph {
+    graph [ rankdir = TB, bgcolor = \"white\" ]
+    node [ shape = box, style = \"rounded\", color = \"black\", fontcolor = \"black\", fontname = \"Helvetica\" ]
+    edge [ color = \"black\", arrowsize = 0.6 ]
 }
 ");
     }
@@ -191,43 +855,160 @@ mod tests {
     #[test]
     fn one_node_added_complete() {
         let mut updater = GraphUpdater::new();
-        let output_action = updater.handle_command(InputAction::Text(ROOT_NODE));
-        let output_action_1 = updater.handle_command(InputAction::No);
-        let output_action_2 = updater.handle_command(InputAction::No);
-        let output_action_3 = updater.handle_command(InputAction::Text(""));
-        assert_eq!(output_action, OutputAction::AskFirstParent(ROOT_NODE.to_string()), "Should ask for 1st parent");
-        assert_eq!(output_action_1, OutputAction::AskIfChildren(ROOT_NODE.to_string()), "Should ask for kids");
+        let output_action = updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        let output_action_1 = updater.handle_command(USER, InputAction::No).unwrap();
+        let output_action_2 = updater.handle_command(USER, InputAction::No).unwrap();
+        let output_action_3 = updater.handle_command(USER, InputAction::Text(""));
+        assert_eq!(output_action, OutputAction::AskFirstParent(named(ROOT_NODE)), "Should ask for 1st parent");
+        assert_eq!(output_action_1, OutputAction::AskIfChildren(named(ROOT_NODE)), "Should ask for kids");
         assert_eq!(output_action_2, OutputAction::NotifyComplete, "Should finilize graph");
-        assert_eq!(output_action_3, OutputAction::NotifyError, "Should notify that graph is already finished");
-        assert_eq!(updater.print_dot(),
+        assert_eq!(output_action_3, Err(StateError::TreeFinished), "Should notify that graph is already finished");
+        assert_eq!(updater.print_dot(Theme::Plain, &PhotoFiles::new(), Lang::En),
 format!("digraph {{
-    0 [ label = \"{}\" ]
+    graph [ rankdir = TB, bgcolor = \"white\" ]
+    node [ shape = box, style = \"rounded\", color = \"black\", fontcolor = \"black\", fontname = \"Helvetica\" ]
+    edge [ color = \"black\", arrowsize = 0.6 ]
+    0 [ label = \"{}\", penwidth = 2 ]
 }}
 ", ROOT_NODE), "Should print graph with root node");
     }
 
+    #[test]
+    fn current_question_repeats_pending_prompt() {
+        let mut updater = GraphUpdater::new();
+        assert_eq!(updater.current_question(USER).unwrap(), OutputAction::AskRoot, "Should ask for the first person");
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        assert_eq!(updater.current_question(USER).unwrap(), OutputAction::AskSecondParent(described(ROOT_NODE, &[MOM_NODE], &[])), "Should repeat question about 2nd parent");
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        assert_eq!(updater.current_question(USER).unwrap(), OutputAction::NotifyComplete, "Should report finished tree");
+    }
+
+    #[test]
+    fn added_people_are_attributed() {
+        let mut updater = GraphUpdater::new();
+        let author = Author::new(1, "Anna".to_string());
+        let collaborator = Author::new(2, "Bruce".to_string());
+        updater.handle_command_by(InputAction::Text(ROOT_NODE), &author).unwrap();
+        let count = updater.node_count();
+        updater.current_question(collaborator.user_id).unwrap();
+        updater.handle_command_by(InputAction::Text(MOM_NODE), &collaborator).unwrap();
+        updater.handle_command_by(InputAction::No, &collaborator).unwrap();
+
+        assert_eq!(updater.names_since(count), vec![MOM_NODE.to_string()], "Should list people added by collaborator");
+        assert_eq!(updater.graph[NodeIndex::new(0)].author, Some(author), "Should keep the author of root");
+        assert_eq!(updater.graph[NodeIndex::new(1)].author, Some(collaborator), "Should attribute parent to collaborator");
+    }
+
+    #[test]
+    fn users_get_separate_cursors() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(DAD_NODE)).unwrap();
+
+        let joined = updater.handle_command(OTHER_USER, InputAction::Text(BRO_NODE)).unwrap();
+        assert_eq!(joined, OutputAction::AskFirstParent(described(MOM_NODE, &[], &[ROOT_NODE])), "Should ask newcomer about someone else instead of taking the answer");
+        let other_answer = updater.handle_command(OTHER_USER, InputAction::Text("Clara")).unwrap();
+        assert_eq!(other_answer, OutputAction::AskSecondParent(named(MOM_NODE)), "Should continue with newcomer's own person");
+        let own_answer = updater.handle_command(USER, InputAction::Text(BRO_NODE)).unwrap();
+        assert_eq!(own_answer, OutputAction::AskIfMoreSiblings(named(ROOT_NODE)), "Should keep the first user's question");
+        updater.handle_command(USER, InputAction::No).unwrap();
+        assert_eq!(updater.current_question(USER).unwrap(), OutputAction::AskFirstParent(described(DAD_NODE, &[], &[BRO_NODE, ROOT_NODE])), "Should skip person described by other user");
+    }
+
+    #[test]
+    fn child_surname_is_inferred_from_parent() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text("Robert Smith")).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        let output_action_1 = updater.handle_command(USER, InputAction::Text(CHILD_NODE)).unwrap();
+        let output_action_2 = updater.handle_command(USER, InputAction::Yes).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::Text(BRO_NODE)).unwrap();
+        let output_action_3 = updater.handle_command(USER, InputAction::Text("Brown")).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::Text("Clara Jones (née Smith)")).unwrap();
+
+        assert_eq!(output_action_1, OutputAction::ConfirmSurname(PersonName::parse("Anna Smith"), "Smith".to_string()), "Should ask to confirm guessed surname");
+        assert_eq!(output_action_2, OutputAction::AskSecondParent(named("Anna Smith")), "Should continue with child after confirmation");
+        assert_eq!(output_action_3, OutputAction::AskSecondParent(named("Bruce Brown")), "Should take written surname instead");
+        assert_eq!(updater.names_since(0), vec!["Robert Smith", "Anna Smith", "Bruce Brown", "Clara Jones (née Smith)"]);
+    }
+
+    #[test]
+    fn people_are_found_by_name() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text("Anna Smith")).unwrap();
+        updater.handle_command(USER, InputAction::Text("Anna")).unwrap();
+        updater.handle_command(USER, InputAction::Text("Bernard Smith")).unwrap();
+        assert_eq!(updater.find_people("anna"), vec![NodeIndex::new(1)], "Should prefer the exact name");
+        assert_eq!(updater.find_people("Smith"), vec![NodeIndex::new(0), NodeIndex::new(2)]);
+        assert_eq!(updater.find_people("Clara"), vec![]);
+    }
+
+    #[test]
+    fn new_warnings_are_reported_once() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text("Robert 1980")).unwrap();
+        updater.handle_command(USER, InputAction::Text("Alexandra 1985")).unwrap();
+        let warnings = updater.take_new_warnings();
+        assert_eq!(warnings.len(), 1, "Should warn about child born before parent");
+        assert_eq!(updater.take_new_warnings(), vec![], "Should not repeat known warnings");
+        let revision = updater.revision();
+        assert_eq!(updater.unlink(revision, warnings[0].parent, warnings[0].child), Ok(Some(("Alexandra".to_string(), ROOT_NODE.to_string()))));
+        assert_eq!(updater.take_new_warnings(), vec![]);
+        assert_eq!(updater.unlink(updater.revision(), warnings[0].parent, warnings[0].child), Ok(None), "Should not remove link twice");
+    }
+
+    #[test]
+    fn outdated_fix_keeps_the_link() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text("Robert 1980")).unwrap();
+        updater.handle_command(USER, InputAction::Text("Alexandra 1985")).unwrap();
+        let warnings = updater.take_new_warnings();
+        let revision = updater.revision();
+        updater.undo(USER).unwrap();
+        updater.handle_command(USER, InputAction::Text("Anna 1950")).unwrap();
+        assert_eq!(updater.unlink(revision, warnings[0].parent, warnings[0].child), Err(StateError::Outdated), "Should not unlink whoever has the indices now");
+        assert_eq!(updater.node_count(), 2);
+        assert_eq!(updater.warnings(), vec![]);
+    }
+
     #[test]
     fn family_with_two_children() {
         let mut updater = GraphUpdater::new();
-        let output_action_1 = updater.handle_command(InputAction::Text(ROOT_NODE));
-        let output_action_2 = updater.handle_command(InputAction::Text(MOM_NODE));
-        let output_action_3 = updater.handle_command(InputAction::Text(DAD_NODE));
-        let output_action_4 = updater.handle_command(InputAction::Text(BRO_NODE));
+        let output_action_1 = updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        let output_action_2 = updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        let output_action_3 = updater.handle_command(USER, InputAction::Text(DAD_NODE)).unwrap();
+        let output_action_4 = updater.handle_command(USER, InputAction::Text(BRO_NODE)).unwrap();
 
-        assert_eq!(output_action_1, OutputAction::AskFirstParent(ROOT_NODE.to_string()), "Should ask for 1st parent");
-        assert_eq!(output_action_2, OutputAction::AskSecondParent(ROOT_NODE.to_string()), "Should ask for 2nd parent");
-        assert_eq!(output_action_3, OutputAction::AskIfSiblings(ROOT_NODE.to_string()), "Should ask for sibling");
-        assert_eq!(output_action_4, OutputAction::AskIfMoreSiblings(ROOT_NODE.to_string()), "Should ask for more siblings");
-        assert_eq!(updater.print_dot(),
+        assert_eq!(output_action_1, OutputAction::AskFirstParent(named(ROOT_NODE)), "Should ask for 1st parent");
+        assert_eq!(output_action_2, OutputAction::AskSecondParent(named(ROOT_NODE)), "Should ask for 2nd parent");
+        assert_eq!(output_action_3, OutputAction::AskIfSiblings(named(ROOT_NODE)), "Should ask for sibling");
+        assert_eq!(output_action_4, OutputAction::AskIfMoreSiblings(named(ROOT_NODE)), "Should ask for more siblings");
+        assert_eq!(updater.print_dot(Theme::Plain, &PhotoFiles::new(), Lang::En),
 format!("digraph {{
-    0 [ label = \"{}\" ]
+    graph [ rankdir = TB, bgcolor = \"white\" ]
+    node [ shape = box, style = \"rounded\", color = \"black\", fontcolor = \"black\", fontname = \"Helvetica\" ]
+    edge [ color = \"black\", arrowsize = 0.6 ]
+    0 [ label = \"{}\", penwidth = 2 ]
     1 [ label = \"{}\" ]
     2 [ label = \"{}\" ]
     3 [ label = \"{}\" ]
-    1 -> 0 [ label = \"\" ]
-    2 -> 0 [ label = \"\" ]
-    2 -> 3 [ label = \"\" ]
-    1 -> 3 [ label = \"\" ]
+    1 -> 0
+    2 -> 0
+    2 -> 3
+    1 -> 3
+    {{ rank = same; \"generation -1\" [ style = invis, shape = point ]; 1; 2; 1 -> 2 [ style = invis ]; }}
+    {{ rank = same; \"generation 0\" [ style = invis, shape = point ]; 0; 3; }}
+    \"generation -1\" -> \"generation 0\" [ style = invis ]
 }}
 ", ROOT_NODE, MOM_NODE, DAD_NODE, BRO_NODE), "Should print graph with 2 kids and 2 parents");
     }
@@ -235,26 +1016,345 @@ format!("digraph {{
     #[test]
     fn orphan_root_with_child_and_spouse() {
         let mut updater = GraphUpdater::new();
-        let output_action_1 = updater.handle_command(InputAction::Text(ROOT_NODE));
-        let output_action_2 = updater.handle_command(InputAction::No);
-        let output_action_3 = updater.handle_command(InputAction::Text(CHILD_NODE));
-        let output_action_4 = updater.handle_command(InputAction::Text(SPOUSE_NODE));
-        let output_action_5 = updater.handle_command(InputAction::No);
-
-        assert_eq!(output_action_1, OutputAction::AskFirstParent(ROOT_NODE.to_string()), "Should ask for parent");
-        assert_eq!(output_action_2, OutputAction::AskIfChildren(ROOT_NODE.to_string()), "Should jump straight to children");
-        assert_eq!(output_action_3, OutputAction::AskSecondParent(CHILD_NODE.to_string()), "Should switch to kid's second parent");
-        assert_eq!(output_action_4, OutputAction::AskIfSiblings(CHILD_NODE.to_string()), "Should check if kids has siblings");
-        assert_eq!(output_action_5, OutputAction::AskFirstParent(format!("{}, who is parent of {}", SPOUSE_NODE, CHILD_NODE)), "Should start asking about spouse");
-        assert_eq!(updater.print_dot(),
+        let output_action_1 = updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        let output_action_2 = updater.handle_command(USER, InputAction::No).unwrap();
+        let output_action_3 = updater.handle_command(USER, InputAction::Text(CHILD_NODE)).unwrap();
+        let output_action_4 = updater.handle_command(USER, InputAction::Text(SPOUSE_NODE)).unwrap();
+        let output_action_5 = updater.handle_command(USER, InputAction::No).unwrap();
+
+        assert_eq!(output_action_1, OutputAction::AskFirstParent(named(ROOT_NODE)), "Should ask for parent");
+        assert_eq!(output_action_2, OutputAction::AskIfChildren(named(ROOT_NODE)), "Should jump straight to children");
+        assert_eq!(output_action_3, OutputAction::AskSecondParent(named(CHILD_NODE)), "Should switch to kid's second parent");
+        assert_eq!(output_action_4, OutputAction::AskIfSiblings(named(CHILD_NODE)), "Should check if kids has siblings");
+        assert_eq!(output_action_5, OutputAction::AskFirstParent(described(SPOUSE_NODE, &[], &[CHILD_NODE])), "Should start asking about spouse");
+        assert_eq!(updater.print_dot(Theme::Plain, &PhotoFiles::new(), Lang::En),
 format!("digraph {{
-    0 [ label = \"{}\" ]
+    graph [ rankdir = TB, bgcolor = \"white\" ]
+    node [ shape = box, style = \"rounded\", color = \"black\", fontcolor = \"black\", fontname = \"Helvetica\" ]
+    edge [ color = \"black\", arrowsize = 0.6 ]
+    0 [ label = \"{}\", penwidth = 2 ]
     1 [ label = \"{}\" ]
     2 [ label = \"{}\" ]
-    0 -> 1 [ label = \"\" ]
-    2 -> 1 [ label = \"\" ]
+    0 -> 1
+    2 -> 1
+    {{ rank = same; \"generation 0\" [ style = invis, shape = point ]; 0; 2; 0 -> 2 [ style = invis ]; }}
+    {{ rank = same; \"generation 1\" [ style = invis, shape = point ]; 1; }}
+    \"generation 0\" -> \"generation 1\" [ style = invis ]
 }}
 ", ROOT_NODE, CHILD_NODE, SPOUSE_NODE), "Should print graph with root node and 1 child");
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn imported_tree_continues_with_missing_parents() {
+        let author = Author::new(USER, "Anna".to_string());
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(DAD_NODE)).unwrap();
+        let (people, relationships) = updater.export_tables();
+
+        let mut imported = GraphUpdater::new();
+        assert_eq!(imported.import_tables(&people, &relationships, &author), Ok(3));
+        assert_eq!(imported.export_tables(), (people, relationships), "Should export the same tables");
+        assert_eq!(imported.current_question(USER).unwrap(), OutputAction::AskFirstParent(described(MOM_NODE, &[], &[ROOT_NODE])), "Should ask about parents of imported people");
+        assert!(imported.import_tables("id,given\n1,\n", "from,to\n", &author).is_err());
+        assert_eq!(imported.node_count(), 3, "Should keep the tree if the tables are wrong");
+    }
+
+    #[test]
+    fn photo_goes_to_edited_person() {
+        let mut updater = GraphUpdater::new();
+        assert_eq!(updater.attach_photo(USER, "first"), None, "Should have nobody to attach the photo to");
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        assert_eq!(updater.attach_photo(USER, "root"), Some(PersonName::parse(ROOT_NODE).into()), "Should attach the photo to the person being asked about");
+
+        updater.edit(USER, NodeIndex::new(1));
+        assert_eq!(updater.attach_photo(USER, "mom"), Some(PersonName::parse(MOM_NODE).into()));
+        assert_eq!(updater.attach_photo(USER, "root again"), Some(PersonName::parse(ROOT_NODE).into()), "Should edit only until the photo is attached");
+        assert_eq!(updater.photos(), vec!["root again".to_string(), "mom".to_string()]);
+    }
+
+    #[test]
+    fn blank_answer_is_rejected() {
+        let mut updater = GraphUpdater::new();
+        assert_eq!(updater.handle_command(USER, InputAction::Text("  ")), Err(StateError::InvalidInput), "Should not start with a nameless person");
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        assert_eq!(updater.handle_command(USER, InputAction::Text("")), Err(StateError::InvalidInput), "Should not add a nameless parent");
+        assert_eq!(updater.node_count(), 1);
+        assert_eq!(updater.current_question(USER).unwrap(), OutputAction::AskFirstParent(named(ROOT_NODE)), "Should still wait for the first parent");
+    }
+
+    #[test]
+    fn undo_takes_back_last_answer() {
+        let mut updater = GraphUpdater::new();
+        assert_eq!(updater.undo(USER), Err(StateError::NothingToUndo), "Should have nothing to undo before the first answer");
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        assert_eq!(updater.undo(USER), Ok(OutputAction::AskFirstParent(named(ROOT_NODE))), "Should ask for the first parent again");
+        assert_eq!(updater.node_count(), 1, "Should remove the added parent");
+        assert_eq!(updater.undo(USER), Ok(OutputAction::AskRoot), "Should undo earlier answers in turn");
+        assert_eq!(updater.undo(USER), Err(StateError::NothingToUndo));
+
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.current_question(OTHER_USER).unwrap();
+        updater.handle_command(OTHER_USER, InputAction::Text("Clara")).unwrap();
+        assert_eq!(updater.undo(USER), Err(StateError::NothingToUndo), "Should keep answers given by others since");
+        assert_eq!(updater.node_count(), 3);
+    }
+
+    #[test]
+    fn another_person_after_undone_one() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.current_question(OTHER_USER).unwrap();
+        updater.undo(USER).unwrap();
+        assert_eq!(updater.handle_command(OTHER_USER, InputAction::Text("Clara")), Err(StateError::UnknownPerson(NodeIndex::new(1))), "Should notice the person is gone");
+        assert_eq!(updater.pick_another(OTHER_USER), Ok(OutputAction::AskFirstParent(named(ROOT_NODE))), "Should move on to someone still in the tree");
+    }
+
+    #[test]
+    fn another_person_is_not_asked_about_by_others() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(DAD_NODE)).unwrap();
+        assert_eq!(updater.current_question(OTHER_USER), Ok(OutputAction::AskFirstParent(described(MOM_NODE, &[], &[ROOT_NODE]))));
+        assert_eq!(updater.pick_another(OTHER_USER), Ok(OutputAction::AskFirstParent(described(DAD_NODE, &[], &[ROOT_NODE]))), "Should not pick the person another user is asked about");
+    }
+
+    #[test]
+    fn back_re_asks_previous_questions() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(DAD_NODE)).unwrap();
+        assert_eq!(updater.handle_command(USER, InputAction::Back), Ok(OutputAction::AskSecondParent(described(ROOT_NODE, &[MOM_NODE], &[]))), "Should ask for the second parent again");
+        assert_eq!(updater.handle_command(USER, InputAction::Back), Ok(OutputAction::AskFirstParent(named(ROOT_NODE))), "Should go back step by step");
+        assert_eq!(updater.node_count(), 1);
+    }
+
+    #[test]
+    fn skipped_person_is_asked_last() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(DAD_NODE)).unwrap();
+        assert_eq!(updater.handle_command(USER, InputAction::No), Ok(OutputAction::AskFirstParent(described(MOM_NODE, &[], &[ROOT_NODE]))));
+        assert_eq!(updater.handle_command(USER, InputAction::Skip), Ok(OutputAction::AskFirstParent(described(DAD_NODE, &[], &[ROOT_NODE]))), "Should move on to the next person");
+        let root_children = updater.handle_command(USER, InputAction::No).unwrap();
+        assert!(matches!(root_children, OutputAction::AskIfChildren(subject) if subject.name == PersonName::parse(ROOT_NODE).into()));
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        assert_eq!(updater.current_question(USER), Ok(OutputAction::AskFirstParent(described(MOM_NODE, &[], &[ROOT_NODE]))), "Should come back to the skipped person at the end");
+    }
+
+    #[test]
+    fn stop_completes_the_branch() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(DAD_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        updater.handle_command(USER, InputAction::Text("Clara")).unwrap();
+        assert_eq!(updater.handle_command(USER, InputAction::Stop), Ok(OutputAction::AskFirstParent(described(DAD_NODE, &[], &[ROOT_NODE]))), "Should continue with the other line");
+        for ix in [1, 3] {
+            assert_eq!(updater.graph[NodeIndex::new(ix)].completeness, NodeCompleteness::ChildrenComplete, "Should not ask about the person and their ancestors");
+        }
+    }
+
+    #[test]
+    fn both_parents_in_one_answer() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        assert_eq!(updater.handle_command(USER, InputAction::Text("Anna, Bruce, Clara")), Err(StateError::InvalidInput), "Should not take three parents");
+        let answer = format!("{} and {}", MOM_NODE, DAD_NODE);
+        assert_eq!(updater.handle_command(USER, InputAction::Text(&answer)), Ok(OutputAction::AskIfSiblings(named(ROOT_NODE))), "Should take both parents at once");
+        assert_eq!(updater.names_since(1), vec![MOM_NODE.to_string(), DAD_NODE.to_string()]);
+        assert_eq!(updater.handle_command(OTHER_USER, InputAction::Text("Anna, Bruce")), Ok(OutputAction::AskFirstParent(described(MOM_NODE, &[], &[ROOT_NODE]))));
+        updater.handle_command(OTHER_USER, InputAction::Text("Clara")).unwrap();
+        assert_eq!(updater.handle_command(OTHER_USER, InputAction::Text("Diana and Edward")), Err(StateError::InvalidInput), "Should not take two names for the second parent");
+    }
+
+    #[test]
+    fn years_after_a_comma_belong_to_the_name() {
+        let mut updater = GraphUpdater::new();
+        assert!(updater.handle_command(USER, InputAction::Text("Robert Smith, b. 1980")).is_ok(), "Should take the person as the bot writes them");
+        updater.handle_command(USER, InputAction::Text("Anna Smith, 1950–2010")).unwrap();
+        assert_eq!(updater.names_since(0), vec!["Robert Smith".to_string(), "Anna Smith".to_string()], "Should not add the years as another parent");
+        assert_eq!(updater.graph[NodeIndex::new(1)].to_string(), "Anna Smith, 1950–2010");
+    }
+
+    #[test]
+    fn siblings_in_one_answer() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(DAD_NODE)).unwrap();
+        let count = updater.node_count();
+        assert_eq!(updater.handle_command(USER, InputAction::Text("Bruce, Clara\nDiana")), Ok(OutputAction::AskIfMoreSiblings(named(ROOT_NODE))));
+        assert_eq!(updater.names_since(count), vec!["Bruce", "Clara", "Diana"]);
+        assert_eq!(updater.graph.neighbors_directed(NodeIndex::new(1), Direction::Outgoing).count(), 4, "Should give every sibling the same parents");
+    }
+
+    #[test]
+    fn children_in_one_answer_are_asked_in_turn() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text("Robert Smith")).unwrap();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        assert_eq!(updater.handle_command(USER, InputAction::Text("Anna and Bruce")), Ok(OutputAction::AskSecondParent(named("Anna Smith"))), "Should give surname without asking");
+        assert_eq!(updater.handle_command(USER, InputAction::No), Ok(OutputAction::AskSecondParent(described("Bruce Smith", &["Robert Smith"], &[]))), "Should continue with the next child of the list");
+        assert_eq!(updater.handle_command(USER, InputAction::Back), Ok(OutputAction::AskSecondParent(described("Anna Smith", &["Robert Smith"], &[]))));
+        let revision = updater.revision();
+        updater.handle_command(USER, InputAction::No).unwrap();
+        assert_eq!(updater.undo_answer(USER, revision), Err(StateError::NothingToUndo), "Should not undo an answer given before the latest");
+        updater.undo(USER).unwrap();
+        assert_eq!(updater.undo_answer(USER, revision), Ok(OutputAction::AskIfChildren(named("Robert Smith"))), "Should take back the whole list");
+        assert_eq!(updater.node_count(), 1);
+    }
+
+    fn tell(updater: &mut GraphUpdater, text: &str) -> Result<Proposal, StatementError> {
+        let author = Author::new(USER, "Anna".to_string());
+        updater.propose(USER, parse_statement(text).unwrap())?;
+        updater.apply_statement(&author)
+    }
+
+    #[test]
+    fn grandparent_through_unknown_parent() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        let proposal = updater.propose(USER, parse_statement("Maria is Robert's grandmother").unwrap()).unwrap();
+        assert_eq!(proposal.new_people, vec!["Maria"]);
+        assert!(proposal.through_unknown);
+        assert_eq!(updater.node_count(), 1, "Should not change the tree before the user confirms");
+
+        updater.apply_statement(&Author::new(USER, "Anna".to_string())).unwrap();
+        let maria = NodeIndex::new(1);
+        let parent = updater.parents(NodeIndex::new(ROOT))[0];
+        assert_eq!(updater.parents(parent), vec![maria]);
+        assert_eq!(updater.graph[maria].sex, Some(Sex::Female));
+        assert_eq!(updater.graph[maria].completeness, NodeCompleteness::Plain, "Should ask about the new person's parents");
+        assert_eq!(updater.graph[parent].completeness, NodeCompleteness::ChildrenComplete, "Should not ask about the unknown parent");
+        assert_eq!(tell(&mut updater, "Maria is the grandmother of Robert"), Err(StatementError::AlreadyKnown));
+        assert_eq!(updater.apply_statement(&Author::new(USER, "Anna".to_string())), Err(StatementError::NothingPending));
+    }
+
+    #[test]
+    fn grandparent_needs_a_free_parent() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(DAD_NODE)).unwrap();
+        assert_eq!(tell(&mut updater, "Clara is Robert's grandmother"), Err(StatementError::ParentUnclear(ROOT_NODE.to_string())));
+        assert!(tell(&mut updater, "Clara is Alexandra's mother").is_ok());
+        assert_eq!(tell(&mut updater, "Clara is Robert's grandmother"), Err(StatementError::AlreadyKnown));
+    }
+
+    #[test]
+    fn siblings_share_parents() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        let proposal = tell(&mut updater, "Bruce is Robert's brother").unwrap();
+        assert!(!proposal.through_unknown);
+        assert_eq!(updater.parents(NodeIndex::new(2)), vec![NodeIndex::new(1)]);
+        assert_eq!(updater.graph[NodeIndex::new(2)].completeness, NodeCompleteness::SiblingsComplete);
+        assert!(tell(&mut updater, "Clara is Diana's sister").unwrap().through_unknown, "Should link strangers through an unknown parent");
+        assert_eq!(tell(&mut updater, "Robert is Robert's brother"), Err(StatementError::SamePerson));
+    }
+
+    #[test]
+    fn marriage_is_drawn_and_undone() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text("John Smith")).unwrap();
+        tell(&mut updater, "John married Lisa in 1970").unwrap();
+        assert!(updater.print_dot(Theme::Plain, &PhotoFiles::new(), Lang::En).contains("0 -> 1 [ dir = none, style = dashed, constraint = false, label = \"⚭ 1970\" ]"));
+        assert_eq!(tell(&mut updater, "Lisa is John's wife"), Err(StatementError::AlreadyKnown));
+        updater.handle_command(USER, InputAction::Back).unwrap();
+        assert!(updater.marriages.is_empty(), "Should undo the statement like an answer");
+        assert_eq!(updater.node_count(), 1);
+    }
+
+    #[test]
+    fn marriages_survive_tables_round_trip() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text("John Smith")).unwrap();
+        tell(&mut updater, "John married Lisa in 1970").unwrap();
+        let (people, relationships) = updater.export_tables();
+        assert!(relationships.ends_with("0,1,spouse,1970\r\n"), "Should write the marriage: {}", relationships);
+
+        let mut imported = GraphUpdater::new();
+        imported.import_tables(&people, &relationships, &Author::new(USER, "Anna".to_string())).unwrap();
+        assert_eq!(imported.marriages, updater.marriages);
+        assert!(imported.export(ExportFormat::Mermaid, Lang::En).contains("    p0 -.-|⚭ 1970| p1\n"));
+        assert!(imported.export(ExportFormat::PlantUml, Lang::En).contains("p0 .. p1 : ⚭ 1970\n"));
+        assert!(imported.export_html("Smiths", &HashMap::new(), Lang::En).contains("<p>Spouses: <a href=\"#p1\">Lisa</a> (1970)</p>"));
+    }
+
+    #[test]
+    fn ambiguous_names_are_reported() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text("Anna Smith")).unwrap();
+        updater.handle_command(USER, InputAction::Text("Anna Brown")).unwrap();
+        assert_eq!(tell(&mut updater, "Bruce is Anna's son"), Err(StatementError::Ambiguous(vec!["Anna Smith".to_string(), "Anna Brown".to_string()])));
+    }
+
+    #[test]
+    fn placeholder_is_asked_only_about_parents() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        assert_eq!(updater.handle_command(USER, InputAction::Unknown), Ok(OutputAction::AskSecondParent(named(ROOT_NODE))));
+        updater.handle_command(USER, InputAction::No).unwrap();
+        let placeholder = NodeIndex::new(1);
+        assert!(updater.graph[placeholder].placeholder);
+        let placeholder_of_root = Subject::new(unknown(&[ROOT_NODE]), vec![], vec![PersonName::parse(ROOT_NODE).into()]);
+        assert_eq!(updater.handle_command(USER, InputAction::No), Ok(OutputAction::AskFirstParent(placeholder_of_root)), "Should ask about the placeholder's parents");
+        assert_eq!(updater.handle_command(USER, InputAction::Text("Maria")), Ok(OutputAction::AskSecondParent(Subject::named(unknown(&[ROOT_NODE])))));
+        let next = updater.handle_command(USER, InputAction::No).unwrap();
+        assert_eq!(next, OutputAction::AskFirstParent(Subject::new(PersonName::parse("Maria"), vec![], vec![unknown(&[ROOT_NODE])])), "Should skip siblings and children of the placeholder");
+        assert_eq!(updater.graph[placeholder].completeness, NodeCompleteness::ChildrenComplete);
+    }
+
+    #[test]
+    fn placeholder_is_named_with_edit() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Unknown).unwrap();
+        assert_eq!(updater.placeholders(), vec![(NodeIndex::new(1), unknown(&[ROOT_NODE]))]);
+        assert!(updater.print_dot(Theme::Classic, &PhotoFiles::new(), Lang::En).contains("1 [ label = \"Unknown parent of Robert\", style = \"rounded,dashed\", color = \"gray60\", fontcolor = \"gray60\" ]"), "Should draw the placeholder in grey");
+
+        updater.edit(USER, NodeIndex::new(1));
+        assert_eq!(updater.handle_command(USER, InputAction::Text("Bernard 1950")), Ok(OutputAction::AskSecondParent(described(ROOT_NODE, &["Bernard"], &[]))), "Should go on with the interview");
+        let person = &updater.graph[NodeIndex::new(1)];
+        assert_eq!((person.name.to_string(), person.lifespan.birth, person.placeholder), ("Bernard".to_string(), Some(1950), false));
+        assert!(updater.placeholders().is_empty());
+        assert_eq!(updater.handle_command(USER, InputAction::Text(MOM_NODE)), Ok(OutputAction::AskIfSiblings(named(ROOT_NODE))), "Should take the next answer as usual");
+    }
+
+    #[test]
+    fn grandparent_joins_placeholder_parent() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Unknown).unwrap();
+        tell(&mut updater, "Clara is Robert's grandmother").unwrap();
+        assert_eq!(updater.parents(NodeIndex::new(2)), vec![NodeIndex::new(3)], "Should link the grandmother through the placeholder");
+        assert_eq!(updater.node_count(), 4);
+    }
+
+    #[test]
+    fn lone_placeholders_are_not_exported() {
+        let mut updater = GraphUpdater::new();
+        updater.handle_command(USER, InputAction::Text(ROOT_NODE)).unwrap();
+        updater.handle_command(USER, InputAction::Unknown).unwrap();
+        updater.handle_command(USER, InputAction::Text(MOM_NODE)).unwrap();
+        assert!(updater.export(ExportFormat::Mermaid, Lang::En).contains("p1[\"Unknown parent of Robert\"]"), "Should keep placeholders linking people");
+        updater.unlink(updater.revision(), NodeIndex::new(1), NodeIndex::new(ROOT)).unwrap();
+        let exported = updater.export(ExportFormat::Mermaid, Lang::En);
+        assert!(!exported.contains("Unknown"));
+        assert!(exported.contains(&format!("p1[\"{}\"]", MOM_NODE)), "Should renumber the people after the placeholder");
+        assert!(!updater.export_tables().0.contains('?'));
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt::{self, Display};
+
+const BIRTH_MARKERS: [&str; 2] = ["b.", "*"];
+const DEATH_MARKERS: [&str; 3] = ["d.", "died", "†"];
+const YEAR_DASHES: [char; 2] = ['-', '–'];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Lifespan {
+    pub birth: Option<i32>,
+    pub death: Option<i32>,
+}
+
+fn parse_year(text: &str) -> Option<i32> {
+    if text.len() == 4 && text.chars().all(|c| c.is_ascii_digit()) { text.parse().ok() } else { None }
+}
+
+impl Lifespan {
+    /// Splits years like "1950-2010", "b. 1950", "*1950", "d. 2010" or a single birth year out of the text
+    pub fn extract(text: &str) -> (String, Lifespan) {
+        let mut lifespan = Lifespan::default();
+        let mut rest: Vec<&str> = vec![];
+        let mut marker: Option<&str> = None;
+
+        for word in text.split_whitespace() {
+            let token = word.trim_matches(|c| c == '(' || c == ')' || c == ',');
+            if BIRTH_MARKERS.contains(&token) || DEATH_MARKERS.contains(&token) {
+                if let Some(marker) = marker.replace(word) {
+                    rest.push(marker);
+                }
+                continue;
+            }
+            let (birth_marked, death_marked) = match marker {
+                Some(marker) => {
+                    let marker = marker.trim_matches(|c| c == '(' || c == ')' || c == ',');
+                    (BIRTH_MARKERS.contains(&marker), DEATH_MARKERS.contains(&marker))
+                }
+                None => (false, false)
+            };
+
+            let years = match token.split_once(&YEAR_DASHES[..]) {
+                Some((birth, death)) if (birth.is_empty() || parse_year(birth).is_some()) && (death.is_empty() || parse_year(death).is_some()) && token.len() > 1 => {
+                    Some((parse_year(birth), parse_year(death)))
+                }
+                Some(_) => None,
+                None => {
+                    if let Some(year) = token.strip_prefix('*').and_then(parse_year) {
+                        Some((Some(year), None))
+                    } else if let Some(year) = token.strip_prefix('†').and_then(parse_year) {
+                        Some((None, Some(year)))
+                    } else {
+                        match parse_year(token) {
+                            Some(year) if death_marked || (!birth_marked && lifespan.birth.is_some()) => Some((None, Some(year))),
+                            Some(year) => Some((Some(year), None)),
+                            None => None
+                        }
+                    }
+                }
+            };
+
+            match years {
+                Some((birth, death)) => {
+                    marker = None;
+                    lifespan.birth = birth.or(lifespan.birth);
+                    lifespan.death = death.or(lifespan.death);
+                }
+                None => {
+                    if let Some(marker) = marker.take() {
+                        rest.push(marker);
+                    }
+                    rest.push(word);
+                }
+            }
+        }
+        if let Some(marker) = marker {
+            rest.push(marker);
+        }
+        (rest.join(" "), lifespan)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.birth.is_none() && self.death.is_none()
+    }
+}
+
+impl Display for Lifespan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.birth, self.death) {
+            (Some(birth), Some(death)) => write!(f, "{}–{}", birth, death),
+            (Some(birth), None) => write!(f, "b. {}", birth),
+            (None, Some(death)) => write!(f, "d. {}", death),
+            (None, None) => Ok(())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lifespan(birth: Option<i32>, death: Option<i32>) -> Lifespan {
+        Lifespan { birth, death }
+    }
+
+    #[test]
+    fn no_years() {
+        assert_eq!(Lifespan::extract("Anna Smith (née Brown)"), ("Anna Smith (née Brown)".to_string(), Lifespan::default()));
+    }
+
+    #[test]
+    fn years_range() {
+        assert_eq!(Lifespan::extract("Anna Smith 1950-2010"), ("Anna Smith".to_string(), lifespan(Some(1950), Some(2010))));
+        assert_eq!(Lifespan::extract("Anna (1950–)"), ("Anna".to_string(), lifespan(Some(1950), None)));
+        assert_eq!(Lifespan::extract("Anna -2010"), ("Anna".to_string(), lifespan(None, Some(2010))));
+    }
+
+    #[test]
+    fn marked_years() {
+        assert_eq!(Lifespan::extract("Anna b. 1950 d. 2010"), ("Anna".to_string(), lifespan(Some(1950), Some(2010))));
+        assert_eq!(Lifespan::extract("Anna *1950 †2010"), ("Anna".to_string(), lifespan(Some(1950), Some(2010))));
+        assert_eq!(Lifespan::extract("Anna died 2010"), ("Anna".to_string(), lifespan(None, Some(2010))));
+        assert_eq!(Lifespan::extract("Anna 1950"), ("Anna".to_string(), lifespan(Some(1950), None)));
+        assert_eq!(Lifespan::extract("Anna d. Smith"), ("Anna d. Smith".to_string(), Lifespan::default()), "Should keep markers without years");
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(lifespan(Some(1950), Some(2010)).to_string(), "1950–2010");
+        assert_eq!(lifespan(Some(1950), None).to_string(), "b. 1950");
+        assert_eq!(lifespan(None, Some(2010)).to_string(), "d. 2010");
+    }
+}
@@ -1,3 +1,10 @@
+pub mod chart;
+pub mod export;
 pub mod graph_updater;
+pub mod lifespan;
 pub mod model;
-pub mod utility;
\ No newline at end of file
+pub mod name;
+pub mod statement;
+pub mod table;
+pub mod utility;
+pub mod validation;
@@ -1,28 +1,214 @@
-use strum_macros::EnumString;
-use strum_macros::Display;
 use std::fmt::{self, Display};
+use std::str::FromStr;
 use petgraph::{graph::{NodeIndex}};
+use super::lifespan::Lifespan;
+use super::name::PersonName;
+use super::chart::Theme;
+use crate::locale::Lang;
+use crate::session::Retention;
 
-#[derive(EnumString, Display, Debug)]
+const BUTTON_PAYLOAD_SEPARATOR: char = ':';
+/// Name of placeholders until somebody names them, people see them called by their children instead
+pub const PLACEHOLDER_NAME: &str = "?";
+
+#[derive(Debug, PartialEq)]
 pub enum ButtonCommand {
     No,
+    Yes,
+    Back,
+    Skip,
+    Stop,
+    /// The parent exists but nobody knows their name
+    Unknown,
+    SwitchTree(u64),
+    /// Removes the link a warning is about: tree, revision the warning was made at, parent and child
+    Unlink(u64, u64, u32, u32),
+    Ignore,
+    Language(Lang),
+    Theme(Theme),
+    /// Keeps the trees of the chat for longer or shorter while nobody uses them
+    Retention(Retention),
+    Recover(Recovery),
+    /// Takes back the answer listing several people: tree and revision after it
+    EditNames(u64, u64),
+    /// Applies the statement the user has typed in the tree
+    ApplyStatement(u64),
+    /// Chooses a placeholder to name: tree and person
+    Edit(u64, u32),
+    /// Keeps the tree about to expire
+    Keep(u64),
+    /// Sends the tables of the tree about to expire
+    ExportNow(u64),
 }
 
-pub struct Person {
+impl Display for ButtonCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ButtonCommand::No => write!(f, "No"),
+            ButtonCommand::Yes => write!(f, "Yes"),
+            ButtonCommand::Back => write!(f, "Back"),
+            ButtonCommand::Skip => write!(f, "Skip"),
+            ButtonCommand::Stop => write!(f, "Stop"),
+            ButtonCommand::Unknown => write!(f, "Unknown"),
+            ButtonCommand::SwitchTree(id) => write!(f, "SwitchTree{}{}", BUTTON_PAYLOAD_SEPARATOR, id),
+            ButtonCommand::Unlink(tree, revision, parent, child) => write!(f, "Unlink{0}{1}{0}{2}{0}{3}{0}{4}", BUTTON_PAYLOAD_SEPARATOR, tree, revision, parent, child),
+            ButtonCommand::Ignore => write!(f, "Ignore"),
+            ButtonCommand::Language(lang) => write!(f, "Language{}{}", BUTTON_PAYLOAD_SEPARATOR, lang.code()),
+            ButtonCommand::Theme(theme) => write!(f, "Theme{}{}", BUTTON_PAYLOAD_SEPARATOR, theme.name()),
+            ButtonCommand::Retention(retention) => write!(f, "Retention{}{}", BUTTON_PAYLOAD_SEPARATOR, retention.code()),
+            ButtonCommand::Recover(recovery) => write!(f, "Recover{}{}", BUTTON_PAYLOAD_SEPARATOR, recovery.code()),
+            ButtonCommand::EditNames(tree, revision) => write!(f, "EditNames{0}{1}{0}{2}", BUTTON_PAYLOAD_SEPARATOR, tree, revision),
+            ButtonCommand::ApplyStatement(tree) => write!(f, "ApplyStatement{}{}", BUTTON_PAYLOAD_SEPARATOR, tree),
+            ButtonCommand::Edit(tree, person) => write!(f, "Edit{0}{1}{0}{2}", BUTTON_PAYLOAD_SEPARATOR, tree, person),
+            ButtonCommand::Keep(tree) => write!(f, "Keep{}{}", BUTTON_PAYLOAD_SEPARATOR, tree),
+            ButtonCommand::ExportNow(tree) => write!(f, "ExportNow{}{}", BUTTON_PAYLOAD_SEPARATOR, tree),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseButtonCommandError;
+
+impl FromStr for ButtonCommand {
+    type Err = ParseButtonCommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, payload) = match s.split_once(BUTTON_PAYLOAD_SEPARATOR) {
+            Some((name, payload)) => (name, Some(payload)),
+            None => (s, None)
+        };
+        match (name, payload) {
+            ("No", None) => Ok(ButtonCommand::No),
+            ("Yes", None) => Ok(ButtonCommand::Yes),
+            ("Back", None) => Ok(ButtonCommand::Back),
+            ("Skip", None) => Ok(ButtonCommand::Skip),
+            ("Stop", None) => Ok(ButtonCommand::Stop),
+            ("Unknown", None) => Ok(ButtonCommand::Unknown),
+            ("SwitchTree", Some(id)) => id.parse().map(ButtonCommand::SwitchTree).map_err(|_| ParseButtonCommandError),
+            ("Unlink", Some(link)) => {
+                let ids: Vec<&str> = link.split(BUTTON_PAYLOAD_SEPARATOR).collect();
+                match ids[..] {
+                    [tree, revision, parent, child] => match (tree.parse(), revision.parse(), parent.parse(), child.parse()) {
+                        (Ok(tree), Ok(revision), Ok(parent), Ok(child)) => Ok(ButtonCommand::Unlink(tree, revision, parent, child)),
+                        _ => Err(ParseButtonCommandError)
+                    },
+                    _ => Err(ParseButtonCommandError)
+                }
+            }
+            ("Ignore", None) => Ok(ButtonCommand::Ignore),
+            ("Language", Some(code)) => Lang::from_code(code).map(ButtonCommand::Language).ok_or(ParseButtonCommandError),
+            ("Theme", Some(name)) => Theme::parse(name).map(ButtonCommand::Theme).ok_or(ParseButtonCommandError),
+            ("Retention", Some(code)) => Retention::parse(code).map(ButtonCommand::Retention).ok_or(ParseButtonCommandError),
+            ("Recover", Some(code)) => Recovery::from_code(code).map(ButtonCommand::Recover).ok_or(ParseButtonCommandError),
+            ("Edit", Some(person)) => match person.split_once(BUTTON_PAYLOAD_SEPARATOR) {
+                Some((tree, person)) => match (tree.parse(), person.parse()) {
+                    (Ok(tree), Ok(person)) => Ok(ButtonCommand::Edit(tree, person)),
+                    _ => Err(ParseButtonCommandError)
+                },
+                None => Err(ParseButtonCommandError)
+            },
+            ("ApplyStatement", Some(tree)) => tree.parse().map(ButtonCommand::ApplyStatement).map_err(|_| ParseButtonCommandError),
+            ("Keep", Some(tree)) => tree.parse().map(ButtonCommand::Keep).map_err(|_| ParseButtonCommandError),
+            ("ExportNow", Some(tree)) => tree.parse().map(ButtonCommand::ExportNow).map_err(|_| ParseButtonCommandError),
+            ("EditNames", Some(answer)) => match answer.split_once(BUTTON_PAYLOAD_SEPARATOR) {
+                Some((tree, revision)) => match (tree.parse(), revision.parse()) {
+                    (Ok(tree), Ok(revision)) => Ok(ButtonCommand::EditNames(tree, revision)),
+                    _ => Err(ParseButtonCommandError)
+                },
+                None => Err(ParseButtonCommandError)
+            },
+            _ => Err(ParseButtonCommandError)
+        }
+    }
+}
+
+/// Telegram user who gave the answer
+#[derive(Clone, Debug, PartialEq)]
+pub struct Author {
+    pub user_id: i64,
     pub name: String,
+}
+
+impl Author {
+    pub fn new(user_id: i64, name: String) -> Self { Self { user_id, name } }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sex {
+    Female,
+    Male,
+}
+
+impl Sex {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Sex::Female => "F",
+            Sex::Male => "M",
+        }
+    }
+
+    /// Reads the letter or the word in any of the supported languages
+    pub fn parse(text: &str) -> Option<Sex> {
+        match text.trim().to_lowercase().as_str() {
+            "f" | "female" | "w" | "weiblich" | "ж" | "женский" => Some(Sex::Female),
+            "m" | "male" | "männlich" | "м" | "мужской" => Some(Sex::Male),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Person {
+    pub name: PersonName,
+    pub lifespan: Lifespan,
     pub completeness: NodeCompleteness,
+    pub author: Option<Author>,
+    /// Only known when the tree was imported from a spreadsheet
+    pub sex: Option<Sex>,
+    pub notes: Option<String>,
+    /// Telegram file id of the photo
+    pub photo: Option<String>,
+    /// Relative who surely exists but whose name is unknown, e.g. the parent linking a grandparent
+    pub placeholder: bool,
 }
 
 impl Display for Person {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.name)
+        write!(f, "{}", self.name)?;
+        if !self.lifespan.is_empty() {
+            write!(f, ", {}", self.lifespan)?;
+        }
+        Ok(())
     }
 }
 
 impl Person {
-    pub fn new(name: String, completeness: NodeCompleteness) -> Self { Self { name, completeness } }
+    pub fn new(name: PersonName, lifespan: Lifespan, completeness: NodeCompleteness) -> Self { Self { name, lifespan, completeness, author: None, sex: None, notes: None, photo: None, placeholder: false } }
+
+    /// Reads the name and the years of life from the answer
+    pub fn parse(text: &str, completeness: NodeCompleteness) -> Self {
+        let (name, lifespan) = Lifespan::extract(text);
+        Self::new(PersonName::parse(&name), lifespan, completeness)
+    }
+
+    /// Stands in for a relative nobody has named, linking people whose relation is known.
+    /// The interview only asks about their parents.
+    pub fn placeholder(completeness: NodeCompleteness) -> Self {
+        let mut person = Self::new(PersonName::new(PLACEHOLDER_NAME.to_string()), Lifespan::default(), completeness);
+        person.placeholder = true;
+        person
+    }
 }
 
+/// Couple told about with a statement, they may have no children in the tree
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marriage {
+    pub spouses: [NodeIndex<u32>; 2],
+    /// Year of the wedding
+    pub year: Option<i32>,
+}
+
+#[derive(Clone)]
 pub struct DescribedNodeInfo {
     pub ix: Option<NodeIndex<u32>>,
 }
@@ -31,7 +217,7 @@ impl DescribedNodeInfo {
     pub fn new(ix: Option::<NodeIndex<u32>>) -> Self { Self { ix } }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum NodeCompleteness {
     Plain,
     OneParent,
@@ -42,20 +228,122 @@ pub enum NodeCompleteness {
 
 pub const NEW_NODE_STATUS: NodeCompleteness = NodeCompleteness::Plain;
 
+/// How people are referred to in questions and charts
+#[derive(Clone, Debug, PartialEq)]
+pub enum Called {
+    Name(PersonName),
+    /// Placeholder, who has no name: "Unknown father of Robert", with the sex if known and their children
+    Unknown(Option<Sex>, Vec<Called>),
+}
+
+impl From<PersonName> for Called {
+    fn from(name: PersonName) -> Self { Called::Name(name) }
+}
+
+/// Person the question is about along with the relatives which tell them apart from namesakes
+#[derive(Debug, PartialEq)]
+pub struct Subject {
+    pub name: Called,
+    pub parents: Vec<Called>,
+    pub children: Vec<Called>,
+}
+
+impl Subject {
+    pub fn new(name: impl Into<Called>, parents: Vec<Called>, children: Vec<Called>) -> Self { Self { name: name.into(), parents, children } }
+
+    pub fn named(name: impl Into<Called>) -> Self { Self::new(name, vec![], vec![]) }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum OutputAction {
-    AskFirstParent(String),
-    AskSecondParent(String),
-    AskIfSiblings(String),
-    AskIfMoreSiblings(String),
-    AskIfChildren(String),
-    AskIfMoreChildren(String),
-    NotifyError,
+    AskRoot,
+    AskFirstParent(Subject),
+    AskSecondParent(Subject),
+    AskIfSiblings(Subject),
+    AskIfMoreSiblings(Subject),
+    AskIfChildren(Subject),
+    AskIfMoreChildren(Subject),
+    ConfirmSurname(PersonName, String),
     NotifyComplete
 }
 
+/// Why the interview can't go on with the answer
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    /// Everybody is described, the answer belongs to no question
+    TreeFinished,
+    /// The answer doesn't fit the question, e.g. an empty name
+    InvalidInput,
+    /// The user was asked about a person who isn't in the tree anymore
+    UnknownPerson(NodeIndex<u32>),
+    /// The interview got into a state it has no question for
+    Inconsistent(String),
+    /// The user hasn't answered yet or somebody has changed the tree since
+    NothingToUndo,
+    /// The button was made for the tree before its latest change
+    Outdated,
+}
+
+impl StateError {
+    /// Ways out offered to the user as buttons
+    pub fn recoveries(&self) -> Vec<Recovery> {
+        match self {
+            StateError::TreeFinished => vec![Recovery::Undo],
+            StateError::InvalidInput | StateError::NothingToUndo | StateError::Outdated => vec![Recovery::Continue],
+            StateError::UnknownPerson(_) => vec![Recovery::PickAnother],
+            StateError::Inconsistent(_) => vec![Recovery::PickAnother, Recovery::Undo],
+        }
+    }
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::TreeFinished => write!(f, "the tree is finished"),
+            StateError::InvalidInput => write!(f, "the answer doesn't fit the question"),
+            StateError::UnknownPerson(ix) => write!(f, "person {} isn't in the tree", ix.index()),
+            StateError::Inconsistent(detail) => write!(f, "inconsistent interview: {}", detail),
+            StateError::NothingToUndo => write!(f, "nothing to undo"),
+            StateError::Outdated => write!(f, "the tree has changed since"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Recovery {
+    /// Repeat the current question
+    Continue,
+    /// Take back the last answer
+    Undo,
+    /// Ask about somebody else
+    PickAnother,
+}
+
+impl Recovery {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Recovery::Continue => "continue",
+            Recovery::Undo => "undo",
+            Recovery::PickAnother => "another",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        [Recovery::Continue, Recovery::Undo, Recovery::PickAnother].into_iter().find(|recovery| recovery.code() == code)
+    }
+}
+
 #[derive(Debug)]
 pub enum InputAction<'a> {
     Text(&'a str),
-    No
+    No,
+    Yes,
+    /// Re-ask the previous question
+    Back,
+    /// Ask about this person after everybody else
+    Skip,
+    /// Don't ask about this person and their ancestors anymore
+    Stop,
+    /// Add a placeholder for the parent
+    Unknown,
 }
\ No newline at end of file
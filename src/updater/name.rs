@@ -0,0 +1,189 @@
+use std::fmt::{self, Display};
+
+use super::lifespan::Lifespan;
+
+/// Words introducing the birth surname: "Anna Smith née Brown", "Anna Smith (born Brown)"
+const BIRTH_SURNAME_MARKERS: [&str; 7] = ["née", "nee", "born", "geb.", "geb", "урожд.", "урожд"];
+/// Lowercase particles which belong to the surname: "Ludwig van Beethoven"
+const SURNAME_PARTICLES: [&str; 16] = ["van", "von", "der", "den", "de", "da", "di", "du", "la", "le", "del", "dos", "das", "ter", "ten", "zu"];
+const NICKNAME_QUOTES: [(char, char); 3] = [('"', '"'), ('“', '”'), ('«', '»')];
+/// Words joining the last name of a list: "Anna, Bruce and Clara"
+const LIST_CONJUNCTIONS: [&str; 4] = ["and", "und", "и", "&"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PersonName {
+    pub given: String,
+    pub surname: Option<String>,
+    pub birth_surname: Option<String>,
+    pub nicknames: Vec<String>,
+}
+
+impl PersonName {
+    pub fn new(given: String) -> Self { Self { given, surname: None, birth_surname: None, nicknames: vec![] } }
+
+    /// Parses free text like `Robert "Bob" Smith` or `Anna Smith (née Brown)`.
+    /// The last word is the surname unless the text is a single word.
+    pub fn parse(text: &str) -> Self {
+        let (rest, nicknames) = extract_nicknames(text);
+        let rest = rest.replace(&['(', ')'][..], " ");
+        let words: Vec<&str> = rest.split_whitespace().collect();
+
+        let marker = words.iter().position(|word| BIRTH_SURNAME_MARKERS.contains(&word.to_lowercase().as_str()));
+        let (words, birth_surname) = match marker {
+            Some(marker) => (&words[..marker], Some(words[marker + 1..].join(" ")).filter(|surname| !surname.is_empty())),
+            None => (&words[..], None)
+        };
+
+        let (given, surname) = match words.len() {
+            0 => (String::new(), None),
+            1 => (words[0].to_string(), None),
+            _ => {
+                let mut start = words.len() - 1;
+                while start > 1 && SURNAME_PARTICLES.contains(&words[start - 1]) {
+                    start -= 1;
+                }
+                (words[..start].join(" "), Some(words[start..].join(" ")))
+            }
+        };
+
+        match (given.is_empty(), nicknames.is_empty()) {
+            (true, false) => Self { given: nicknames.join(" "), surname, birth_surname, nicknames: vec![] },
+            (true, true) => Self::new(text.trim().to_string()),
+            _ => Self { given, surname, birth_surname, nicknames }
+        }
+    }
+}
+
+/// Part of the previous person's description rather than another person,
+/// e.g. the years in "Anna Smith, 1950–2010" the bot writes itself or the birth surname in "Anna, née Brown"
+fn continues_name(fragment: &str) -> bool {
+    let (rest, lifespan) = Lifespan::extract(fragment);
+    let (rest, nicknames) = extract_nicknames(&rest);
+    let rest = rest.replace(&['(', ')'][..], " ");
+    match rest.split_whitespace().next() {
+        Some(word) => BIRTH_SURNAME_MARKERS.contains(&word.to_lowercase().as_str()),
+        None => !lifespan.is_empty() || !nicknames.is_empty()
+    }
+}
+
+/// Splits an answer listing several people, e.g. "Anna, Bruce and Clara" or one name per line
+pub fn split_names(text: &str) -> Vec<String> {
+    let fragments = text.split(&['\n', ',', ';'][..])
+        .flat_map(|part| {
+            let mut names = vec![];
+            let mut name: Vec<&str> = vec![];
+            for word in part.split_whitespace() {
+                if LIST_CONJUNCTIONS.contains(&word.to_lowercase().as_str()) {
+                    names.push(name.join(" "));
+                    name.clear();
+                } else {
+                    name.push(word);
+                }
+            }
+            names.push(name.join(" "));
+            names
+        })
+        .filter(|name| !name.is_empty());
+    let mut names: Vec<String> = vec![];
+    for fragment in fragments {
+        match names.last_mut() {
+            Some(name) if continues_name(&fragment) => {
+                name.push(' ');
+                name.push_str(&fragment);
+            }
+            _ => names.push(fragment)
+        }
+    }
+    names
+}
+
+fn extract_nicknames(text: &str) -> (String, Vec<String>) {
+    let mut rest = String::new();
+    let mut nicknames = vec![];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match NICKNAME_QUOTES.iter().find(|(open, _)| *open == c) {
+            Some((_, close)) => {
+                let nickname: String = chars.by_ref().take_while(|c| c != close).collect();
+                let nickname = nickname.trim();
+                if !nickname.is_empty() {
+                    nicknames.push(nickname.to_string());
+                }
+                rest.push(' ');
+            }
+            None => rest.push(c)
+        }
+    }
+    (rest, nicknames)
+}
+
+impl Display for PersonName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.given)?;
+        for nickname in &self.nicknames {
+            write!(f, " \"{}\"", nickname)?;
+        }
+        if let Some(surname) = &self.surname {
+            write!(f, " {}", surname)?;
+        }
+        if let Some(birth_surname) = &self.birth_surname {
+            write!(f, " (née {})", birth_surname)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_word_is_given_name() {
+        assert_eq!(PersonName::parse(" Robert "), PersonName::new("Robert".to_string()));
+    }
+
+    #[test]
+    fn last_word_is_surname() {
+        let name = PersonName::parse("Anna Maria Smith");
+        assert_eq!(name.given, "Anna Maria");
+        assert_eq!(name.surname, Some("Smith".to_string()));
+        assert_eq!(PersonName::parse("Ludwig van Beethoven").surname, Some("van Beethoven".to_string()), "Should keep particles in surname");
+    }
+
+    #[test]
+    fn birth_surname_and_nicknames() {
+        let name = PersonName::parse("Anna Smith (née Brown)");
+        assert_eq!(name.surname, Some("Smith".to_string()));
+        assert_eq!(name.birth_surname, Some("Brown".to_string()));
+        assert_eq!(name.to_string(), "Anna Smith (née Brown)");
+
+        let name = PersonName::parse("Robert “Bob” Smith born Jones");
+        assert_eq!(name.nicknames, vec!["Bob".to_string()]);
+        assert_eq!(name.birth_surname, Some("Jones".to_string()));
+        assert_eq!(name.to_string(), "Robert \"Bob\" Smith (née Jones)");
+    }
+
+    #[test]
+    fn lists_of_names() {
+        assert_eq!(split_names("Anna, Bruce and Clara Smith"), vec!["Anna", "Bruce", "Clara Smith"]);
+        assert_eq!(split_names("Anna 1950-2010\nBruce\n\n"), vec!["Anna 1950-2010", "Bruce"]);
+        assert_eq!(split_names("Анна и Борис"), vec!["Анна", "Борис"]);
+        assert_eq!(split_names("Anna Smith (née Brown)"), vec!["Anna Smith (née Brown)"]);
+        assert!(split_names(" , ").is_empty());
+    }
+
+    #[test]
+    fn years_and_birth_surnames_stay_with_the_name() {
+        assert_eq!(split_names("Anna Smith, 1950"), vec!["Anna Smith 1950"]);
+        assert_eq!(split_names("Anna, b. 1950 and Bruce"), vec!["Anna b. 1950", "Bruce"]);
+        assert_eq!(split_names("Anna Smith, 1950–2010, Bruce"), vec!["Anna Smith 1950–2010", "Bruce"]);
+        assert_eq!(split_names("Anna Smith, née Brown, \"Granny\""), vec!["Anna Smith née Brown \"Granny\""]);
+        assert_eq!(split_names("1950, Anna"), vec!["1950", "Anna"], "Should keep years nobody is named before");
+    }
+
+    #[test]
+    fn nickname_only() {
+        assert_eq!(PersonName::parse("\"Granny\"").to_string(), "Granny");
+    }
+}
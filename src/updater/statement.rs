@@ -0,0 +1,198 @@
+use super::model::Sex;
+use super::name::PersonName;
+
+/// Words to leave out before a role: "the mother of", "a brother of"
+const ARTICLES: [&str; 3] = ["the", "a", "an"];
+/// Words between the couple and "married": "got married", "are married"
+const MARRIAGE_VERBS: [&str; 4] = ["got", "are", "were", "have"];
+/// Words before the year of the wedding: "in 1970", "since 1970"
+const YEAR_MARKERS: [&str; 2] = ["in", "since"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kinship {
+    Parent,
+    Child,
+    Grandparent,
+    Grandchild,
+    Sibling,
+    Spouse,
+}
+
+const ROLES: [(&str, Kinship, Option<Sex>); 19] = [
+    ("mother", Kinship::Parent, Some(Sex::Female)),
+    ("father", Kinship::Parent, Some(Sex::Male)),
+    ("parent", Kinship::Parent, None),
+    ("daughter", Kinship::Child, Some(Sex::Female)),
+    ("son", Kinship::Child, Some(Sex::Male)),
+    ("child", Kinship::Child, None),
+    ("grandmother", Kinship::Grandparent, Some(Sex::Female)),
+    ("grandfather", Kinship::Grandparent, Some(Sex::Male)),
+    ("grandparent", Kinship::Grandparent, None),
+    ("granddaughter", Kinship::Grandchild, Some(Sex::Female)),
+    ("grandson", Kinship::Grandchild, Some(Sex::Male)),
+    ("grandchild", Kinship::Grandchild, None),
+    ("sister", Kinship::Sibling, Some(Sex::Female)),
+    ("brother", Kinship::Sibling, Some(Sex::Male)),
+    ("sibling", Kinship::Sibling, None),
+    ("wife", Kinship::Spouse, Some(Sex::Female)),
+    ("husband", Kinship::Spouse, Some(Sex::Male)),
+    ("spouse", Kinship::Spouse, None),
+    ("partner", Kinship::Spouse, None),
+];
+
+/// Relation typed as a sentence: `person` is the `kinship` of `relative`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statement {
+    pub person: String,
+    pub kinship: Kinship,
+    pub relative: String,
+    /// Follows from the role, e.g. "grandmother"
+    pub sex: Option<Sex>,
+    /// Year of the wedding
+    pub year: Option<i32>,
+}
+
+impl Statement {
+    fn new(person: &[&str], kinship: Kinship, relative: &[&str], sex: Option<Sex>, year: Option<i32>) -> Option<Self> {
+        let (person, relative) = (person.join(" "), relative.join(" "));
+        if person.is_empty() || relative.is_empty() {
+            return None;
+        }
+        Some(Self { person, kinship, relative, sex, year })
+    }
+}
+
+/// What the statement changes in the tree, shown to the user before it's applied
+#[derive(Debug, PartialEq)]
+pub struct Proposal {
+    pub person: PersonName,
+    pub kinship: Kinship,
+    pub relative: PersonName,
+    pub sex: Option<Sex>,
+    pub year: Option<i32>,
+    /// People who aren't in the tree yet
+    pub new_people: Vec<String>,
+    /// The two are linked through a parent whose name is unknown
+    pub through_unknown: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StatementError {
+    /// Several people in the tree have the name, with their full names
+    Ambiguous(Vec<String>),
+    SamePerson,
+    AlreadyKnown,
+    /// Both parents of the grandchild are known, so it's unclear which of them links the grandparent
+    ParentUnclear(String),
+    /// The confirmed statement is gone, e.g. undone or replaced by a newer one
+    NothingPending,
+}
+
+fn role(word: &str) -> Option<(Kinship, Option<Sex>)> {
+    let word = word.to_lowercase();
+    ROLES.iter().find(|(name, _, _)| *name == word).map(|(_, kinship, sex)| (*kinship, *sex))
+}
+
+fn is_word(word: &str, words: &[&str]) -> bool {
+    words.contains(&word.to_lowercase().as_str())
+}
+
+/// Cuts "in 1970" from the end of the sentence
+fn take_year<'a, 'b>(words: &'a [&'b str]) -> (&'a [&'b str], Option<i32>) {
+    match words {
+        [rest @ .., marker, year] if is_word(marker, &YEAR_MARKERS) => match year.parse() {
+            Ok(year) => (rest, Some(year)),
+            Err(_) => (words, None)
+        },
+        _ => (words, None)
+    }
+}
+
+/// Name with the possessive ending: "Robert's", "James'"
+fn possessive(words: &[&str]) -> Option<Vec<String>> {
+    let (last, rest) = words.split_last()?;
+    let owner = ["'s", "’s", "'", "’"].iter().find_map(|ending| last.strip_suffix(ending))?;
+    let mut name: Vec<String> = rest.iter().map(|word| word.to_string()).collect();
+    name.push(owner.to_string());
+    Some(name)
+}
+
+/// "Anna and Bruce married", "Anna married Bruce in 1970", "Anna is married to Bruce"
+fn parse_marriage(words: &[&str]) -> Option<Statement> {
+    let at = words.iter().position(|word| is_word(word, &["married"]))?;
+    let mut left = &words[..at];
+    while let [rest @ .., verb] = left {
+        if !is_word(verb, &MARRIAGE_VERBS) && !is_word(verb, &["is"]) {
+            break;
+        }
+        left = rest;
+    }
+    let right = match &words[at + 1..] {
+        [to, rest @ ..] if is_word(to, &["to"]) => rest,
+        rest => rest
+    };
+    let (right, year) = take_year(right);
+    if !right.is_empty() {
+        return Statement::new(left, Kinship::Spouse, right, None, year);
+    }
+    let and = left.iter().position(|word| is_word(word, &["and", "&"]))?;
+    Statement::new(&left[..and], Kinship::Spouse, &left[and + 1..], None, year)
+}
+
+/// Reads sentences like "Maria is Robert's grandmother", "Maria is the mother of Robert" or "John married Lisa in 1970".
+/// Anything else is left to the interview.
+pub fn parse_statement(text: &str) -> Option<Statement> {
+    let text = text.trim().trim_end_matches(&['.', '!'][..]);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let is = words.iter().position(|word| is_word(word, &["is"]));
+    match is.map(|is| (&words[..is], &words[is + 1..])) {
+        Some((person, [article, named, of, relative @ ..])) if is_word(article, &ARTICLES) && is_word(of, &["of"]) => {
+            let (kinship, sex) = role(named)?;
+            Statement::new(person, kinship, relative, sex, None)
+        }
+        Some((person, [owner @ .., named])) if role(named).is_some() => {
+            let (kinship, sex) = role(named)?;
+            let relative = possessive(owner)?;
+            let relative: Vec<&str> = relative.iter().map(String::as_str).collect();
+            Statement::new(person, kinship, &relative, sex, None)
+        }
+        _ => parse_marriage(&words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statement(person: &str, kinship: Kinship, relative: &str, sex: Option<Sex>, year: Option<i32>) -> Option<Statement> {
+        Some(Statement { person: person.to_string(), kinship, relative: relative.to_string(), sex, year })
+    }
+
+    #[test]
+    fn possessive_roles() {
+        assert_eq!(parse_statement("Maria is Robert's grandmother."), statement("Maria", Kinship::Grandparent, "Robert", Some(Sex::Female), None));
+        assert_eq!(parse_statement("Anna Smith is James’ daughter"), statement("Anna Smith", Kinship::Child, "James", Some(Sex::Female), None));
+        assert_eq!(parse_statement("Bruce is Anna Smith's brother"), statement("Bruce", Kinship::Sibling, "Anna Smith", Some(Sex::Male), None));
+    }
+
+    #[test]
+    fn roles_with_of() {
+        assert_eq!(parse_statement("Clara is the mother of Robert Smith"), statement("Clara", Kinship::Parent, "Robert Smith", Some(Sex::Female), None));
+        assert_eq!(parse_statement("Tom is a grandson of Clara"), statement("Tom", Kinship::Grandchild, "Clara", Some(Sex::Male), None));
+    }
+
+    #[test]
+    fn marriages() {
+        assert_eq!(parse_statement("John married Lisa in 1970"), statement("John", Kinship::Spouse, "Lisa", None, Some(1970)));
+        assert_eq!(parse_statement("John and Lisa got married in 1970"), statement("John", Kinship::Spouse, "Lisa", None, Some(1970)));
+        assert_eq!(parse_statement("John is married to Lisa Brown"), statement("John", Kinship::Spouse, "Lisa Brown", None, None));
+        assert_eq!(parse_statement("Lisa is John's wife"), statement("Lisa", Kinship::Spouse, "John", Some(Sex::Female), None));
+    }
+
+    #[test]
+    fn names_are_not_statements() {
+        for text in ["Robert Smith", "Anna and Bruce", "Maria is Robert", "Robert's mother", "married", "Ann is the mother of"] {
+            assert_eq!(parse_statement(text), None, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::{Directed, Direction, Graph};
+
+use super::lifespan::Lifespan;
+use super::model::{Marriage, NodeCompleteness, Person, Sex, PLACEHOLDER_NAME};
+use super::name::PersonName;
+
+/// Spreadsheets open UTF-8 files with Cyrillic names correctly only when they start with the byte order mark
+const BYTE_ORDER_MARK: char = '\u{FEFF}';
+/// Spreadsheets save CSV with the separator of the system locale, e.g. ";" in Germany and Russia
+const SEPARATORS: [char; 3] = [',', ';', '\t'];
+const NICKNAME_SEPARATOR: char = ',';
+
+const PEOPLE_COLUMNS: [&str; 11] = ["id", "given", "nicknames", "surname", "birth_surname", "birth", "death", "sex", "notes", "photo", "placeholder"];
+/// Value of the `placeholder` column for relatives nobody has named, their `given` is left empty
+const PLACEHOLDER_MARK: &str = "yes";
+const RELATIONSHIP_COLUMNS: [&str; 4] = ["from", "to", "type", "year"];
+const MEMBER_COLUMNS: [&str; 4] = ["chat", "access", "tree_name", "retention"];
+/// File with the members of a tree saved on shutdown
+pub const MEMBERS_FILE_NAME: &str = "members.csv";
+/// `from` is a parent of `to`
+const PARENT_RELATION: &str = "parent";
+/// `from` is a child of `to`
+const CHILD_RELATION: &str = "child";
+/// `from` married `to`, the `year` of the wedding is optional
+const SPOUSE_RELATION: &str = "spouse";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Table {
+    People,
+    Relationships,
+}
+
+impl Table {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Table::People => "people.csv",
+            Table::Relationships => "relationships.csv",
+        }
+    }
+
+    /// Tells the tables apart by their header, so renamed files are still recognised
+    pub fn detect(text: &str) -> Option<Table> {
+        let rows = parse_csv(text).ok()?;
+        let (_, header) = rows.first()?;
+        let has = |column: &str| header.iter().any(|name| name.trim().eq_ignore_ascii_case(column));
+        if has("from") && has("to") {
+            Some(Table::Relationships)
+        } else if has("id") && has("given") {
+            Some(Table::People)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RowProblem {
+    UnclosedQuote,
+    MissingColumn(&'static str),
+    MissingId,
+    DuplicateId(String),
+    MissingName,
+    BadYear(String),
+    BadSex(String),
+    BadPlaceholder(String),
+    UnknownPerson(String),
+    UnknownRelation(String),
+    SelfLink,
+}
+
+/// Problem in a row of the table, rows are numbered like in a spreadsheet with the header being the 1st one
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowError {
+    pub table: Table,
+    pub row: usize,
+    pub problem: RowProblem,
+}
+
+impl RowError {
+    fn new(table: Table, row: usize, problem: RowProblem) -> Self { Self { table, row, problem } }
+}
+
+fn detect_separator(text: &str) -> char {
+    let header = text.lines().next().unwrap_or_default();
+    SEPARATORS.iter().copied().max_by_key(|separator| header.matches(*separator).count()).unwrap_or(',')
+}
+
+/// Splits the text into rows of fields along with the number of the line each row starts on.
+/// Quoted fields may contain separators, line breaks and doubled quotes. Blank lines are skipped.
+fn parse_csv(text: &str) -> Result<Vec<(usize, Vec<String>)>, usize> {
+    let text = text.trim_start_matches(BYTE_ORDER_MARK);
+    let separator = detect_separator(text);
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut row_line = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            '\n' if quoted => {
+                line += 1;
+                field.push(c);
+            }
+            '\r' if !quoted => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|field| !field.trim().is_empty()) {
+                    rows.push((row_line, std::mem::take(&mut row)));
+                }
+                row.clear();
+                line += 1;
+                row_line = line;
+            }
+            c if c == separator && !quoted => row.push(std::mem::take(&mut field)),
+            c => field.push(c)
+        }
+    }
+    if quoted {
+        return Err(row_line);
+    }
+    row.push(field);
+    if row.iter().any(|field| !field.trim().is_empty()) {
+        rows.push((row_line, row));
+    }
+    Ok(rows)
+}
+
+fn write_row(text: &mut String, fields: &[String]) {
+    let fields: Vec<String> = fields.iter()
+        .map(|field| {
+            if field.contains(|c| SEPARATORS.contains(&c) || c == '"' || c == '\n' || c == '\r') {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    text.push_str(&fields.join(","));
+    text.push_str("\r\n");
+}
+
+fn write_table(columns: &[&str], rows: impl Iterator<Item = Vec<String>>) -> String {
+    let mut text = String::from(BYTE_ORDER_MARK);
+    write_row(&mut text, &columns.iter().map(|column| column.to_string()).collect::<Vec<String>>());
+    for row in rows {
+        write_row(&mut text, &row);
+    }
+    text
+}
+
+fn optional(value: &Option<impl ToString>) -> String {
+    value.as_ref().map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// People in the order of the graph, so the root stays the first row. Ids are node indices.
+pub fn write_people(graph: &Graph<Person, &str, Directed, u32>) -> String {
+    write_table(&PEOPLE_COLUMNS, graph.node_indices().map(|ix| {
+        let person = &graph[ix];
+        vec![
+            ix.index().to_string(),
+            if person.placeholder { String::new() } else { person.name.given.clone() },
+            person.name.nicknames.join(&format!("{} ", NICKNAME_SEPARATOR)),
+            optional(&person.name.surname),
+            optional(&person.name.birth_surname),
+            optional(&person.lifespan.birth),
+            optional(&person.lifespan.death),
+            person.sex.map(|sex| sex.code().to_string()).unwrap_or_default(),
+            optional(&person.notes),
+            optional(&person.photo),
+            if person.placeholder { PLACEHOLDER_MARK.to_string() } else { String::new() },
+        ]
+    }))
+}
+
+/// Parents and children first, then the marriages
+pub fn write_relationships(graph: &Graph<Person, &str, Directed, u32>, marriages: &[Marriage]) -> String {
+    let parents = graph.edge_references().map(|edge| {
+        vec![edge.source().index().to_string(), edge.target().index().to_string(), PARENT_RELATION.to_string(), String::new()]
+    });
+    let spouses = marriages.iter().map(|marriage| {
+        let [first, second] = marriage.spouses;
+        vec![first.index().to_string(), second.index().to_string(), SPOUSE_RELATION.to_string(), optional(&marriage.year)]
+    });
+    write_table(&RELATIONSHIP_COLUMNS, parents.chain(spouses))
+}
+
+/// Chats of the tree with their access and the name each of them gave the tree
+pub fn write_members<'a>(members: impl Iterator<Item = MemberRow<&'a str>>) -> String {
+    write_table(&MEMBER_COLUMNS, members.map(|(chat, access, name, retention)| vec![chat.to_string(), access.to_string(), name.to_string(), retention.to_string()]))
+}
+
+/// Chat, access code, the name the chat gave the tree and the retention code of the chat
+pub type MemberRow<S> = (i64, S, S, S);
+
+/// Members written by `write_members`, None if the file is damaged.
+/// Files saved before retention was written have it empty.
+pub fn read_members(text: &str) -> Option<Vec<MemberRow<String>>> {
+    let mut rows = parse_csv(text).ok()?.into_iter();
+    let (_, header) = rows.next()?;
+    let column = |name: &str| header.iter().position(|column| column.trim() == name);
+    let (chat, access, name) = (column(MEMBER_COLUMNS[0])?, column(MEMBER_COLUMNS[1])?, column(MEMBER_COLUMNS[2])?);
+    let retention = column(MEMBER_COLUMNS[3]);
+    rows
+        .map(|(_, fields)| {
+            let field = |i: Option<usize>| i.and_then(|i| fields.get(i)).map(|field| field.trim().to_string()).unwrap_or_default();
+            Some((field(Some(chat)).parse().ok()?, field(Some(access)), field(Some(name)), field(retention)))
+        })
+        .collect()
+}
+
+/// Row number along with the trimmed values by column
+type Row = (usize, HashMap<String, String>);
+
+/// Rows of the table, missing trailing fields are empty
+fn read_rows(table: Table, text: &str, required: &[&'static str]) -> Result<Vec<Row>, Vec<RowError>> {
+    let mut rows = parse_csv(text).map_err(|line| vec![RowError::new(table, line, RowProblem::UnclosedQuote)])?.into_iter();
+    let (header_line, header) = rows.next().unwrap_or((1, vec![]));
+    let header: Vec<String> = header.iter().map(|column| column.trim().to_lowercase()).collect();
+    let missing: Vec<RowError> = required.iter()
+        .filter(|column| !header.contains(&column.to_string()))
+        .map(|column| RowError::new(table, header_line, RowProblem::MissingColumn(column)))
+        .collect();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+    Ok(rows
+        .map(|(line, fields)| {
+            let values = header.iter().cloned()
+                .zip(fields.into_iter().map(|field| field.trim().to_string()).chain(std::iter::repeat(String::new())))
+                .collect();
+            (line, values)
+        })
+        .collect())
+}
+
+fn read_person(values: &HashMap<String, String>) -> Result<Person, RowProblem> {
+    let value = |column: &str| values.get(column).filter(|value| !value.is_empty()).cloned();
+    let year = |column: &str| match value(column) {
+        Some(text) => text.parse::<i32>().map(Some).map_err(|_| RowProblem::BadYear(text)),
+        None => Ok(None)
+    };
+    let placeholder = match value("placeholder").map(|text| (text.to_lowercase(), text)) {
+        Some((mark, _)) if [PLACEHOLDER_MARK, "true", "1"].contains(&mark.as_str()) => true,
+        Some((mark, _)) if ["no", "false", "0"].contains(&mark.as_str()) => false,
+        Some((_, text)) => return Err(RowProblem::BadPlaceholder(text)),
+        None => false
+    };
+    // a placeholder somebody has named in the spreadsheet is a person like any other
+    let placeholder = placeholder && value("given").is_none();
+    let given = match value("given") {
+        Some(given) => given,
+        None if placeholder => PLACEHOLDER_NAME.to_string(),
+        None => return Err(RowProblem::MissingName)
+    };
+    let name = PersonName {
+        given,
+        surname: value("surname"),
+        birth_surname: value("birth_surname"),
+        nicknames: value("nicknames")
+            .map(|nicknames| nicknames.split(NICKNAME_SEPARATOR).map(str::trim).filter(|nickname| !nickname.is_empty()).map(String::from).collect())
+            .unwrap_or_default(),
+    };
+    let lifespan = Lifespan { birth: year("birth")?, death: year("death")? };
+    let sex = match value("sex") {
+        Some(text) => Some(Sex::parse(&text).ok_or(RowProblem::BadSex(text))?),
+        None => None
+    };
+    let mut person = Person::new(name, lifespan, NodeCompleteness::Plain);
+    person.sex = sex;
+    person.notes = value("notes");
+    person.photo = value("photo");
+    person.placeholder = placeholder;
+    Ok(person)
+}
+
+/// The bot keeps asking about people whose parents are missing, everyone else is considered complete
+fn imported_completeness(parents: usize) -> NodeCompleteness {
+    match parents {
+        0 => NodeCompleteness::Plain,
+        1 => NodeCompleteness::OneParent,
+        _ => NodeCompleteness::ChildrenComplete
+    }
+}
+
+/// People linked to their parents along with the marriages
+pub type Family = (Graph<Person, &'static str, Directed, u32>, Vec<Marriage>);
+
+/// What a row of the relationships table links
+enum Link {
+    Parent(NodeIndex<u32>, NodeIndex<u32>),
+    Spouses(Marriage),
+}
+
+/// Builds the graph and the marriages from both tables, the first person becomes the root.
+/// Nothing is imported if any row is wrong, so the user can fix all of them at once.
+pub fn read_tables(people: &str, relationships: &str) -> Result<Family, Vec<RowError>> {
+    let mut errors = vec![];
+    let mut graph = Graph::new();
+    let mut marriages: Vec<Marriage> = vec![];
+    //people whose rows are wrong keep their ids, so they aren't reported once more in relationships
+    let mut ids: HashMap<String, Option<NodeIndex<u32>>> = HashMap::new();
+
+    for (line, values) in read_rows(Table::People, people, &PEOPLE_COLUMNS[..2])? {
+        let id = values["id"].clone();
+        if id.is_empty() {
+            errors.push(RowError::new(Table::People, line, RowProblem::MissingId));
+            continue;
+        }
+        if ids.contains_key(&id) {
+            errors.push(RowError::new(Table::People, line, RowProblem::DuplicateId(id)));
+            continue;
+        }
+        match read_person(&values) {
+            Ok(person) => {
+                ids.insert(id, Some(graph.add_node(person)));
+            }
+            Err(problem) => {
+                ids.insert(id, None);
+                errors.push(RowError::new(Table::People, line, problem));
+            }
+        }
+    }
+
+    let rows = match read_rows(Table::Relationships, relationships, &RELATIONSHIP_COLUMNS[..2]) {
+        Ok(rows) => rows,
+        Err(mut header_errors) => {
+            errors.append(&mut header_errors);
+            return Err(errors);
+        }
+    };
+    for (line, values) in rows {
+        let person = |column: &str| ids.get(&values[column]).copied().ok_or_else(|| RowProblem::UnknownPerson(values[column].clone()));
+        let relation = values.get("type").map(|relation| relation.to_lowercase()).unwrap_or_default();
+        let year = match values.get("year").filter(|year| !year.is_empty()) {
+            Some(text) => text.parse::<i32>().map(Some).map_err(|_| RowProblem::BadYear(text.clone())),
+            None => Ok(None)
+        };
+        let link = match (person("from"), person("to")) {
+            (Err(problem), _) | (_, Err(problem)) => Err(problem),
+            (Ok(Some(from)), Ok(Some(to))) if from == to => Err(RowProblem::SelfLink),
+            (Ok(Some(from)), Ok(Some(to))) => match relation.as_str() {
+                "" | PARENT_RELATION => Ok(Some(Link::Parent(from, to))),
+                CHILD_RELATION => Ok(Some(Link::Parent(to, from))),
+                SPOUSE_RELATION => year.map(|year| Some(Link::Spouses(Marriage { spouses: [from, to], year }))),
+                _ => Err(RowProblem::UnknownRelation(relation))
+            },
+            _ => Ok(None)
+        };
+        match link {
+            Ok(Some(Link::Parent(parent, child))) => {
+                if graph.find_edge(parent, child).is_none() {
+                    graph.add_edge(parent, child, "");
+                }
+            }
+            Ok(Some(Link::Spouses(marriage))) => {
+                let [first, second] = marriage.spouses;
+                if !marriages.iter().any(|known| known.spouses.contains(&first) && known.spouses.contains(&second)) {
+                    marriages.push(marriage);
+                }
+            }
+            Ok(None) => {}
+            Err(problem) => errors.push(RowError::new(Table::Relationships, line, problem))
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    for ix in graph.node_indices() {
+        graph[ix].completeness = imported_completeness(graph.neighbors_directed(ix, Direction::Incoming).count());
+    }
+    Ok((graph, marriages))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_survive_round_trip() {
+        let mut graph = Graph::new();
+        let child = graph.add_node(Person::parse("Robert \"Bob\" Smith 1980", NodeCompleteness::Plain));
+        let mut mom = Person::parse("Anna Smith (née Brown) 1950-2010", NodeCompleteness::Plain);
+        mom.sex = Some(Sex::Female);
+        mom.notes = Some("Teacher, \"the best\"\nin town".to_string());
+        mom.photo = Some("AgACAgIAAxkBAAIB".to_string());
+        let mom = graph.add_node(mom);
+        graph.add_edge(mom, child, "");
+        let dad = graph.add_node(Person::parse("John Smith", NodeCompleteness::Plain));
+        let marriages = vec![Marriage { spouses: [dad, mom], year: Some(1970) }];
+
+        let people = write_people(&graph);
+        let relationships = write_relationships(&graph, &marriages);
+        assert_eq!(people,
+"\u{FEFF}id,given,nicknames,surname,birth_surname,birth,death,sex,notes,photo,placeholder\r
+0,Robert,Bob,Smith,,1980,,,,,\r
+1,Anna,,Smith,Brown,1950,2010,F,\"Teacher, \"\"the best\"\"
+in town\",AgACAgIAAxkBAAIB,\r
+2,John,,Smith,,,,,,,\r
+");
+        assert_eq!(relationships, "\u{FEFF}from,to,type,year\r\n1,0,parent,\r\n2,1,spouse,1970\r\n");
+        assert_eq!(Table::detect(&people), Some(Table::People));
+        assert_eq!(Table::detect(&relationships), Some(Table::Relationships));
+
+        let (imported, imported_marriages) = read_tables(&people, &relationships).unwrap();
+        assert_eq!(imported_marriages, marriages, "Should keep marriages along with the year");
+        assert_eq!(imported[child].to_string(), "Robert \"Bob\" Smith, b. 1980");
+        assert_eq!(imported[child].completeness, NodeCompleteness::OneParent);
+        assert_eq!(imported[mom].to_string(), "Anna Smith (née Brown), 1950–2010");
+        assert_eq!(imported[mom].notes, Some("Teacher, \"the best\"\nin town".to_string()));
+        assert_eq!(imported[mom].photo, Some("AgACAgIAAxkBAAIB".to_string()));
+        assert!(imported.find_edge(mom, child).is_some());
+        assert!(!imported[mom].placeholder);
+    }
+
+    #[test]
+    fn placeholders_are_read_back() {
+        let mut graph = Graph::new();
+        let child = graph.add_node(Person::parse("Robert", NodeCompleteness::Plain));
+        let parent = graph.add_node(Person::placeholder(NodeCompleteness::Plain));
+        graph.add_edge(parent, child, "");
+        let people = write_people(&graph);
+        assert!(people.ends_with("\r\n1,,,,,,,,,,yes\r\n"), "Should mark the placeholder instead of naming it: {}", people);
+
+        let (imported, _) = read_tables(&people, &write_relationships(&graph, &[])).unwrap();
+        assert!(imported[parent].placeholder);
+        assert!(!imported[child].placeholder);
+        let (named, _) = read_tables("id,given,placeholder\n1,Robert,\n2,?,\n3,Maria,yes\n", "from,to\n2,1\n3,1\n").unwrap();
+        assert!(named.node_weights().all(|person| !person.placeholder), "Should only take placeholders without a name from the column");
+        assert_eq!(errors("id,given,placeholder\n1,Robert,maybe\n", "from,to\n"), vec![RowError::new(Table::People, 2, RowProblem::BadPlaceholder("maybe".to_string()))]);
+    }
+
+    #[test]
+    fn spreadsheet_edits_are_read() {
+        let people = "ID;Given;Surname;Sex\nanna;Anna;Smith;ж\n\nbob;Bob;Smith;m\n";
+        let relationships = "to;from;type;year\nanna;bob;child;\nbob;anna;Spouse;\n";
+        let (graph, marriages) = read_tables(people, relationships).unwrap();
+        assert_eq!(graph.node_count(), 2);
+        assert_eq!(graph[graph.node_indices().next().unwrap()].sex, Some(Sex::Female));
+        let (parent, child) = graph.edge_endpoints(graph.edge_indices().next().unwrap()).unwrap();
+        assert_eq!((graph[parent].name.given.as_str(), graph[child].name.given.as_str()), ("Anna", "Bob"));
+        assert_eq!(marriages.len(), 1, "Should read a marriage typed in either order once");
+    }
+
+    fn errors(people: &str, relationships: &str) -> Vec<RowError> {
+        match read_tables(people, relationships) {
+            Ok(_) => panic!("tables should have errors"),
+            Err(errors) => errors
+        }
+    }
+
+    #[test]
+    fn row_errors_are_reported() {
+        let people = "id,given,birth\n1,Anna,19x0\n1,Bob,\n2,,1950\n3,Carl,\n4,Dora,\n";
+        let relationships = "from,to,type,year\n3,5,parent,\n3,3,parent,\n1,3,parent,\n4,3,cousin,\n3,4,spouse,197x\n";
+        let error = |table, row, problem| RowError::new(table, row, problem);
+        assert_eq!(errors(people, relationships), vec![
+            error(Table::People, 2, RowProblem::BadYear("19x0".to_string())),
+            error(Table::People, 3, RowProblem::DuplicateId("1".to_string())),
+            error(Table::People, 4, RowProblem::MissingName),
+            error(Table::Relationships, 2, RowProblem::UnknownPerson("5".to_string())),
+            error(Table::Relationships, 3, RowProblem::SelfLink),
+            error(Table::Relationships, 5, RowProblem::UnknownRelation("cousin".to_string())),
+            error(Table::Relationships, 6, RowProblem::BadYear("197x".to_string())),
+        ]);
+        assert_eq!(errors("name\nAnna", "from,to"), vec![
+            error(Table::People, 1, RowProblem::MissingColumn("id")),
+            error(Table::People, 1, RowProblem::MissingColumn("given")),
+        ]);
+        assert_eq!(errors("id,given\n1,\"Anna", "from,to"), vec![error(Table::People, 2, RowProblem::UnclosedQuote)]);
+    }
+}
@@ -1,30 +1,37 @@
-use super::model::{ Person };
+use super::model::{ Called, Person, Subject };
 use petgraph::{graph::{NodeIndex}, Direction};
 use petgraph::{Graph, Directed};
 
-pub fn get_node_description(graph: &Graph<Person, &str, Directed, u32>, ix: &NodeIndex<u32>) -> Option<String> {
-    let mut parent_names: Vec::<&str> = vec!();
-    let mut child_names: Vec::<&str> = vec!();
-    let mut parents = graph.neighbors_directed(*ix, Direction::Incoming).detach();
-    let mut children = graph.neighbors_directed(*ix, Direction::Outgoing).detach();
-    while let Some(i) = parents.next_node(graph) {
-        let parent = &graph[i];
-        parent_names.push(&parent.name);
-    }
-    while let Some(i) = children.next_node(graph) {
-        let child = &graph[i];
-        child_names.push(&child.name);
-    }
-    match (!parent_names.is_empty(), !child_names.is_empty()) {
-        (true, true) => {
-            Some(format!("who is parent of {} and also child of {}",child_names.join(", "), parent_names.join(", ")))
-        },
-        (false, true) => {
-            Some(format!("who is parent of {}", child_names.join(", ")))
-        },
-        (true, false) => {
-            Some(format!("who is child of {}", parent_names.join(", ")))
-        }
-        (false, false) => None
+/// Escapes text for HTML pages, Telegram HTML messages and HTML-like labels of Graphviz
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Placeholders called by placeholder children are told apart that many generations down, e.g. "Unknown parent of unknown father of Robert"
+const UNKNOWN_GENERATIONS: usize = 3;
+
+fn called_within(graph: &Graph<Person, &str, Directed, u32>, ix: NodeIndex<u32>, generations: usize) -> Called {
+    let person = &graph[ix];
+    if !person.placeholder || generations == 0 {
+        return Called::Name(person.name.clone());
     }
-}
\ No newline at end of file
+    let children = graph.neighbors_directed(ix, Direction::Outgoing)
+        .map(|child| called_within(graph, child, generations - 1))
+        .collect();
+    Called::Unknown(person.sex, children)
+}
+
+/// The person's name or, for placeholders, who they are a parent of
+pub fn called(graph: &Graph<Person, &str, Directed, u32>, ix: NodeIndex<u32>) -> Called {
+    called_within(graph, ix, UNKNOWN_GENERATIONS)
+}
+
+pub fn get_node_subject(graph: &Graph<Person, &str, Directed, u32>, ix: &NodeIndex<u32>) -> Subject {
+    let parents = graph.neighbors_directed(*ix, Direction::Incoming)
+        .map(|i| called(graph, i))
+        .collect();
+    let children = graph.neighbors_directed(*ix, Direction::Outgoing)
+        .map(|i| called(graph, i))
+        .collect();
+    Subject::new(called(graph, *ix), parents, children)
+}
@@ -0,0 +1,152 @@
+use petgraph::algo::{is_cyclic_directed, tarjan_scc};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::{Directed, Direction, Graph};
+
+use super::model::Person;
+
+pub const MAX_PARENTS: usize = 2;
+pub const MIN_PARENT_AGE: i32 = 12;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Issue {
+    /// Person became their own ancestor
+    Cycle(Vec<String>),
+    TooManyParents(String, usize),
+    ChildBornBeforeParent(String, String),
+    ParentTooYoung(String, String, i32),
+}
+
+/// Problem in the tree along with the parent-child link which most likely caused it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub issue: Issue,
+    pub parent: NodeIndex<u32>,
+    pub child: NodeIndex<u32>,
+}
+
+fn endpoints(graph: &Graph<Person, &str, Directed, u32>, edge: EdgeIndex<u32>) -> (NodeIndex<u32>, NodeIndex<u32>) {
+    graph.edge_endpoints(edge).expect("edge index comes from the graph")
+}
+
+fn cycle_warnings(graph: &Graph<Person, &str, Directed, u32>) -> Vec<Warning> {
+    if !is_cyclic_directed(graph) {
+        return vec![];
+    }
+    tarjan_scc(graph).into_iter()
+        .filter_map(|component| {
+            //the newest link inside of the component is the one that closed the loop
+            let link = graph.edge_indices()
+                .rev()
+                .find(|edge| {
+                    let (parent, child) = endpoints(graph, *edge);
+                    component.contains(&parent) && component.contains(&child)
+                })?;
+            let (parent, child) = endpoints(graph, link);
+            let people = component.iter().map(|ix| graph[*ix].name.to_string()).collect();
+            Some(Warning { issue: Issue::Cycle(people), parent, child })
+        })
+        .collect()
+}
+
+fn parent_count_warnings(graph: &Graph<Person, &str, Directed, u32>) -> Vec<Warning> {
+    graph.node_indices()
+        .filter_map(|child| {
+            let count = graph.neighbors_directed(child, Direction::Incoming).count();
+            if count <= MAX_PARENTS {
+                return None;
+            }
+            let link = graph.edge_indices().rev().find(|edge| endpoints(graph, *edge).1 == child)?;
+            let (parent, _) = endpoints(graph, link);
+            Some(Warning { issue: Issue::TooManyParents(graph[child].name.to_string(), count), parent, child })
+        })
+        .collect()
+}
+
+fn date_warnings(graph: &Graph<Person, &str, Directed, u32>) -> Vec<Warning> {
+    graph.edge_indices()
+        .filter_map(|edge| {
+            let (parent, child) = endpoints(graph, edge);
+            let parent_birth = graph[parent].lifespan.birth?;
+            let child_birth = graph[child].lifespan.birth?;
+            let parent_name = graph[parent].name.to_string();
+            let child_name = graph[child].name.to_string();
+            let age = child_birth - parent_birth;
+            if age < 0 {
+                Some(Warning { issue: Issue::ChildBornBeforeParent(parent_name, child_name), parent, child })
+            } else if age < MIN_PARENT_AGE {
+                Some(Warning { issue: Issue::ParentTooYoung(parent_name, child_name, age), parent, child })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Finds impossible structures: cycles, too many parents and conflicting birth years
+pub fn validate(graph: &Graph<Person, &str, Directed, u32>) -> Vec<Warning> {
+    let mut warnings = cycle_warnings(graph);
+    warnings.extend(parent_count_warnings(graph));
+    warnings.extend(date_warnings(graph));
+    warnings
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::updater::model::NodeCompleteness;
+
+    fn add(graph: &mut Graph<Person, &str, Directed, u32>, text: &str) -> NodeIndex<u32> {
+        graph.add_node(Person::parse(text, NodeCompleteness::Plain))
+    }
+
+    #[test]
+    fn valid_family() {
+        let mut graph = Graph::new();
+        let child = add(&mut graph, "Robert 1980");
+        let mom = add(&mut graph, "Alexandra 1955");
+        let dad = add(&mut graph, "Bernard");
+        graph.add_edge(mom, child, "");
+        graph.add_edge(dad, child, "");
+        assert_eq!(validate(&graph), vec![]);
+    }
+
+    #[test]
+    fn own_ancestor() {
+        let mut graph = Graph::new();
+        let child = add(&mut graph, "Robert");
+        let mom = add(&mut graph, "Alexandra");
+        graph.add_edge(mom, child, "");
+        graph.add_edge(child, mom, "");
+        let warnings = validate(&graph);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].parent, warnings[0].child), (child, mom), "Should blame the newest link");
+    }
+
+    #[test]
+    fn third_parent() {
+        let mut graph = Graph::new();
+        let child = add(&mut graph, "Robert");
+        for name in ["Alexandra", "Bernard", "Clara"] {
+            let parent = add(&mut graph, name);
+            graph.add_edge(parent, child, "");
+        }
+        let warnings = validate(&graph);
+        assert_eq!(warnings, vec![Warning { issue: Issue::TooManyParents("Robert".to_string(), 3), parent: NodeIndex::new(3), child }]);
+    }
+
+    #[test]
+    fn conflicting_years() {
+        let mut graph = Graph::new();
+        let child = add(&mut graph, "Robert 1950");
+        let mom = add(&mut graph, "Alexandra 1960");
+        let dad = add(&mut graph, "Bernard 1942");
+        graph.add_edge(mom, child, "");
+        graph.add_edge(dad, child, "");
+        let issues: Vec<Issue> = validate(&graph).into_iter().map(|warning| warning.issue).collect();
+        assert_eq!(issues, vec![
+            Issue::ChildBornBeforeParent("Alexandra".to_string(), "Robert".to_string()),
+            Issue::ParentTooYoung("Bernard".to_string(), "Robert".to_string(), 8),
+        ]);
+    }
+}
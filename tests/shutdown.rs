@@ -0,0 +1,164 @@
+//! Runs the bot against a local stand-in for the Telegram Bot API and stops it with SIGTERM while an update is handled.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use warp::Filter;
+
+const TOKEN: &str = "123:test";
+/// The stand-in answers sendMessage that late, so the update is still handled when the bot is stopped
+const SEND_DELAY: Duration = Duration::from_secs(1);
+
+/// Bot API methods the bot has called in lower case, as Telegram ignores the case, with their JSON bodies,
+/// "<method> done" once the answer is sent
+type Calls = Arc<Mutex<Vec<(String, Value)>>>;
+
+fn answer(method: &str) -> Value {
+    let result = match method {
+        "getme" => json!({
+            "id": 1, "is_bot": true, "first_name": "Pedigree", "username": "pedigree_test_bot",
+            "can_join_groups": true, "can_read_all_group_messages": false, "supports_inline_queries": false
+        }),
+        "sendmessage" => json!({ "message_id": 2, "date": 0, "chat": { "id": 42, "type": "private", "first_name": "Anna" }, "text": "ok" }),
+        _ => json!(true)
+    };
+    json!({ "ok": true, "result": result })
+}
+
+/// Serves the Bot API methods the bot calls on start, on /start and on shutdown
+fn stand_in(calls: Calls) -> SocketAddr {
+    let api = warp::post()
+        .and(warp::path!(String / String))
+        .and(warp::body::bytes())
+        .and_then(move |_bot: String, method: String, body: warp::hyper::body::Bytes| {
+            let calls = calls.clone();
+            async move {
+                let method = method.to_lowercase();
+                let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+                calls.lock().unwrap().push((method.clone(), body));
+                if method == "sendmessage" {
+                    tokio::time::sleep(SEND_DELAY).await;
+                }
+                calls.lock().unwrap().push((format!("{} done", method), Value::Null));
+                Ok::<_, warp::Rejection>(warp::reply::json(&answer(&method)))
+            }
+        });
+    let (addr, server) = warp::serve(api).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    addr
+}
+
+async fn wait_for<T, F: Fn() -> Option<T>>(what: &str, check: F) -> T {
+    let started = Instant::now();
+    loop {
+        if let Some(found) = check() {
+            return found;
+        }
+        assert!(started.elapsed() < Duration::from_secs(20), "Timed out waiting for {}", what);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn called(calls: &Calls, method: &str) -> Option<Value> {
+    calls.lock().unwrap().iter().find(|(called, _)| called == method).map(|(_, body)| body.clone())
+}
+
+/// graphviz isn't needed to handle /start, a script answering `dot -V` is enough to pass the startup checks
+fn fake_renderer(dir: &Path) -> String {
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    let dot = bin.join("dot");
+    fs::write(&dot, "#!/bin/sh\necho 'dot - graphviz version test' >&2\n").unwrap();
+    fs::set_permissions(&dot, fs::Permissions::from_mode(0o755)).unwrap();
+    format!("{}:{}", bin.display(), std::env::var("PATH").unwrap_or_default())
+}
+
+fn post_update(addr: SocketAddr, path: &str, secret_token: &str, update: &Value) -> String {
+    let body = update.to_string();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nX-Telegram-Bot-Api-Secret-Token: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, addr, secret_token, body.len(), body
+    ).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.lines().next().unwrap_or_default().to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sigterm_finishes_updates_saves_trees_and_deletes_webhook() {
+    let calls = Calls::default();
+    let api = stand_in(calls.clone());
+    let dir = std::env::temp_dir().join(format!("pedigree-bot-shutdown-{}", std::process::id()));
+    let storage = dir.join("storage");
+    let path = fake_renderer(&dir);
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let bot_addr = SocketAddr::from(([127, 0, 0, 1], port));
+
+    let mut bot = Command::new(env!("CARGO_BIN_EXE_pedigree-bot"))
+        .env("TELOXIDE_TOKEN", TOKEN)
+        .env("SERVER_URL", "https://example.com/bot")
+        .env("IP", "127.0.0.1")
+        .env("PORT", port.to_string())
+        .env("STORAGE_PATH", &storage)
+        .env("TELEGRAM_API_URL", format!("http://{}", api))
+        .env("DELETE_WEBHOOK_ON_SHUTDOWN", "true")
+        .env("SHUTDOWN_TIMEOUT_SECS", "10")
+        .env("PATH", path)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let webhook = wait_for("setWebhook", || called(&calls, "setwebhook")).await;
+    let url = webhook["url"].as_str().unwrap().to_string();
+    let secret_token = webhook["secret_token"].as_str().unwrap().to_string();
+    let webhook_path = url.strip_prefix("https://example.com").unwrap().to_string();
+    assert!(webhook_path.starts_with("/bot/"), "Should add the secret segment to {}", url);
+    wait_for("the webhook server", || TcpStream::connect(bot_addr).ok()).await;
+
+    let update = json!({
+        "update_id": 1,
+        "message": {
+            "message_id": 1, "date": 0, "text": "/start",
+            "chat": { "id": 42, "type": "private", "first_name": "Anna" },
+            "from": { "id": 42, "is_bot": false, "first_name": "Anna", "language_code": "en" },
+            "entities": [{ "type": "bot_command", "offset": 0, "length": 6 }]
+        }
+    });
+    assert_eq!(post_update(bot_addr, &webhook_path, "wrong", &update), "HTTP/1.1 401 Unauthorized");
+    assert_eq!(post_update(bot_addr, &webhook_path, &secret_token, &update), "HTTP/1.1 200 OK");
+
+    wait_for("the answer to /start", || called(&calls, "sendmessage")).await;
+    let status = Command::new("kill").arg("-TERM").arg(bot.id().to_string()).status().unwrap();
+    assert!(status.success());
+    let exit = tokio::task::spawn_blocking(move || {
+        let exit = bot.wait().unwrap();
+        let mut log = String::new();
+        bot.stderr.take().unwrap().read_to_string(&mut log).unwrap();
+        (exit, log)
+    });
+    let (exit, log) = tokio::time::timeout(Duration::from_secs(20), exit).await.expect("Should stop in time").unwrap();
+
+    assert!(exit.success(), "Should exit cleanly, log:\n{}", log);
+    let methods: Vec<String> = calls.lock().unwrap().iter().map(|(method, _)| method.clone()).collect();
+    let answered = methods.iter().position(|method| method == "sendmessage done").expect("Should finish the update being handled");
+    let deleted = methods.iter().position(|method| method == "deletewebhook").expect("Should delete the webhook");
+    assert!(answered < deleted, "Should wait for the update before deleting the webhook: {:?}", methods);
+
+    let trees: Vec<_> = fs::read_dir(storage.join("trees")).unwrap().collect();
+    assert_eq!(trees.len(), 1, "Should save the tree created by /start");
+    let members = fs::read_to_string(trees[0].as_ref().unwrap().path().join("members.csv")).unwrap();
+    assert!(members.contains("42,owner,My tree"), "{}", members);
+    assert!(log.contains("The bot has stopped: 1 trees saved to"), "Should end with a clear line, log:\n{}", log);
+    assert!(log.contains("webhook deleted"), "{}", log);
+
+    fs::remove_dir_all(dir).unwrap();
+}